@@ -89,11 +89,44 @@ var textures : binding_array<texture_2d<u32>>;
 var nearest_sampler: sampler;
 @group(1) @binding(6)
 var output: texture_storage_2d<rgba8unorm,write>;
-
+//cells per side of the biome map, followed by the tint palette entry of every 4x4 column
+@group(1) @binding(7)
+var<storage,read> biome_cells: array<u32>;
+//BIOME_TINTS colours per palette entry, one for every tint index after 0
+@group(1) @binding(8)
+var<storage,read> biome_tints: array<vec4<f32>>;
+//representative leaf value of every octant, NO_LOD where it has none
+@group(1) @binding(9)
+var<storage,read> lod_values: array<u32>;
+
+
+const BIOME_TINTS:u32 = 6u;
+const BIOME_CELL_SIZE:f32 = 4.0;
+
+fn biome_tint(block_position: vec3<f32>, tint_index: u32) -> vec3<f32> {
+    if tint_index == 0u {
+        return vec3(1.0);
+    }
+    let cells_per_side = biome_cells[0];
+    let default_entry = arrayLength(&biome_tints) / BIOME_TINTS - 1u;
+    let cell = vec2<i32>(floor(block_position.xz / BIOME_CELL_SIZE));
+    var entry = default_entry;
+    if all(cell >= vec2(0i)) && all(vec2<u32>(cell) < vec2(cells_per_side)) {
+        entry = min(biome_cells[1u + u32(cell.y) * cells_per_side + u32(cell.x)], default_entry);
+    }
+    return biome_tints[entry * BIOME_TINTS + tint_index - 1u].rgb;
+}
 
 const OCTREE_MAX_SCALE:u32 =23;
 const OCTREE_MAX_STEPS:u32 = 1000;
 const OCTREE_EPSILON:f32 = 1.1920929e-7;
+const LEAF_BIT:u32 = 1u;
+const CHILD_BIT:u32 = 2u;
+//child octants with a value in lod_values
+const LOD_BIT:u32 = 4u;
+const NO_LOD:u32 = 0xFFFFFFFFu;
+//octants are drawn as their lod value once they shrink below this many pixels
+const LOD_PIXELS:f32 = 1.0;
 const RAY_EPSILON:f32 = 5e-8;
 
 
@@ -110,8 +143,11 @@ fn main(@builtin(local_invocation_index) local_idx: u32,@builtin(global_invocati
     let camera = PrecomputedCameraData(uniform_data.camera_world_position,uniform_data.camera_scaled_view_dir,uniform_data.camera_scaled_view_right,uniform_data.camera_view_up_ortho,vec2<f32>(uniform_data.inv_image_size_x,uniform_data.inv_image_size_y));
     let ray = create_ray_from_precomputed(camera,global_id.xy);
     //textureStore(output,global_id.xy,vec4<f32>(ray.origin,1.0));
+
+    //height of a pixel at a distance of 1 along the ray
+    let pixel_spread = 2.0 * uniform_data.inv_image_size_y / length(uniform_data.camera_scaled_view_dir);
     
-    intersect_octree(global_id,ray,local_idx,1024.0);
+    intersect_octree(global_id,ray,local_idx,1024.0,pixel_spread * LOD_PIXELS);
 }
 
 const MAX_BOUNCES:u32 = 5;
@@ -223,7 +259,7 @@ struct OctreeIntersectResult{
     material_id:u32,
 }
 
-fn intersect_octree(global_id:vec3<u32>,ray: Ray,local_idx:u32, max_dst: f32) {
+fn intersect_octree(global_id:vec3<u32>,ray: Ray,local_idx:u32, max_dst: f32, lod_spread: f32) {
     let octree_scale: f32 = octree_uniform.octree_scale;
     var root: u32 = octree_uniform.root;
     var scale: u32 = 23u-1u;
@@ -295,14 +331,23 @@ fn intersect_octree(global_id:vec3<u32>,ray: Ray,local_idx:u32, max_dst: f32) {
         let shift_for_child_header = 16u * (unmirrored_idx % 2u);
         let header_16bit: u32 = (header_word_containing_child_header >> shift_for_child_header) & 0xFFFFu; // Isolate the 16 bits
 
-        let is_child:bool = (header_16bit & 255u) != 0u; // Now checks lower 8 bits of the 16-bit header
-        let is_leaf:bool = (header_16bit == 0xFFFFu);   // Now correctly checks if the 16-bit header is 0xFFFF
+        let is_child:bool = (header_16bit & CHILD_BIT) != 0u;
+        let is_leaf:bool = (header_16bit & LEAF_BIT) != 0u;
+        let has_lod:bool = (header_16bit & LOD_BIT) != 0u;
+
+        //octants smaller than the footprint of a pixel at this distance stop the descent
+        let child_data:u32 = octree[current_node_base_ptr+4u+unmirrored_idx];
+        var lod_value:u32 = NO_LOD;
+        if has_lod && scale_exp2 < t_min*lod_spread{
+            lod_value = lod_values[child_data];
+        }
+        let is_lod:bool = lod_value != NO_LOD;
 
         if is_child && t_min<=t_max{
 
-            if is_leaf&&t_min >=0.0{
+            if (is_leaf||is_lod)&&t_min >=0.0{
                 //hit
-                let leaf_value:u32 = octree[12*parent_octant_idx+4+unmirrored_idx];
+                let leaf_value:u32 = select(child_data,lod_value,is_lod);
 
                 let unmirrored_components:vec3<f32> = 3.0-scale_exp2-pos;
                 
@@ -370,7 +415,9 @@ fn intersect_octree(global_id:vec3<u32>,ray: Ray,local_idx:u32, max_dst: f32) {
 
                 let color:vec4<u32> = textureLoad(texture,uv_2,0i);
 
-                let float_color = vec4<f32>(f32(color.x),f32(color.y),f32(color.z),f32(color.w)) / 255.0;
+                var float_color = vec4<f32>(f32(color.x),f32(color.y),f32(color.z),f32(color.w)) / 255.0;
+                let block_position = (unmirrored_pos - 1.0) / octree_uniform.octree_scale;
+                float_color = vec4(float_color.rgb * biome_tint(block_position, material.tint_index), float_color.a);
 
                 textureStore(output,global_id.xy,float_color);
                 return;
@@ -1,3 +1,4 @@
 pub mod main_app;
+pub mod octree_info;
 pub mod settings;
 pub mod world_loading;
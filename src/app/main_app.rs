@@ -2,20 +2,36 @@ use eframe::egui::{
     self, Button, Color32, ColorImage, DragValue, Image, ImageData, ImageSource, Label,
     RadioButton, TextureHandle, TextureOptions, Ui, load::SizedTexture,
 };
-use log::info;
-use mc_utils::coords::block::BlockCoords;
+use hashbrown::HashMap;
+use log::{error, info};
+use mc_utils::{
+    coords::block::BlockCoords, owned::nbt_string::NBTString, resource_loader::ResourceLoader,
+};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
 use crate::{
-    colors::U8Color,
+    octree::new_octree::{Octree, load_or_build_world_octree},
     renderer::{
+        camera::Camera,
         gpu_renderer::GPURenderer,
+        image_export::{ExportFormat, export_image},
         renderer_trait::{FrameInFlight, FrameInFlightPoll, RenderingBackend},
         tile_renderer::{RendererMode, RendererStatus},
     },
-    scene::Scene,
+    scene::{
+        Scene,
+        resource_manager::{BuiltModels, ModelBuilder, ModelHandle, ModelID},
+        scene_file::{SceneFile, WorldReference},
+    },
+    textures::material::Material,
 };
 
 use super::{
+    octree_info::OctreeInfoWindow,
     settings::{RenderSettingsWindow, RendererBackendSetting},
     world_loading::WorldLoadingDialog,
 };
@@ -24,9 +40,11 @@ pub struct Application {
     status: RendererStatus,
     settings: RenderSettingsWindow,
     world_loading_dialog: WorldLoadingDialog,
+    octree_info: OctreeInfoWindow,
     renderer: Box<dyn RenderingBackend>,
     frame_in_flight: Option<Box<dyn FrameInFlight>>,
     render_texture: Option<TextureHandle>,
+    export_format: ExportFormat,
 }
 /* pub fn load_world() -> (ModelManager, Scene) {
     let model_manager = ModelManager::new();
@@ -60,8 +78,106 @@ pub struct Application {
     //println!("{:?}", tree);
     (model_manager, scene)
 } */
-pub fn load_world_2(path: &str, origin: &BlockCoords, depth: u8) -> Scene {
-    todo!()
+pub const RESOURCE_PACK_PATH: &str = "./assets/resourcepacks";
+pub const OCTREE_CACHE_PATH: &str = "./cache/octrees";
+
+///Loads every region of the world at `path` that overlaps the cube of side `2^depth` centered on
+///`origin` and resolves the block states found in them into models. Built octrees are cached in
+///[`OCTREE_CACHE_PATH`]. Leaves only hold a model, so every block is shaded with the material of
+///the first face of its model, blocks with different textures per face like logs or grass look the
+///same from every side
+pub fn load_world_2(path: &str, origin: &BlockCoords, depth: u8) -> anyhow::Result<Scene> {
+    let blockstate_map = Arc::new(Mutex::new(HashMap::new()));
+
+    let air = NBTString::new_from_str("minecraft:air#normal");
+    blockstate_map.lock().unwrap().insert(air, 0);
+
+    let start = Instant::now();
+    let (octree, biomes) = load_or_build_world_octree(
+        Path::new(path),
+        origin,
+        depth,
+        blockstate_map.clone(),
+        Path::new(OCTREE_CACHE_PATH),
+    )
+    .ok_or_else(|| anyhow::anyhow!("no regions found in range of {origin:?} in {path}"))?;
+    let end = Instant::now();
+    info!(
+        "time to build world octree: {:?}",
+        end.duration_since(start)
+    );
+
+    let mut model_builder = ModelBuilder::new(ResourceLoader::new(RESOURCE_PACK_PATH));
+
+    let blockstate_map = blockstate_map.lock().unwrap();
+    let mut blockstate_to_model: Vec<Option<ModelHandle>> = vec![None; blockstate_map.len()];
+    blockstate_map
+        .iter()
+        .for_each(|(mapped_state, &blockstate_id)| {
+            //air is never turned into a model
+            if blockstate_id == 0 {
+                return;
+            }
+            blockstate_to_model[blockstate_id as usize] =
+                model_builder.try_add_model_from_mapped_state(&mapped_state.to_str());
+        });
+    drop(blockstate_map);
+    model_builder.load_texture_animations(Path::new(RESOURCE_PACK_PATH));
+
+    let colormaps = model_builder.load_colormaps();
+    let models = model_builder.build();
+
+    let mut scene = world_scene(octree, &blockstate_to_model, models);
+    scene.set_biomes(biomes, &colormaps);
+    scene.world = Some(WorldReference {
+        path: path.to_string(),
+        origin: [origin.x, origin.y, origin.z],
+        depth,
+    });
+    Ok(scene)
+}
+
+///Turns the block state ids in the leaves of `octree` into block ids and builds the scene around
+///them. Block ids are the model handles offset by one, so that 0 stays air
+fn world_scene(
+    mut octree: Octree,
+    blockstate_to_model: &[Option<ModelHandle>],
+    models: BuiltModels,
+) -> Scene {
+    octree.remap_leaves(|blockstate_id| {
+        blockstate_to_model
+            .get(blockstate_id as usize)
+            .copied()
+            .flatten()
+            .map(|handle| handle.0 as ModelID + 1)
+    });
+    //only done now, block states that share a model can make more subtrees equal
+    let removed = octree.deduplicate();
+    info!("merged {removed} identical octants");
+    //the cache doesn't store lod values, they have to be built from the remapped leaves
+    octree.compute_lod_values();
+
+    let materials = std::iter::once(Material::AIR)
+        .chain(models.block_materials().iter().cloned())
+        .collect();
+    Scene::new(octree, materials, models)
+}
+
+///Loads the world referenced by a scene file and applies its camera and lighting settings
+pub fn open_scene(path: &Path) -> anyhow::Result<(Scene, Camera)> {
+    let scene_file = SceneFile::load(path)?;
+    let world = scene_file
+        .world
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("{} does not reference a world", path.display()))?;
+    let origin = BlockCoords {
+        x: world.origin[0],
+        y: world.origin[1],
+        z: world.origin[2],
+    };
+    let mut scene = load_world_2(&world.path, &origin, world.depth)?;
+    scene_file.apply_to(&mut scene)?;
+    Ok((scene, scene_file.camera()))
 }
 impl Default for Application {
     fn default() -> Self {
@@ -72,17 +188,12 @@ impl Default for Application {
             frame_in_flight: None,
             settings: Default::default(),
             world_loading_dialog: Default::default(),
+            octree_info: Default::default(),
+            export_format: Default::default(),
         }
     }
 }
 
-//TODO move this to colors module
-fn pixel_slice_to_u8_slice(slice: &[U8Color]) -> &[u8] {
-    let ptr = slice.as_ptr();
-    let len = std::mem::size_of_val(slice);
-    unsafe { std::slice::from_raw_parts(ptr.cast(), len) }
-}
-
 impl Application {
     pub fn draw_start_stop_button(
         &mut self,
@@ -144,6 +255,76 @@ impl Application {
         }
         self.world_loading_dialog.show(ctx, &mut self.renderer);
     }
+    pub fn draw_octree_info_button(&mut self, ctx: &egui::Context, ui: &mut Ui) {
+        if ui.button("Octree Info").clicked() {
+            self.octree_info.open = true;
+        }
+        self.octree_info.show(ctx, self.renderer.as_ref());
+    }
+    pub fn draw_scene_file_buttons(&mut self, ui: &mut Ui) {
+        if ui.button("Open Scene").clicked()
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter("Scene", &["json"])
+                .pick_file()
+        {
+            match open_scene(&path) {
+                Ok((scene, camera)) => {
+                    let scene = Arc::new(parking_lot::RwLock::new(scene));
+                    self.renderer.as_mut().set_scene(&scene);
+                    self.renderer.as_mut().set_camera(camera);
+                }
+                Err(err) => error!("failed to open scene: {err:#}"),
+            }
+        }
+        let scene = self.renderer.get_scene().cloned();
+        if ui
+            .add_enabled(scene.is_some(), Button::new("Save Scene"))
+            .clicked()
+            && let Some(scene) = scene
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter("Scene", &["json"])
+                .set_file_name("scene.json")
+                .save_file()
+        {
+            let scene_file = SceneFile::from_scene(&scene.read(), self.renderer.get_camera());
+            if let Err(err) = scene_file.save(&path) {
+                error!("failed to save scene: {err:#}");
+            }
+        }
+    }
+    pub fn draw_export_button(&mut self, ui: &mut Ui) {
+        let frame_buffer = self.renderer.get_frame_buffer();
+        egui::ComboBox::from_id_salt("export format")
+            .selected_text(self.export_format.to_str())
+            .show_ui(ui, |ui| {
+                ExportFormat::FORMATS.iter().for_each(|format| {
+                    ui.selectable_value(&mut self.export_format, *format, format.to_str());
+                });
+            });
+        if ui
+            .add_enabled(frame_buffer.is_some(), Button::new("Export..."))
+            .clicked()
+            && let Some(frame_buffer) = frame_buffer
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter(
+                    self.export_format.to_str(),
+                    &[self.export_format.extension()],
+                )
+                .set_file_name(format!("render.{}", self.export_format.extension()))
+                .save_file()
+        {
+            let frame_buffer = frame_buffer.lock().unwrap().clone();
+            if let Err(err) = export_image(
+                &path,
+                self.export_format,
+                self.renderer.get_resolution(),
+                &frame_buffer,
+                &self.renderer.get_tone_mapper(),
+            ) {
+                error!("failed to export image: {err:#}");
+            }
+        }
+    }
     pub fn draw_camera_coordinates(&mut self, ctx: &egui::Context, ui: &mut Ui) {
         ui.add_enabled(
             self.renderer.which_backend() != RendererBackendSetting::Dummy,
@@ -184,6 +365,9 @@ impl Application {
                 self.draw_mode_switch_radio_buttons(ctx, frame, ui);
                 self.draw_render_settings_button(ctx, frame, ui);
                 self.draw_load_world_button(ctx, ui);
+                self.draw_octree_info_button(ctx, ui);
+                self.draw_scene_file_buttons(ui);
+                self.draw_export_button(ui);
                 self.draw_backend_label(ctx, ui);
                 self.draw_camera_coordinates(ctx, ui);
             });
@@ -258,3 +442,43 @@ impl eframe::App for Application {
         true
     }
 }
+
+#[cfg(test)]
+mod test {
+    use glam::{UVec3, Vec2, Vec3A};
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use crate::{colors::U8Color, scene::resource_manager::ModelData, textures::texture::Texture};
+
+    use super::*;
+
+    #[test]
+    pub fn the_first_model_is_not_air() {
+        //block state 1 is the first model, a red full block
+        let mut octree = Octree::default();
+        octree.set_voxel(UVec3::new(4, 4, 4), 1);
+        let red = Material::builder()
+            .albedo(Texture::Color(U8Color::new(255, 0, 0, 255)))
+            .build();
+        let mut uvs = [Vec2::ZERO; 12];
+        (0..6).for_each(|face| uvs[face * 2 + 1] = Vec2::splat(16.0));
+        let block = ModelData::SimpleAABB {
+            uvs,
+            materials: Box::new(std::array::from_fn(|_| red.clone())),
+        };
+        let models = BuiltModels::from_model_data(&[block], Vec::new());
+        let scene = world_scene(octree, &[None, Some(ModelHandle(0))], models);
+
+        let camera = Camera::look_at(
+            Vec3A::new(4.5, 4.5, 12.0),
+            Vec3A::splat(4.5),
+            Vec3A::Y,
+            30f32.to_radians(),
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        let preview = scene.get_preview_color(camera.get_ray(0.0, 0.0), 0.0, 0.0, &mut rng);
+        assert!(preview.x > 0.0 && preview.y == 0.0 && preview.z == 0.0);
+        let color = scene.get_color(camera.get_ray(0.0, 0.0), &mut rng, 1);
+        assert!(color.x > 0.0 && color.y == 0.0 && color.z == 0.0);
+    }
+}
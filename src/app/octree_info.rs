@@ -0,0 +1,100 @@
+use eframe::egui::{self, Button, Grid, Label, ScrollArea, Window};
+
+use crate::{octree::new_octree::OctreeStats, renderer::renderer_trait::RenderingBackend};
+
+///Most common leaf values listed in the window
+const HISTOGRAM_ENTRIES: usize = 32;
+
+#[derive(Default)]
+pub struct OctreeInfoWindow {
+    pub open: bool,
+    ///Walking a world sized tree takes a while, so the stats are only gathered on request
+    stats: Option<OctreeStats>,
+}
+
+impl OctreeInfoWindow {
+    pub fn show(&mut self, ctx: &egui::Context, renderer: &dyn RenderingBackend) {
+        let mut refresh = false;
+        if Window::new("Octree Info")
+            .resizable([true, true])
+            .open(&mut self.open)
+            .default_width(280.0)
+            .show(ctx, |ui| {
+                refresh = ui.add(Button::new("Refresh")).clicked();
+                ui.separator();
+                let Some(stats) = &self.stats else {
+                    ui.add(Label::new("No scene loaded"));
+                    return;
+                };
+                Grid::new("octree info").striped(true).show(ui, |ui| {
+                    let mut row = |name: &str, value: String| {
+                        ui.add(Label::new(name));
+                        ui.add(Label::new(value));
+                        ui.end_row();
+                    };
+                    row("Octants", stats.octants.to_string());
+                    row("Reachable Octants", stats.reachable_octants.to_string());
+                    row("Free Octants", stats.free_octants.to_string());
+                    row("Leaves", stats.leaves().to_string());
+                    row("Compacted Leaves", stats.compacted_leaves.to_string());
+                    row("CPU Memory", format_bytes(stats.cpu_bytes));
+                    row("GPU Buffers", format_bytes(stats.gpu_bytes));
+                });
+                ui.separator();
+                ui.add(Label::new("Leaves per Depth"));
+                Grid::new("octree leaves per depth")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        stats.leaves_per_depth.iter().enumerate().skip(1).for_each(
+                            |(depth, count)| {
+                                ui.add(Label::new(depth.to_string()));
+                                ui.add(Label::new(count.to_string()));
+                                ui.end_row();
+                            },
+                        );
+                    });
+                ui.separator();
+                ui.add(Label::new("Leaf Values"));
+                ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                    Grid::new("octree leaf values")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            stats
+                                .value_histogram
+                                .iter()
+                                .take(HISTOGRAM_ENTRIES)
+                                .for_each(|(value, count)| {
+                                    ui.add(Label::new(value.to_string()));
+                                    ui.add(Label::new(count.to_string()));
+                                    ui.end_row();
+                                });
+                        });
+                    let remaining = stats
+                        .value_histogram
+                        .len()
+                        .saturating_sub(HISTOGRAM_ENTRIES);
+                    if remaining > 0 {
+                        ui.add(Label::new(format!("{remaining} more values")));
+                    }
+                });
+            })
+            .is_some()
+            && (refresh || self.stats.is_none())
+        {
+            self.stats = renderer
+                .get_scene()
+                .map(|scene| scene.read().octree.stats());
+        }
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
@@ -1,6 +1,21 @@
-use eframe::egui::{self, DragValue, Label, RadioButton, Window};
+use std::sync::Arc;
 
-use crate::renderer::{gpu_renderer::GPURenderer, renderer_trait::RenderingBackend};
+use eframe::egui::{self, DragValue, Label, RadioButton, Slider, Window};
+use log::error;
+
+use crate::{
+    colors::tone_mapping::{ToneMapper, ToneMappingOperator},
+    renderer::{
+        cpu_renderer::CPURenderer, gpu_renderer::GPURenderer, renderer_trait::RenderingBackend,
+    },
+    scene::{
+        Scene,
+        environment_map::EnvironmentMap,
+        fog::{Fog, FogMode},
+        sky::{Sky, SkyModel},
+    },
+    textures::animation::{AnimationTime, TextureAnimation},
+};
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum RendererBackendSetting {
@@ -24,6 +39,12 @@ pub struct RenderSettingsWindow {
     pub open: bool,
     backend: RendererBackendSetting,
     resolution: (u32, u32),
+    tone_mapper: ToneMapper,
+    max_depth: u32,
+    russian_roulette_depth: u32,
+    sky: Sky,
+    fog: Fog,
+    texture_animation: TextureAnimation,
 }
 
 impl RenderSettingsWindow {
@@ -70,25 +91,215 @@ impl RenderSettingsWindow {
                     ui.add(DragValue::new(&mut self.resolution.1));
                 });
                 ui.separator();
+                //tone mapping only changes how the samples are displayed, so it applies right away
+                ui.add(Label::new("Tone Mapping"));
+                egui::ComboBox::from_id_salt("tone mapping operator")
+                    .selected_text(self.tone_mapper.operator.to_str())
+                    .show_ui(ui, |ui| {
+                        ToneMappingOperator::OPERATORS.iter().for_each(|operator| {
+                            ui.selectable_value(
+                                &mut self.tone_mapper.operator,
+                                *operator,
+                                operator.to_str(),
+                            );
+                        });
+                    });
+                ui.add(
+                    Slider::new(
+                        &mut self.tone_mapper.exposure,
+                        ToneMapper::MIN_EXPOSURE..=ToneMapper::MAX_EXPOSURE,
+                    )
+                    .text("Exposure"),
+                );
+                if renderer.get_tone_mapper() != self.tone_mapper {
+                    renderer.set_tone_mapper(self.tone_mapper);
+                }
+                ui.separator();
+                ui.add(Label::new("Path Depth"));
+                ui.horizontal(|ui| {
+                    ui.add(Label::new("Max:"));
+                    ui.add(DragValue::new(&mut self.max_depth).range(1..=Scene::MAX_MAX_DEPTH));
+                });
+                ui.horizontal(|ui| {
+                    ui.add(Label::new("Russian Roulette After:"));
+                    ui.add(
+                        DragValue::new(&mut self.russian_roulette_depth)
+                            .range(0..=Scene::MAX_MAX_DEPTH),
+                    );
+                });
+                ui.separator();
+                ui.add(Label::new("Sky"));
+                egui::ComboBox::from_id_salt("sky model")
+                    .selected_text(self.sky.model.to_str())
+                    .show_ui(ui, |ui| {
+                        SkyModel::MODELS.iter().for_each(|model| {
+                            ui.selectable_value(&mut self.sky.model, *model, model.to_str());
+                        });
+                    });
+                ui.add_enabled(
+                    self.sky.model == SkyModel::Preetham,
+                    Slider::new(
+                        &mut self.sky.turbidity,
+                        Sky::MIN_TURBIDITY..=Sky::MAX_TURBIDITY,
+                    )
+                    .text("Turbidity"),
+                );
+                ui.add_enabled(
+                    self.sky.model == SkyModel::Preetham,
+                    Slider::new(&mut self.sky.ground_albedo, 0.0..=1.0).text("Ground Albedo"),
+                );
+                ui.add_enabled_ui(self.sky.model == SkyModel::Environment, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Load Environment...").clicked()
+                            && let Some(path) = rfd::FileDialog::new()
+                                .add_filter("HDR Image", &["hdr", "exr"])
+                                .pick_file()
+                        {
+                            match EnvironmentMap::load(&path) {
+                                Ok(environment) => {
+                                    self.sky.environment = Some(Arc::new(environment))
+                                }
+                                Err(err) => error!("{err:#}"),
+                            }
+                        }
+                        let name = self
+                            .sky
+                            .environment
+                            .as_ref()
+                            .map_or("None", |environment| environment.path.as_str());
+                        ui.add(Label::new(name).truncate());
+                    });
+                    ui.horizontal(|ui| {
+                        ui.drag_angle(&mut self.sky.environment_rotation);
+                        ui.label("Rotation");
+                    });
+                    ui.add(
+                        Slider::new(
+                            &mut self.sky.environment_intensity,
+                            0.0..=Sky::MAX_ENVIRONMENT_INTENSITY,
+                        )
+                        .text("Intensity"),
+                    );
+                });
+                ui.separator();
+                ui.add(Label::new("Fog"));
+                egui::ComboBox::from_id_salt("fog mode")
+                    .selected_text(self.fog.mode.to_str())
+                    .show_ui(ui, |ui| {
+                        FogMode::MODES.iter().for_each(|mode| {
+                            ui.selectable_value(&mut self.fog.mode, *mode, mode.to_str());
+                        });
+                    });
+                ui.add_enabled_ui(self.fog.mode != FogMode::None, |ui| {
+                    ui.add(
+                        Slider::new(&mut self.fog.density, 0.0..=Fog::MAX_DENSITY)
+                            .logarithmic(true)
+                            .text("Density"),
+                    );
+                    ui.horizontal(|ui| {
+                        let mut albedo = self.fog.albedo.to_array();
+                        if ui.color_edit_button_rgb(&mut albedo).changed() {
+                            self.fog.albedo = albedo.into();
+                        }
+                        ui.label("Albedo");
+                    });
+                    ui.add(
+                        Slider::new(
+                            &mut self.fog.anisotropy,
+                            -Fog::MAX_ANISOTROPY..=Fog::MAX_ANISOTROPY,
+                        )
+                        .text("Anisotropy"),
+                    );
+                });
+                ui.add_enabled_ui(self.fog.mode == FogMode::Layered, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.add(DragValue::new(&mut self.fog.height));
+                        ui.label("Height");
+                    });
+                    ui.add(
+                        Slider::new(&mut self.fog.falloff, Fog::MIN_FALLOFF..=Fog::MAX_FALLOFF)
+                            .logarithmic(true)
+                            .text("Falloff"),
+                    );
+                });
+                ui.separator();
+                ui.add(Label::new("Texture Animation"));
+                let time = self.texture_animation.time.value();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add(RadioButton::new(
+                            matches!(self.texture_animation.time, AnimationTime::Frame(_)),
+                            "Frame",
+                        ))
+                        .clicked()
+                    {
+                        self.texture_animation.time = AnimationTime::Frame(time as u32);
+                    };
+                    if ui
+                        .add(RadioButton::new(
+                            matches!(self.texture_animation.time, AnimationTime::Tick(_)),
+                            "Tick",
+                        ))
+                        .clicked()
+                    {
+                        self.texture_animation.time = AnimationTime::Tick(time);
+                    };
+                    match &mut self.texture_animation.time {
+                        AnimationTime::Frame(frame) => ui.add(DragValue::new(frame)),
+                        AnimationTime::Tick(tick) => {
+                            ui.add(DragValue::new(tick).range(0.0..=f32::MAX).speed(0.1))
+                        }
+                    };
+                });
+                ui.checkbox(&mut self.texture_animation.interpolate, "Interpolate");
+                ui.separator();
                 if ui.button("Apply").clicked() {
                     if renderer.get_resolution() != self.resolution {
                         renderer.as_mut().set_resolution(self.resolution);
                     }
+                    if let Some(scene) = renderer.get_scene().cloned() {
+                        let mut scene_guard = scene.write();
+                        let changed = scene_guard.max_depth != self.max_depth
+                            || scene_guard.russian_roulette_depth != self.russian_roulette_depth
+                            || scene_guard.sky != self.sky
+                            || scene_guard.fog != self.fog
+                            || scene_guard.texture_animation != self.texture_animation;
+                        scene_guard.max_depth = self.max_depth;
+                        scene_guard.russian_roulette_depth = self.russian_roulette_depth;
+                        scene_guard.set_sky(self.sky.clone());
+                        scene_guard.fog = self.fog.clone();
+                        scene_guard.set_texture_animation(self.texture_animation);
+                        drop(scene_guard);
+                        //restarts the render with the new settings
+                        if changed {
+                            renderer.set_scene(&scene);
+                        }
+                    }
                     if renderer.which_backend() != self.backend {
-                        let render_state = frame.wgpu_render_state().unwrap();
-
                         let old_backend = match self.backend {
                             RendererBackendSetting::Dummy => panic!(),
-                            RendererBackendSetting::CPU => unimplemented!(),
-                            RendererBackendSetting::GPU => std::mem::replace(
+                            RendererBackendSetting::CPU => std::mem::replace(
                                 renderer,
-                                Box::new(GPURenderer::new(
-                                    &render_state.device,
-                                    &render_state.queue,
-                                    self.resolution,
-                                )),
+                                Box::new(CPURenderer::new(self.resolution)),
                             ),
+                            RendererBackendSetting::GPU => {
+                                let render_state = frame.wgpu_render_state().unwrap();
+                                std::mem::replace(
+                                    renderer,
+                                    Box::new(GPURenderer::new(
+                                        &render_state.device,
+                                        &render_state.queue,
+                                        self.resolution,
+                                    )),
+                                )
+                            }
                         };
+                        //carry the loaded scene and view over to the new backend
+                        renderer.set_camera(old_backend.get_camera().clone());
+                        renderer.set_tone_mapper(old_backend.get_tone_mapper());
+                        if let Some(scene) = old_backend.get_scene() {
+                            renderer.set_scene(scene);
+                        }
                         drop(old_backend);
                     }
                 }
@@ -97,6 +308,32 @@ impl RenderSettingsWindow {
             None => {
                 let render_resolution = renderer.get_resolution();
                 self.resolution = (render_resolution.0, render_resolution.1);
+                self.tone_mapper = renderer.get_tone_mapper();
+                let (max_depth, russian_roulette_depth, sky, fog, texture_animation) =
+                    match renderer.get_scene() {
+                        Some(scene) => {
+                            let scene = scene.read();
+                            (
+                                scene.max_depth,
+                                scene.russian_roulette_depth,
+                                scene.sky.clone(),
+                                scene.fog.clone(),
+                                scene.texture_animation,
+                            )
+                        }
+                        None => (
+                            Scene::DEFAULT_MAX_DEPTH,
+                            Scene::DEFAULT_RUSSIAN_ROULETTE_DEPTH,
+                            Sky::default(),
+                            Fog::default(),
+                            TextureAnimation::default(),
+                        ),
+                    };
+                self.max_depth = max_depth;
+                self.russian_roulette_depth = russian_roulette_depth;
+                self.sky = sky;
+                self.fog = fog;
+                self.texture_animation = texture_animation;
             }
         };
     }
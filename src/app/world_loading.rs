@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use eframe::egui::{self, Button, DragValue, Label, Slider, Window};
+use log::error;
 use mc_utils::coords::block::BlockCoords;
 
 use crate::renderer::renderer_trait::RenderingBackend;
@@ -47,9 +48,13 @@ impl WorldLoadingDialog {
                     }
                 });
                 if ui.add(Button::new("Load")).clicked() {
-                    let scene = load_world_2(&self.path, &self.position, self.depth as u8);
-                    let scene = Arc::new(parking_lot::RwLock::new(scene));
-                    renderer.as_mut().set_scene(&scene);
+                    match load_world_2(&self.path, &self.position, self.depth as u8) {
+                        Ok(scene) => {
+                            let scene = Arc::new(parking_lot::RwLock::new(scene));
+                            renderer.as_mut().set_scene(&scene);
+                        }
+                        Err(err) => error!("failed to load world: {err}"),
+                    }
                 }
                 ui.separator();
             })
@@ -0,0 +1,521 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, anyhow, bail};
+use glam::Vec3A;
+use mc_utils::coords::block::BlockCoords;
+use octree_pathtracing::{
+    colors::{
+        F32Color,
+        tone_mapping::{ToneMapper, ToneMappingOperator},
+    },
+    main_app::{load_world_2, open_scene},
+    renderer::{
+        camera::Camera,
+        image_export::{ExportFormat, export_image},
+        render_dump::{RenderDump, scene_hash},
+        tile_renderer::{RendererMode, TileRenderer},
+    },
+    scene::{
+        Scene, Sun,
+        environment_map::EnvironmentMap,
+        fog::{Fog, FogMode},
+        sky::{Sky, SkyModel},
+    },
+    textures::animation::{AnimationTime, TextureAnimation},
+};
+
+const USAGE: &str = "usage: octree-render (--world <path> | --scene <path>) [options]
+       octree-render merge <output dump> <dump>... [--image <path>] [tone mapping options]
+
+options:
+    --world <path>            minecraft world folder to render
+    --scene <path>            scene file to render, the other options override its settings
+    --origin <x,y,z>          block position the octree is centered on (default 0,0,0)
+    --depth <n>               octree depth, the loaded cube is 2^n blocks wide (default 8)
+    --resolution <w>x<h>      output resolution (default 1280x720)
+    --spp <n>                 samples per pixel (default 256)
+    --branch-count <n>        samples taken per pixel per pass (default 10)
+    --threads <n>             worker threads (default: all cores)
+    --camera <x,y,z>          camera position (default: origin + 0,32,0)
+    --look-at <x,y,z>         point the camera faces (default: origin)
+    --fov <degrees>           horizontal field of view (default 70)
+    --sun-azimuth <degrees>   sun azimuth (default 72)
+    --sun-altitude <degrees>  sun altitude (default 60)
+    --sky <model>             flat, preetham or environment (default flat)
+    --turbidity <t>           haziness of the preetham sky, 1.7 to 10 (default 2.5)
+    --ground-albedo <a>       brightness of the ground below the horizon (default 0.3)
+    --environment <path>      hdr or exr environment map, implies --sky environment
+    --environment-rotation <degrees>
+                              rotation of the environment map around the vertical axis
+    --environment-intensity <x>
+                              brightness multiplier of the environment map (default 1)
+    --fog <mode>              none, uniform or layered (default none)
+    --fog-density <d>         chance per block of light hitting a fog particle (default 0.01)
+    --animation-frame <n>     show frame n of every animated texture (default 0)
+    --animation-tick <t>      show animated textures t game ticks into their animations
+    --interpolate-frames <b>  blend animation frames where the texture asks for it (default true)
+    --max-depth <n>           maximum number of bounces per path (default 5)
+    --roulette-depth <n>      bounce after which russian roulette may end paths (default 3)
+    --output <path>           output image (default render.png)
+    --format <format>         png, png16, pfm or exr (default: from the output extension)
+    --tone-mapping <operator> linear, reinhard, hable or aces (default linear)
+    --exposure <stops>        exposure applied before tone mapping (default 0)
+    --dump <path>             periodically save the accumulated samples so the render can resume
+    --dump-interval <seconds> time between dumps (default 300)
+    --resume <path>           continue the render stored in a dump";
+
+struct Options {
+    world: Option<PathBuf>,
+    scene: Option<PathBuf>,
+    origin: BlockCoords,
+    depth: u8,
+    resolution: (usize, usize),
+    spp: u32,
+    branch_count: u32,
+    threads: usize,
+    camera: Option<Vec3A>,
+    look_at: Option<Vec3A>,
+    fov: Option<f32>,
+    sun_azimuth: Option<f32>,
+    sun_altitude: Option<f32>,
+    sky_model: Option<SkyModel>,
+    turbidity: Option<f32>,
+    ground_albedo: Option<f32>,
+    environment: Option<PathBuf>,
+    environment_rotation: Option<f32>,
+    environment_intensity: Option<f32>,
+    fog_mode: Option<FogMode>,
+    fog_density: Option<f32>,
+    animation_time: Option<AnimationTime>,
+    interpolate_frames: Option<bool>,
+    max_depth: Option<u32>,
+    russian_roulette_depth: Option<u32>,
+    output: PathBuf,
+    format: Option<ExportFormat>,
+    tone_mapper: ToneMapper,
+    dump: Option<PathBuf>,
+    dump_interval: Duration,
+    resume: Option<PathBuf>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut options = Options {
+            world: None,
+            scene: None,
+            origin: BlockCoords::default(),
+            depth: 8,
+            resolution: (1280, 720),
+            spp: 256,
+            branch_count: 10,
+            threads: std::thread::available_parallelism()
+                .map(|count| count.get())
+                .unwrap_or(1),
+            camera: None,
+            look_at: None,
+            fov: None,
+            sun_azimuth: None,
+            sun_altitude: None,
+            sky_model: None,
+            turbidity: None,
+            ground_albedo: None,
+            environment: None,
+            environment_rotation: None,
+            environment_intensity: None,
+            fog_mode: None,
+            fog_density: None,
+            animation_time: None,
+            interpolate_frames: None,
+            max_depth: None,
+            russian_roulette_depth: None,
+            output: PathBuf::from("render.png"),
+            format: None,
+            tone_mapper: ToneMapper::default(),
+            dump: None,
+            dump_interval: Duration::from_secs(300),
+            resume: None,
+        };
+
+        while let Some(flag) = args.next() {
+            if flag == "--help" || flag == "-h" {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow!("missing value for {flag}"))?;
+            match flag.as_str() {
+                "--world" => options.world = Some(PathBuf::from(value)),
+                "--scene" => options.scene = Some(PathBuf::from(value)),
+                "--origin" => {
+                    let [x, y, z] = parse_triple::<i64>(&value)?;
+                    options.origin = BlockCoords { x, y, z };
+                }
+                "--depth" => options.depth = value.parse()?,
+                "--resolution" => {
+                    let (x, y) = value
+                        .split_once('x')
+                        .ok_or_else(|| anyhow!("expected <w>x<h>, got {value}"))?;
+                    options.resolution = (x.parse()?, y.parse()?);
+                }
+                "--spp" => options.spp = value.parse()?,
+                "--branch-count" => options.branch_count = value.parse()?,
+                "--threads" => options.threads = value.parse()?,
+                "--camera" => options.camera = Some(Vec3A::from_array(parse_triple(&value)?)),
+                "--look-at" => options.look_at = Some(Vec3A::from_array(parse_triple(&value)?)),
+                "--fov" => options.fov = Some(value.parse()?),
+                "--sun-azimuth" => options.sun_azimuth = Some(value.parse()?),
+                "--sun-altitude" => options.sun_altitude = Some(value.parse()?),
+                "--sky" => options.sky_model = Some(parse_sky_model(&value)?),
+                "--turbidity" => options.turbidity = Some(value.parse()?),
+                "--ground-albedo" => options.ground_albedo = Some(value.parse()?),
+                "--environment" => options.environment = Some(PathBuf::from(value)),
+                "--environment-rotation" => options.environment_rotation = Some(value.parse()?),
+                "--environment-intensity" => options.environment_intensity = Some(value.parse()?),
+                "--fog" => options.fog_mode = Some(parse_fog_mode(&value)?),
+                "--fog-density" => options.fog_density = Some(value.parse()?),
+                "--animation-frame" => {
+                    options.animation_time = Some(AnimationTime::Frame(value.parse()?))
+                }
+                "--animation-tick" => {
+                    options.animation_time = Some(AnimationTime::Tick(value.parse()?))
+                }
+                "--interpolate-frames" => options.interpolate_frames = Some(value.parse()?),
+                "--max-depth" => options.max_depth = Some(value.parse()?),
+                "--roulette-depth" => options.russian_roulette_depth = Some(value.parse()?),
+                "--output" => options.output = PathBuf::from(value),
+                "--format" => options.format = Some(parse_format(&value)?),
+                "--tone-mapping" => options.tone_mapper.operator = parse_operator(&value)?,
+                "--exposure" => options.tone_mapper.exposure = value.parse()?,
+                "--dump" => options.dump = Some(PathBuf::from(value)),
+                "--dump-interval" => options.dump_interval = Duration::from_secs(value.parse()?),
+                "--resume" => options.resume = Some(PathBuf::from(value)),
+                _ => bail!("unknown option {flag}\n\n{USAGE}"),
+            }
+        }
+
+        if options.world.is_none() == options.scene.is_none() {
+            bail!("exactly one of --world or --scene is required\n\n{USAGE}");
+        }
+        if options.resolution.0 == 0 || options.resolution.1 == 0 {
+            bail!("resolution must not be zero");
+        }
+        if options.threads == 0 {
+            bail!("thread count must not be zero");
+        }
+        Ok(options)
+    }
+
+    ///Without an explicit camera the view looks down at the origin from above
+    fn camera(&self, scene_camera: Option<Camera>) -> Camera {
+        if let Some(mut camera) = scene_camera {
+            if let Some(eye) = self.camera {
+                camera.eye = eye;
+            }
+            if let Some(look_at) = self.look_at
+                && look_at != camera.eye
+            {
+                camera = Camera::look_at(camera.eye, look_at, Vec3A::Y, camera.fov);
+            }
+            if let Some(fov) = self.fov {
+                camera.fov = fov.to_radians();
+            }
+            return camera;
+        }
+        let origin = Vec3A::new(
+            self.origin.x as f32,
+            self.origin.y as f32,
+            self.origin.z as f32,
+        );
+        let eye = self.camera.unwrap_or(origin + Vec3A::new(0.0, 32.0, 0.0));
+        let mut center = self.look_at.unwrap_or(origin);
+        if center == eye {
+            center = eye + Vec3A::Z;
+        }
+        Camera::look_at(eye, center, Vec3A::Y, self.fov.unwrap_or(70.0).to_radians())
+    }
+}
+
+fn parse_triple<T: std::str::FromStr>(value: &str) -> anyhow::Result<[T; 3]>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let parts = value
+        .split(',')
+        .map(|part| part.trim().parse::<T>())
+        .collect::<Result<Vec<_>, _>>()?;
+    parts
+        .try_into()
+        .map_err(|_| anyhow!("expected <x,y,z>, got {value}"))
+}
+
+fn parse_format(value: &str) -> anyhow::Result<ExportFormat> {
+    match value {
+        "png" => Ok(ExportFormat::Png),
+        "png16" => Ok(ExportFormat::Png16),
+        "pfm" => Ok(ExportFormat::Pfm),
+        "exr" => Ok(ExportFormat::Exr),
+        _ => bail!("unknown image format {value}"),
+    }
+}
+
+fn parse_operator(value: &str) -> anyhow::Result<ToneMappingOperator> {
+    match value {
+        "linear" => Ok(ToneMappingOperator::Linear),
+        "reinhard" => Ok(ToneMappingOperator::Reinhard),
+        "hable" => Ok(ToneMappingOperator::Hable),
+        "aces" => Ok(ToneMappingOperator::Aces),
+        _ => bail!("unknown tone mapping operator {value}"),
+    }
+}
+
+fn parse_sky_model(value: &str) -> anyhow::Result<SkyModel> {
+    match value {
+        "flat" => Ok(SkyModel::Flat),
+        "preetham" => Ok(SkyModel::Preetham),
+        "environment" => Ok(SkyModel::Environment),
+        _ => bail!("unknown sky model {value}"),
+    }
+}
+
+fn parse_fog_mode(value: &str) -> anyhow::Result<FogMode> {
+    match value {
+        "none" => Ok(FogMode::None),
+        "uniform" => Ok(FogMode::Uniform),
+        "layered" => Ok(FogMode::Layered),
+        _ => bail!("unknown fog mode {value}"),
+    }
+}
+
+fn save_image(
+    path: &Path,
+    format: Option<ExportFormat>,
+    resolution: (u32, u32),
+    samples: &[F32Color],
+    tone_mapper: &ToneMapper,
+) -> anyhow::Result<()> {
+    let format = format
+        .or_else(|| ExportFormat::from_path(path))
+        .ok_or_else(|| anyhow!("can't tell the image format of {}", path.display()))?;
+    export_image(path, format, resolution, samples, tone_mapper)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+///Combines dumps of the same scene rendered on different machines
+fn merge(mut args: impl Iterator<Item = String>) -> anyhow::Result<()> {
+    let output = args
+        .next()
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!("missing output dump\n\n{USAGE}"))?;
+    let mut image = None;
+    let mut tone_mapper = ToneMapper::default();
+    let mut inputs = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--image" => image = Some(PathBuf::from(value()?)),
+            "--tone-mapping" => tone_mapper.operator = parse_operator(&value()?)?,
+            "--exposure" => tone_mapper.exposure = value()?.parse()?,
+            _ => inputs.push(PathBuf::from(&arg)),
+        }
+    }
+
+    let mut merged: Option<RenderDump> = None;
+    for input in &inputs {
+        let dump = RenderDump::load(input)
+            .with_context(|| format!("failed to read {}", input.display()))?;
+        eprintln!("{}: {} spp", input.display(), dump.spp);
+        merged = Some(match merged {
+            Some(merged) => merged
+                .merge(&dump)
+                .with_context(|| format!("failed to merge {}", input.display()))?,
+            None => dump,
+        });
+    }
+    let merged = merged.ok_or_else(|| anyhow!("no dumps to merge\n\n{USAGE}"))?;
+
+    merged
+        .save(&output)
+        .with_context(|| format!("failed to write {}", output.display()))?;
+    eprintln!("wrote {} with {} spp", output.display(), merged.spp);
+    if let Some(image) = image {
+        save_image(
+            &image,
+            None,
+            merged.resolution,
+            &merged.samples,
+            &tone_mapper,
+        )?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    env_logger::init();
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "merge") {
+        args.next();
+        return merge(args);
+    }
+    let options = Options::parse(args)?;
+
+    let start = Instant::now();
+    let (mut scene, scene_camera) = match (&options.scene, &options.world) {
+        (Some(scene_path), _) => {
+            eprintln!("loading {}", scene_path.display());
+            let (scene, camera) = open_scene(scene_path)?;
+            (scene, Some(camera))
+        }
+        (None, Some(world)) => {
+            let world = world
+                .to_str()
+                .ok_or_else(|| anyhow!("world path is not valid utf-8"))?;
+            eprintln!("loading {world}");
+            (load_world_2(world, &options.origin, options.depth)?, None)
+        }
+        (None, None) => unreachable!(),
+    };
+    if options.sun_azimuth.is_some() || options.sun_altitude.is_some() {
+        let azimuth = options
+            .sun_azimuth
+            .map(f32::to_radians)
+            .unwrap_or(scene.sun.azimuth);
+        let altitude = options
+            .sun_altitude
+            .map(f32::to_radians)
+            .unwrap_or(scene.sun.altitude);
+        let mut sun = Sun::from_angles(azimuth, altitude);
+        sun.luminosity = scene.sun.luminosity;
+        sun.luminosity_pdf = scene.sun.luminosity_pdf;
+        sun.importance_sample_chance = scene.sun.importance_sample_chance;
+        sun.importance_sample_radius = scene.sun.importance_sample_radius;
+        scene.set_sun(sun);
+    }
+    let mut sky = scene.sky.clone();
+    if let Some(environment) = &options.environment {
+        sky.environment = Some(Arc::new(EnvironmentMap::load(environment)?));
+        sky.model = SkyModel::Environment;
+    }
+    if let Some(sky_model) = options.sky_model {
+        sky.model = sky_model;
+    }
+    if let Some(environment_rotation) = options.environment_rotation {
+        sky.environment_rotation = environment_rotation.to_radians();
+    }
+    if let Some(environment_intensity) = options.environment_intensity {
+        sky.environment_intensity =
+            environment_intensity.clamp(0.0, Sky::MAX_ENVIRONMENT_INTENSITY);
+    }
+    if let Some(turbidity) = options.turbidity {
+        sky.turbidity = turbidity.clamp(Sky::MIN_TURBIDITY, Sky::MAX_TURBIDITY);
+    }
+    if let Some(ground_albedo) = options.ground_albedo {
+        sky.ground_albedo = ground_albedo.clamp(0.0, 1.0);
+    }
+    scene.set_sky(sky);
+    if let Some(fog_mode) = options.fog_mode {
+        scene.fog.mode = fog_mode;
+    }
+    if let Some(fog_density) = options.fog_density {
+        scene.fog.density = fog_density.clamp(0.0, Fog::MAX_DENSITY);
+    }
+    if options.animation_time.is_some() || options.interpolate_frames.is_some() {
+        scene.set_texture_animation(TextureAnimation {
+            time: options
+                .animation_time
+                .unwrap_or(scene.texture_animation.time),
+            interpolate: options
+                .interpolate_frames
+                .unwrap_or(scene.texture_animation.interpolate),
+        });
+    }
+    if let Some(max_depth) = options.max_depth {
+        scene.max_depth = max_depth.clamp(1, Scene::MAX_MAX_DEPTH);
+    }
+    if let Some(russian_roulette_depth) = options.russian_roulette_depth {
+        scene.russian_roulette_depth = russian_roulette_depth;
+    }
+    eprintln!("loaded world in {:?}", start.elapsed());
+
+    let camera = options.camera(scene_camera);
+    let scene_hash = scene_hash(&scene, &camera);
+
+    let scene = Arc::new(parking_lot::RwLock::new(scene));
+    let mut renderer = TileRenderer::new(
+        options.resolution,
+        options.spp,
+        options.branch_count,
+        options.threads,
+    );
+    renderer.set_mode(RendererMode::PathTraced);
+    renderer.edit_camera(|renderer_camera| *renderer_camera = camera.clone());
+
+    let start = Instant::now();
+    match &options.resume {
+        Some(resume) => {
+            let dump = RenderDump::load(resume)
+                .with_context(|| format!("failed to read {}", resume.display()))?;
+            if dump.scene_hash != scene_hash {
+                bail!(
+                    "{} was rendered from a different scene or camera",
+                    resume.display()
+                );
+            }
+            eprintln!("resuming from {} at {} spp", resume.display(), dump.spp);
+            renderer.resume_from(scene, dump);
+        }
+        None => renderer.render_scene(scene),
+    }
+    let mut last_dump = Instant::now();
+    loop {
+        let current_spp = renderer.get_current_spp();
+        eprint!(
+            "\rrendering: {}/{} spp ({:.1?})",
+            current_spp.min(options.spp),
+            options.spp,
+            start.elapsed()
+        );
+        if current_spp >= options.spp {
+            break;
+        }
+        if let Some(dump_path) = &options.dump
+            && last_dump.elapsed() >= options.dump_interval
+        {
+            renderer
+                .dump(scene_hash)
+                .save(dump_path)
+                .with_context(|| format!("failed to write {}", dump_path.display()))?;
+            last_dump = Instant::now();
+        }
+        sleep(Duration::from_millis(250));
+    }
+    eprintln!();
+    //stopping the renderer resets its sample count
+    let dump = renderer.dump(scene_hash);
+    renderer.stop();
+    if let Some(dump_path) = &options.dump {
+        dump.save(dump_path)
+            .with_context(|| format!("failed to write {}", dump_path.display()))?;
+    }
+    save_image(
+        &options.output,
+        options.format,
+        dump.resolution,
+        &dump.samples,
+        &options.tone_mapper,
+    )?;
+    eprintln!(
+        "wrote {} in {:?}",
+        options.output.display(),
+        start.elapsed()
+    );
+    Ok(())
+}
@@ -1,3 +1,5 @@
+pub mod tone_mapping;
+
 use std::{mem::transmute, ops::Mul};
 
 use crate::textures::texture::{LUT_TABLE_BYTE, LUT_TABLE_FLOAT};
@@ -132,6 +134,12 @@ impl F32Color {
     }
 }
 
+pub fn pixel_slice_to_u8_slice(slice: &[U8Color]) -> &[u8] {
+    let ptr = slice.as_ptr();
+    let len = std::mem::size_of_val(slice);
+    unsafe { std::slice::from_raw_parts(ptr.cast(), len) }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct U8Color {
@@ -0,0 +1,141 @@
+use super::{F32Color, PixelColor as _, U8Color};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMappingOperator {
+    ///Clips everything above 1
+    #[default]
+    Linear,
+    Reinhard,
+    ///John Hable's filmic curve from Uncharted 2
+    Hable,
+    ///Krzysztof Narkowicz's fit of the ACES reference rendering transform
+    Aces,
+}
+
+impl ToneMappingOperator {
+    pub const OPERATORS: [ToneMappingOperator; 4] = [
+        ToneMappingOperator::Linear,
+        ToneMappingOperator::Reinhard,
+        ToneMappingOperator::Hable,
+        ToneMappingOperator::Aces,
+    ];
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            ToneMappingOperator::Linear => "Linear",
+            ToneMappingOperator::Reinhard => "Reinhard",
+            ToneMappingOperator::Hable => "Filmic (Hable)",
+            ToneMappingOperator::Aces => "ACES",
+        }
+    }
+
+    ///Maps linear radiance to [0, 1]
+    #[inline]
+    pub fn apply(&self, value: f32) -> f32 {
+        let value = value.max(0.0);
+        match self {
+            ToneMappingOperator::Linear => value,
+            ToneMappingOperator::Reinhard => value / (1.0 + value),
+            ToneMappingOperator::Hable => {
+                const EXPOSURE_BIAS: f32 = 2.0;
+                const WHITE_POINT: f32 = 11.2;
+                hable_partial(value * EXPOSURE_BIAS) / hable_partial(WHITE_POINT)
+            }
+            ToneMappingOperator::Aces => {
+                (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+            }
+        }
+        .clamp(0.0, 1.0)
+    }
+}
+
+#[inline]
+fn hable_partial(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+///Turns the linear accumulation buffer into displayable colors
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ToneMapper {
+    pub operator: ToneMappingOperator,
+    ///In stops, every step doubles the brightness
+    pub exposure: f32,
+}
+
+impl ToneMapper {
+    pub const GAMMA: f32 = 2.2;
+    pub const MIN_EXPOSURE: f32 = -8.0;
+    pub const MAX_EXPOSURE: f32 = 8.0;
+
+    pub fn new(operator: ToneMappingOperator, exposure: f32) -> Self {
+        Self { operator, exposure }
+    }
+
+    ///Exposure, tone mapping and gamma correction of a single channel, the result is in [0, 1]
+    #[inline]
+    pub fn map_channel(&self, value: f32) -> f32 {
+        let scale = self.exposure.exp2();
+        self.operator
+            .apply(value * scale)
+            .powf(1.0 / ToneMapper::GAMMA)
+    }
+
+    #[inline]
+    pub fn map_to_u8(&self, color: &F32Color) -> U8Color {
+        let [r, g, b] = [color.r(), color.g(), color.b()]
+            .map(|channel| (self.map_channel(channel) * 255.0).round() as u8);
+        U8Color::new(r, g, b, (color.a().clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+
+    #[inline]
+    pub fn map_to_u16(&self, color: &F32Color) -> [u16; 4] {
+        let [r, g, b] = [color.r(), color.g(), color.b()]
+            .map(|channel| (self.map_channel(channel) * 65535.0).round() as u16);
+        [
+            r,
+            g,
+            b,
+            (color.a().clamp(0.0, 1.0) * 65535.0).round() as u16,
+        ]
+    }
+
+    pub fn map_buffer(&self, output: &mut [U8Color], input: &[F32Color]) {
+        output
+            .iter_mut()
+            .zip(input)
+            .for_each(|(output, input)| *output = self.map_to_u8(input));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn operators_stay_in_range() {
+        ToneMappingOperator::OPERATORS.iter().for_each(|operator| {
+            let mut previous = 0.0;
+            [0.0, 0.01, 0.5, 1.0, 4.0, 100.0, 1e6]
+                .into_iter()
+                .for_each(|value| {
+                    let mapped = operator.apply(value);
+                    assert!((0.0..=1.0).contains(&mapped), "{operator:?} {value}");
+                    assert!(mapped >= previous, "{operator:?} is not monotonic");
+                    previous = mapped;
+                });
+        });
+    }
+
+    #[test]
+    pub fn exposure_doubles_brightness() {
+        let tone_mapper = ToneMapper::new(ToneMappingOperator::Linear, 1.0);
+        let expected = 0.5f32.powf(1.0 / ToneMapper::GAMMA);
+        assert!((tone_mapper.map_channel(0.25) - expected).abs() < 1e-6);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod cuboid;
+pub mod gpu_biomes;
 pub mod gpu_camera;
 pub mod gpu_material;
 pub mod gpu_octree;
@@ -0,0 +1,52 @@
+use glam::Vec3A;
+
+use crate::{
+    octree::biome_map::BiomeMap,
+    scene::biome::{BiomeColors, TintType},
+};
+
+///Colours per palette entry in the tint buffer, one for every [`TintType`] but `None`
+pub const BIOME_TINTS: usize = TintType::TYPES.len() - 1;
+
+///Packs a biome map into the cells per side followed by the palette entry of every column, and
+///its colours into [`BIOME_TINTS`] vectors per entry. Unknown columns point at the last entry
+pub fn biomes_to_gpu_data(biomes: &BiomeMap, colors: &[BiomeColors]) -> (Vec<u32>, Vec<[f32; 4]>) {
+    let default_entry = colors.len() as u32 - 1;
+    let cells = std::iter::once(biomes.cells_per_side())
+        .chain(
+            biomes
+                .cells()
+                .iter()
+                .map(|&cell| (cell as u32).min(default_entry)),
+        )
+        .collect();
+
+    //the preview shades with the texture bytes as they are, so the tints go back to srgb
+    let to_srgb = |color: Vec3A| color.powf(1.0 / 2.2).extend(1.0).to_array();
+    let mut tints = Vec::with_capacity(colors.len() * BIOME_TINTS);
+    tints.extend(colors.iter().flat_map(|colors| {
+        TintType::TYPES[1..]
+            .iter()
+            .map(|&tint| to_srgb(colors.tint(tint)))
+    }));
+    (cells, tints)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scene::biome::Colormaps;
+
+    #[test]
+    pub fn unknown_columns_use_the_last_entry() {
+        let mut biomes = BiomeMap::new(3);
+        biomes.set_column(1, 1, "minecraft:swamp");
+        let colors = BiomeColors::for_palette(biomes.palette(), &Colormaps::default());
+        let (cells, tints) = biomes_to_gpu_data(&biomes, &colors);
+
+        assert_eq!(cells, [2, 1, 1, 1, 0]);
+        assert_eq!(tints.len(), 2 * BIOME_TINTS);
+        let water = TintType::Water as usize - 1;
+        assert!(tints[water] != tints[BIOME_TINTS + water]);
+    }
+}
@@ -41,7 +41,11 @@ impl GPUMaterial {
             emittance,
             roughness,
             metalness,
+            subsurface_color: _,
+            mean_free_path: _,
             texture: _,
+            normal_map: _,
+            specular_map: _,
             tint_index,
         } = material;
 
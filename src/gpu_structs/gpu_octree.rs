@@ -1,6 +1,6 @@
 use std::fmt::Debug;
 
-use crate::octree::new_octree::{ChildType, Octree};
+use crate::octree::new_octree::{ChildType, Octant, OctantId, Octree};
 use bytemuck::{Pod, Zeroable};
 
 ///The first four words are header data, that leaves 128 bits for metadata about the octants,
@@ -23,54 +23,237 @@ pub struct GPUOctreeUniform {
 
 const LEAF_BIT: u32 = 0b0000_0000_0000_0000_0000_0000_0000_0001;
 const CHILD_BIT: u32 = 0b0000_0000_0000_0000_0000_0000_0000_0010;
+///Set on child octants that have a representative leaf value in the buffer from
+///[`lod_values_to_gpu_data`]
 const LOD_BIT: u32 = 0b0000_0000_0000_0000_0000_0000_0000_0100;
 
+const HEADER_WORDS: usize = 4;
+const HEADER_BITS_PER_CHILD: usize = 16;
+
+impl GPUOctreeNode {
+    #[inline]
+    fn header_for(&self, index: usize) -> u32 {
+        let header_index = index / 2;
+        let header_shift = HEADER_BITS_PER_CHILD * (index % 2);
+        (self.data[header_index] >> header_shift) & 0xFFFF
+    }
+
+    #[inline]
+    fn set_header_for(&mut self, index: usize, header: u32) {
+        let header_index = index / 2;
+        let header_shift = HEADER_BITS_PER_CHILD * (index % 2);
+        self.data[header_index] &= !(0xFFFF << header_shift);
+        self.data[header_index] |= (header & 0xFFFF) << header_shift;
+    }
+
+    ///Packs an octant into the node format. `has_lod` tells which child octants have a
+    ///representative leaf value
+    pub fn encode<F: Fn(OctantId) -> bool>(octant: &Octant, has_lod: F) -> Self {
+        let mut node = GPUOctreeNode::zeroed();
+        octant
+            .iter_children()
+            .enumerate()
+            .for_each(|(index, (child_type, data))| {
+                let header = match child_type {
+                    ChildType::Empty => 0,
+                    ChildType::Octant if has_lod(*data) => CHILD_BIT | LOD_BIT,
+                    ChildType::Octant => CHILD_BIT,
+                    ChildType::Leaf => CHILD_BIT | LEAF_BIT,
+                };
+                node.set_header_for(index, header);
+                if child_type != ChildType::Empty {
+                    node.data[HEADER_WORDS + index] = *data;
+                }
+            });
+        node
+    }
+
+    ///Unpacks a node back into an octant. LOD values are not part of the octant, see
+    ///[`GPUOctreeNode::is_lod`]
+    pub fn decode(&self) -> Octant {
+        let mut octant = Octant::default();
+        octant.init_children_with(|index| {
+            let header = self.header_for(index as usize);
+            let data = self.data[HEADER_WORDS + index as usize];
+            if header & CHILD_BIT == 0 {
+                (ChildType::Empty, 0)
+            } else if header & LEAF_BIT != 0 {
+                (ChildType::Leaf, data)
+            } else {
+                (ChildType::Octant, data)
+            }
+        });
+        octant
+    }
+
+    ///Whether the child at `index` is an octant with a representative leaf value
+    pub fn is_lod(&self, index: u8) -> bool {
+        self.header_for(index as usize) & LOD_BIT != 0
+    }
+}
+
+///Packs every octant of the tree. Empty trees get a root without children, so there is always a
+///node for the shader to start from
 pub fn octree_to_gpu_data(tree: &Octree) -> (GPUOctreeUniform, Vec<GPUOctreeNode>) {
-    let mut octant_data = [0u32; 12];
-    let gpu_octants = tree
+    let mut gpu_octants = tree
         .octants_slice()
         .iter()
-        .map(|octant| {
-            octant
-                .iter_children()
-                .enumerate()
-                .for_each(|(index, child)| todo!());
-
-            //verify
-            octant
-                .iter_children()
-                .enumerate()
-                .for_each(|(index, (child_type, data))| {
-                    let header_index = index / 2;
-                    let data_index = 4 + index;
-                    let header_shift = 16 * (index % 2);
-
-                    let is_child = ((octant_data[header_index] >> header_shift) & CHILD_BIT) != 0;
-                    let is_leaf = ((octant_data[header_index] >> header_shift) & LEAF_BIT) != 0;
-
-                    assert!(is_child == matches!(child_type, ChildType::Octant | ChildType::Leaf));
-
-                    assert!(is_leaf == matches!(child_type, ChildType::Leaf));
-
-                    if is_child {
-                        assert_eq!(*data, octant_data[data_index]);
-                    }
-                });
-            let node = GPUOctreeNode { data: octant_data };
-
-            octant_data.iter_mut().for_each(|val| {
-                *val = 0;
-            });
-            node
-        })
+        .map(|octant| GPUOctreeNode::encode(octant, |child| tree.lod_value(child).is_some()))
         .collect::<Vec<_>>();
+    let root = tree.root().unwrap_or_else(|| {
+        gpu_octants.push(GPUOctreeNode::zeroed());
+        (gpu_octants.len() - 1) as u32
+    });
 
     let uniform = GPUOctreeUniform {
         octree_scale: tree.scale(),
         depth: tree.depth() as u32,
-        root: tree.root().unwrap(),
+        root,
         padding: 0,
     };
 
     (uniform, gpu_octants)
 }
+
+///Marks octants without a representative leaf value in the buffer from [`lod_values_to_gpu_data`]
+pub const NO_LOD: u32 = u32::MAX;
+
+///The representative leaf value of every octant, [`NO_LOD`] where there is none. Never empty, so
+///it can always be bound
+pub fn lod_values_to_gpu_data(tree: &Octree) -> Vec<u32> {
+    let values = (0..tree.octants_slice().len() as OctantId)
+        .map(|octant_id| tree.lod_value(octant_id).unwrap_or(NO_LOD))
+        .collect::<Vec<_>>();
+    if values.is_empty() {
+        return vec![NO_LOD];
+    }
+    values
+}
+
+///Unpacks the output of [`octree_to_gpu_data`]. A root without children is read as an empty tree,
+///its node is kept
+pub fn gpu_data_to_octree(uniform: &GPUOctreeUniform, nodes: &[GPUOctreeNode]) -> Octree {
+    let octants = nodes.iter().map(GPUOctreeNode::decode).collect::<Vec<_>>();
+    let root = octants
+        .get(uniform.root as usize)
+        .filter(|root| (0..8).any(|index| root.is_child(index)))
+        .map(|_| uniform.root);
+    Octree::from_parts(root, octants, uniform.depth as u8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_tree() -> Octree {
+        let mut leaves = Octant::default();
+        leaves.set_child(ChildType::Leaf, 7, 0);
+        leaves.set_child(ChildType::Leaf, 8, 3);
+        leaves.set_child(ChildType::Leaf, u32::MAX, 7);
+
+        let mut root = Octant::default();
+        root.set_child(ChildType::Octant, 0, 1);
+        root.set_child(ChildType::Leaf, 42, 6);
+
+        Octree::from_parts(Some(1), vec![leaves, root], 2)
+    }
+
+    #[test]
+    pub fn node_round_trip() {
+        let tree = test_tree();
+        for octant in tree.octants_slice() {
+            let node = GPUOctreeNode::encode(octant, |_| false);
+            let decoded = node.decode();
+            (0..8u8).for_each(|index| {
+                assert_eq!(octant.get_type_of(index), decoded.get_type_of(index));
+                if octant.is_child(index) {
+                    assert_eq!(octant.get_child(index), decoded.get_child(index));
+                }
+            });
+        }
+    }
+
+    #[test]
+    pub fn header_bits() {
+        let tree = test_tree();
+        let (_, nodes) = octree_to_gpu_data(&tree);
+        let root = &nodes[1];
+
+        assert_eq!(root.header_for(0), 0);
+        assert_eq!(root.header_for(1), CHILD_BIT);
+        assert!(!root.is_lod(1));
+        assert_eq!(root.header_for(6), CHILD_BIT | LEAF_BIT);
+        assert_eq!(root.data[HEADER_WORDS + 1], 0);
+        assert_eq!(root.data[HEADER_WORDS + 6], 42);
+
+        let leaves = &nodes[0];
+        assert_eq!(leaves.header_for(0), CHILD_BIT | LEAF_BIT);
+        assert_eq!(leaves.header_for(7), CHILD_BIT | LEAF_BIT);
+        assert_eq!(leaves.data[HEADER_WORDS + 7], u32::MAX);
+    }
+
+    #[test]
+    pub fn octants_with_a_lod_value_are_marked() {
+        let mut octants = test_tree().octants_slice().to_vec();
+        (0..8).for_each(|index| {
+            octants[0].set_child(ChildType::Leaf, 7, index);
+        });
+        let mut tree = Octree::from_parts(Some(1), octants, 2);
+        tree.compute_lod_values();
+
+        let (_, nodes) = octree_to_gpu_data(&tree);
+        assert_eq!(nodes[1].header_for(1), CHILD_BIT | LOD_BIT);
+        assert!(nodes[1].is_lod(1));
+        assert!(!nodes[1].is_lod(6));
+        assert_eq!(nodes[1].decode().get_child(1), (ChildType::Octant, 0));
+    }
+
+    #[test]
+    pub fn empty_trees_get_an_empty_root() {
+        let tree = Octree::default();
+        let (uniform, nodes) = octree_to_gpu_data(&tree);
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(uniform.root, 0);
+        assert!((0..8).all(|index| nodes[0].header_for(index) == 0));
+
+        let decoded = gpu_data_to_octree(&uniform, &nodes);
+        assert_eq!(decoded.root(), None);
+    }
+
+    #[test]
+    pub fn lod_values_fill_every_octant() {
+        let tree = test_tree();
+        assert_eq!(lod_values_to_gpu_data(&tree), [NO_LOD; 2]);
+        let mut octants = tree.octants_slice().to_vec();
+        octants[0].set_child(ChildType::Leaf, 7, 1);
+        octants[0].set_child(ChildType::Leaf, 7, 2);
+        let mut tree = Octree::from_parts(tree.root(), octants, tree.depth());
+        tree.compute_lod_values();
+        //the root is only a fifth full
+        assert_eq!(lod_values_to_gpu_data(&tree), [7, NO_LOD]);
+        assert_eq!(lod_values_to_gpu_data(&Octree::default()), [NO_LOD]);
+    }
+
+    #[test]
+    pub fn tree_round_trip() {
+        let tree = test_tree();
+        let (uniform, nodes) = octree_to_gpu_data(&tree);
+        assert_eq!(uniform.root, 1);
+        assert_eq!(uniform.depth, 2);
+
+        let decoded = gpu_data_to_octree(&uniform, &nodes);
+        assert_eq!(decoded.root(), tree.root());
+        assert_eq!(decoded.depth(), tree.depth());
+        tree.octants_slice()
+            .iter()
+            .zip(decoded.octants_slice())
+            .for_each(|(original, decoded)| {
+                (0..8u8).for_each(|index| {
+                    assert_eq!(original.get_type_of(index), decoded.get_type_of(index));
+                    if original.is_child(index) {
+                        assert_eq!(original.get_child(index), decoded.get_child(index));
+                    }
+                });
+            });
+    }
+}
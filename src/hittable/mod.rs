@@ -57,12 +57,21 @@ pub struct HitRecord {
     pub u: f32,
     pub v: f32,
     pub current_material: MaterialID,
+    ///Material of the face that was hit, which differs from `current_material` for blocks whose
+    ///model has several textures
+    pub face_material: MaterialID,
     pub normal: Vec3A,
     //    pub geom_normal: Vec3A,
     pub previous_material: MaterialID,
     pub color: Vec4,
     pub depth: u32,
     pub specular: bool,
+    ///Solid angle pdf of the bounce that produced this ray's direction. 0 if no light sampling
+    ///technique could have produced it
+    pub bsdf_pdf: f32,
+    ///Rough estimate of how much of the light found by this ray reaches the camera, used to pick
+    ///the russian roulette survival probability
+    pub throughput: Vec3A,
 }
 
 impl Default for HitRecord {
@@ -72,6 +81,7 @@ impl Default for HitRecord {
             u: 0.0,
             v: 0.0,
             current_material: Default::default(),
+            face_material: Default::default(),
             normal: Vec3A::ZERO,
             //     geom_normal: Vec3A::ZERO,
             previous_material: Default::default(),
@@ -79,6 +89,8 @@ impl Default for HitRecord {
             color: Vec4::ZERO,
             depth: 0,
             specular: true,
+            bsdf_pdf: 0.0,
+            throughput: Vec3A::ONE,
         }
     }
 }
@@ -2,7 +2,7 @@
 pub mod app;
 pub mod colors;
 pub mod geometry;
-mod gpu_structs;
+pub mod gpu_structs;
 pub mod hittable;
 pub mod octree;
 mod packed_indices;
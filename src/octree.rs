@@ -1 +1,4 @@
+pub mod biome_map;
 pub mod new_octree;
+pub mod new_octree_traversal;
+pub mod octree_cache;
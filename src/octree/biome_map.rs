@@ -0,0 +1,151 @@
+use glam::UVec3;
+
+///Minecraft stores biomes in cells of 4x4x4 blocks
+pub const BIOME_CELL_SIZE: u32 = 4;
+///Columns without a loaded chunk
+pub const UNKNOWN_BIOME: u16 = u16::MAX;
+
+///The biome at the surface of every 4x4 column of blocks covered by an octree, x first. Positions
+///are in the same block units as the octree
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BiomeMap {
+    cells_per_side: u32,
+    ///Biome names, like `minecraft:plains`
+    palette: Vec<String>,
+    ///Index into `palette` of every column, [`UNKNOWN_BIOME`] where nothing was loaded
+    cells: Box<[u16]>,
+}
+
+impl BiomeMap {
+    ///An empty map covering an octree of `depth`
+    pub fn new(depth: u8) -> Self {
+        let cells_per_side = (1u32 << depth).div_ceil(BIOME_CELL_SIZE);
+        Self {
+            cells_per_side,
+            palette: Vec::new(),
+            cells: vec![UNKNOWN_BIOME; (cells_per_side * cells_per_side) as usize]
+                .into_boxed_slice(),
+        }
+    }
+
+    ///Returns `None` if `cells` doesn't fill a square of `cells_per_side` or indexes past the
+    ///end of `palette`
+    pub fn from_parts(
+        cells_per_side: u32,
+        palette: Vec<String>,
+        cells: Box<[u16]>,
+    ) -> Option<Self> {
+        let in_palette = |&cell: &u16| cell == UNKNOWN_BIOME || (cell as usize) < palette.len();
+        (cells.len() == (cells_per_side * cells_per_side) as usize && cells.iter().all(in_palette))
+            .then_some(Self {
+                cells_per_side,
+                palette,
+                cells,
+            })
+    }
+
+    pub fn cells_per_side(&self) -> u32 {
+        self.cells_per_side
+    }
+
+    pub fn palette(&self) -> &[String] {
+        &self.palette
+    }
+
+    pub fn cells(&self) -> &[u16] {
+        &self.cells
+    }
+
+    ///Sets the biome of the column at cell `x`, `z`
+    pub fn set_column(&mut self, x: u32, z: u32, biome: &str) {
+        if x >= self.cells_per_side || z >= self.cells_per_side {
+            return;
+        }
+        let id = match self.palette.iter().position(|name| name == biome) {
+            Some(id) => id,
+            None => {
+                self.palette.push(biome.to_string());
+                self.palette.len() - 1
+            }
+        };
+        self.cells[(z * self.cells_per_side + x) as usize] = id as u16;
+    }
+
+    ///Palette index of the biome of the column holding the block at `x`, `z`
+    pub fn biome_id_at(&self, x: f32, z: f32) -> u16 {
+        if x < 0.0 || z < 0.0 {
+            return UNKNOWN_BIOME;
+        }
+        let cell_x = (x / BIOME_CELL_SIZE as f32) as u32;
+        let cell_z = (z / BIOME_CELL_SIZE as f32) as u32;
+        if cell_x >= self.cells_per_side || cell_z >= self.cells_per_side {
+            return UNKNOWN_BIOME;
+        }
+        self.cells[(cell_z * self.cells_per_side + cell_x) as usize]
+    }
+
+    pub fn biome_at(&self, x: f32, z: f32) -> Option<&str> {
+        self.palette
+            .get(self.biome_id_at(x, z) as usize)
+            .map(String::as_str)
+    }
+
+    ///Combines the maps of subtrees placed by [`super::new_octree::Octree::from_subtrees`] with
+    ///the same arguments
+    pub fn from_submaps(depth: u8, subtree_depth: u8, submaps: Vec<(UVec3, BiomeMap)>) -> Self {
+        assert!(subtree_depth <= depth);
+        let mut map = BiomeMap::new(depth);
+        let submap_cells = (1u32 << subtree_depth).div_ceil(BIOME_CELL_SIZE);
+        for (grid_position, submap) in submaps {
+            let origin_x = grid_position.x * submap_cells;
+            let origin_z = grid_position.z * submap_cells;
+            for z in 0..submap.cells_per_side {
+                for x in 0..submap.cells_per_side {
+                    let cell = submap.cells[(z * submap.cells_per_side + x) as usize];
+                    if let Some(biome) = submap.palette.get(cell as usize) {
+                        map.set_column(origin_x + x, origin_z + z, biome);
+                    }
+                }
+            }
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn submaps_are_placed_on_grid() {
+        let submap = |biome: &str| {
+            let mut map = BiomeMap::new(3);
+            map.set_column(1, 0, biome);
+            map
+        };
+        let map = BiomeMap::from_submaps(
+            5,
+            3,
+            vec![
+                (UVec3::new(0, 0, 0), submap("minecraft:plains")),
+                (UVec3::new(3, 0, 2), submap("minecraft:swamp")),
+            ],
+        );
+
+        assert_eq!(map.cells_per_side(), 8);
+        assert_eq!(map.biome_at(5.0, 2.0), Some("minecraft:plains"));
+        //the second submap starts 24 blocks along x and 16 along z
+        assert_eq!(map.biome_at(28.5, 17.0), Some("minecraft:swamp"));
+        assert_eq!(map.biome_at(0.0, 0.0), None);
+        assert_eq!(map.biome_at(-1.0, 2.0), None);
+        assert_eq!(map.biome_at(40.0, 2.0), None);
+        assert_eq!(map.palette().len(), 2);
+
+        let cells = map.cells().into();
+        assert_eq!(
+            BiomeMap::from_parts(8, map.palette().to_vec(), cells),
+            Some(map)
+        );
+        assert_eq!(BiomeMap::from_parts(8, Vec::new(), Box::new([0; 64])), None);
+    }
+}
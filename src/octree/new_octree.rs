@@ -1,7 +1,10 @@
+use glam::UVec3;
 use hashbrown::HashMap;
+use log::{info, warn};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::marker::PhantomData;
 use std::num::NonZeroU32;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use std::{fmt::Debug, sync::Arc, time::Instant};
 
@@ -10,21 +13,51 @@ use mc_utils::{
     owned::nbt_string::NBTString, region::borrow::Region, section::borrow::Section,
 };
 
+use crate::geometry::aabb::AABB;
+use crate::gpu_structs::gpu_octree::GPUOctreeNode;
+use crate::octree::{
+    biome_map::{BIOME_CELL_SIZE, BiomeMap},
+    octree_cache::{OctreeCache, SourceStamp},
+};
+
 #[derive(Default)]
 //max depth of 21
 pub struct Octree {
     root: Option<OctantId>,
     octants: Vec<Octant>,
     depth: u8,
+    ///Representative leaf value of every octant, empty until [`Octree::compute_lod_values`] runs
+    lod_values: Vec<Option<u32>>,
+    ///Octants removed by edits, reused before the octant list grows
+    free_list: Vec<OctantId>,
+    ///Whether an octant may have more than one parent, like after [`Octree::deduplicate`]. Edits
+    ///copy the octants they change instead of changing them in place while this is set
+    shared: bool,
 }
 
 impl Octree {
     pub fn new_octant(&mut self) -> OctantId {
+        if let Some(free_id) = self.free_list.pop() {
+            self.octants[free_id as usize].clear();
+            return free_id;
+        }
         let new_octant_id = self.octants.len();
         self.octants.push(Default::default());
         new_octant_id as OctantId
     }
 
+    pub fn from_parts(root: Option<OctantId>, octants: Vec<Octant>, depth: u8) -> Self {
+        let shared = has_shared_octants(root, &octants);
+        Self {
+            root,
+            octants,
+            depth,
+            lod_values: Vec::new(),
+            free_list: Vec::new(),
+            shared,
+        }
+    }
+
     pub fn root(&self) -> Option<OctantId> {
         self.root
     }
@@ -52,27 +85,648 @@ impl Octree {
         }
     }
 
+    ///Builds a tree of `depth` out of subtrees that all have a depth of `subtree_depth`. Each
+    ///subtree is placed at its position on a grid measured in subtree sized cells
+    pub fn from_subtrees(depth: u8, subtree_depth: u8, subtrees: Vec<(UVec3, Octree)>) -> Self {
+        assert!(subtree_depth <= depth);
+        let mut tree = Octree {
+            root: None,
+            octants: Vec::new(),
+            depth,
+            lod_values: Vec::new(),
+            free_list: Vec::new(),
+            shared: false,
+        };
+
+        let levels_above = depth - subtree_depth;
+
+        for (grid_position, subtree) in subtrees {
+            let Octree {
+                root: Some(subtree_root),
+                octants: mut subtree_octants,
+                shared,
+                ..
+            } = subtree
+            else {
+                continue;
+            };
+            tree.shared |= shared;
+
+            let offset = tree.octants.len() as u32;
+            subtree_octants.iter_mut().for_each(|octant| {
+                octant.iter_children_mut().for_each(|(child_type, value)| {
+                    if child_type == ChildType::Octant {
+                        *value += offset;
+                    }
+                });
+            });
+            tree.octants.extend(subtree_octants);
+            let subtree_root = subtree_root + offset;
+
+            if levels_above == 0 {
+                tree.root = Some(subtree_root);
+                continue;
+            }
+
+            let mut current = match tree.root {
+                Some(root) => root,
+                None => {
+                    let root = tree.new_octant();
+                    tree.root = Some(root);
+                    root
+                }
+            };
+
+            for level in (0..levels_above).rev() {
+                let child_index = (((grid_position.x >> level) & 1)
+                    | (((grid_position.y >> level) & 1) << 1)
+                    | (((grid_position.z >> level) & 1) << 2))
+                    as u8;
+
+                if level == 0 {
+                    tree.octants[current as usize].set_child(
+                        ChildType::Octant,
+                        subtree_root,
+                        child_index,
+                    );
+                    break;
+                }
+
+                current = match tree.octants[current as usize].get_child(child_index) {
+                    (ChildType::Octant, id) => id,
+                    _ => {
+                        let id = tree.new_octant();
+                        tree.octants[current as usize].set_child(
+                            ChildType::Octant,
+                            id,
+                            child_index,
+                        );
+                        id
+                    }
+                };
+            }
+        }
+
+        tree
+    }
+
+    ///Replaces the value of every leaf with the result of `f`. Leaves for which `f` returns `None`
+    ///are removed
+    pub fn remap_leaves<F: FnMut(u32) -> Option<u32>>(&mut self, mut f: F) {
+        self.lod_values
+            .iter_mut()
+            .for_each(|lod_value| *lod_value = lod_value.and_then(&mut f));
+        self.octants.iter_mut().for_each(|octant| {
+            (0..8u8).for_each(|index| {
+                if let (ChildType::Leaf, value) = octant.get_child(index) {
+                    match f(value) {
+                        Some(new_value) => {
+                            octant.overwrite_child(ChildType::Leaf, new_value, index)
+                        }
+                        None => octant.overwrite_child(ChildType::Empty, 0, index),
+                    }
+                }
+            });
+        });
+    }
+
+    ///Calls `f` with the lowest corner, side length and value of every leaf. Positions and sizes
+    ///are measured in the smallest cells of the tree
+    pub fn for_each_leaf<F: FnMut(UVec3, u32, u32)>(&self, mut f: F) {
+        self.leaves()
+            .for_each(|leaf| f(leaf.position, leaf.size, leaf.value));
+    }
+
+    ///Iterates over every non-empty leaf, compacted ones included
+    pub fn leaves(&self) -> OctreeLeafIterator<'_> {
+        self.query_box(UVec3::ZERO, UVec3::MAX)
+    }
+
+    ///Iterates over the leaves that overlap the voxels from `min` to `max` inclusive. Leaves are
+    ///returned whole, even when only part of them is inside the box
+    pub fn query_box(&self, min: UVec3, max: UVec3) -> OctreeLeafIterator<'_> {
+        let stack = match self.root {
+            Some(root) if self.depth > 0 => {
+                vec![(ChildType::Octant, root, UVec3::ZERO, 1 << self.depth)]
+            }
+            _ => Vec::new(),
+        };
+        OctreeLeafIterator {
+            octants: &self.octants,
+            stack,
+            min,
+            max,
+        }
+    }
+
+    ///Picks a representative leaf value for every octant, for renderers that stop before reaching
+    ///the leaves of far away octants. It is the value covering the most of the octant, found from
+    ///the values of its children. Octants that are less than half full get none, as drawing them
+    ///solid would make thin structures look thicker than they are
+    pub fn compute_lod_values(&mut self) {
+        //representative value and the fraction of the octant covered by leaves
+        let mut summaries: Vec<Option<(Option<u32>, f32)>> = vec![None; self.octants.len()];
+        let Some(root) = self.root else {
+            self.lod_values.clear();
+            return;
+        };
+
+        let mut stack = vec![(root, false)];
+        while let Some((octant_id, children_done)) = stack.pop() {
+            if summaries[octant_id as usize].is_some() {
+                continue;
+            }
+            let octant = &self.octants[octant_id as usize];
+            if !children_done {
+                stack.push((octant_id, true));
+                octant.iter_children().for_each(|(child_type, &child)| {
+                    if child_type == ChildType::Octant && summaries[child as usize].is_none() {
+                        stack.push((child, false));
+                    }
+                });
+                continue;
+            }
+
+            let mut candidates: Vec<(u32, f32)> = Vec::with_capacity(8);
+            let mut coverage = 0.0;
+            octant.iter_children().for_each(|(child_type, &child)| {
+                let (value, child_coverage) = match child_type {
+                    ChildType::Empty => return,
+                    ChildType::Leaf => (Some(child), 1.0),
+                    ChildType::Octant => summaries[child as usize].unwrap(),
+                };
+                coverage += child_coverage / 8.0;
+                let Some(value) = value else {
+                    return;
+                };
+                match candidates
+                    .iter_mut()
+                    .find(|(candidate, _)| *candidate == value)
+                {
+                    Some((_, weight)) => *weight += child_coverage,
+                    None => candidates.push((value, child_coverage)),
+                }
+            });
+            let majority = candidates
+                .iter()
+                .fold(
+                    None,
+                    |best: Option<(u32, f32)>, &(value, weight)| match best {
+                        Some((_, best_weight)) if best_weight >= weight => best,
+                        _ => Some((value, weight)),
+                    },
+                )
+                .map(|(value, _)| value);
+            summaries[octant_id as usize] = Some((majority, coverage));
+        }
+
+        self.lod_values = summaries
+            .into_iter()
+            .map(|summary| {
+                let (value, coverage) = summary?;
+                value.filter(|_| coverage >= 0.5)
+            })
+            .collect();
+    }
+
+    ///Representative leaf value of an octant, see [`Octree::compute_lod_values`]
+    pub fn lod_value(&self, octant_id: OctantId) -> Option<u32> {
+        self.lod_values.get(octant_id as usize).copied().flatten()
+    }
+
+    ///Merges identical subtrees so each one is stored once, turning the tree into a directed
+    ///acyclic graph. Octants are only merged with octants at the same depth, and ones that can't be
+    ///reached from the root, like the ones freed by edits, are dropped. Returns how many octants
+    ///were removed
+    pub fn deduplicate(&mut self) -> usize {
+        let before = self.octants.len();
+        let Some(root) = self.root else {
+            self.octants.clear();
+            self.free_list.clear();
+            return before;
+        };
+
+        let mut new_ids: Vec<Option<OctantId>> = vec![None; before];
+        let mut unique: HashMap<(u8, Octant), OctantId> = HashMap::new();
+        let mut octants = Vec::new();
+
+        //children are merged before their parents, so equal subtrees end up with equal octants
+        let mut stack = vec![(root, 0u8, false)];
+        while let Some((octant_id, depth, children_merged)) = stack.pop() {
+            if new_ids[octant_id as usize].is_some() {
+                continue;
+            }
+            let octant = &self.octants[octant_id as usize];
+            if !children_merged {
+                stack.push((octant_id, depth, true));
+                octant.iter_children().for_each(|(child_type, &child)| {
+                    if child_type == ChildType::Octant && new_ids[child as usize].is_none() {
+                        stack.push((child, depth + 1, false));
+                    }
+                });
+                continue;
+            }
+
+            let mut merged = octant.normalized();
+            merged.iter_children_mut().for_each(|(child_type, child)| {
+                if child_type == ChildType::Octant {
+                    *child = new_ids[*child as usize].unwrap();
+                }
+            });
+            let new_id = *unique
+                .entry((depth, merged))
+                .or_insert_with_key(|(_, merged)| {
+                    octants.push(merged.clone());
+                    (octants.len() - 1) as OctantId
+                });
+            new_ids[octant_id as usize] = Some(new_id);
+        }
+
+        self.root = new_ids[root as usize];
+        self.octants = octants;
+        self.lod_values.clear();
+        self.free_list.clear();
+        self.shared = has_shared_octants(self.root, &self.octants);
+        before - self.octants.len()
+    }
+
+    ///Value of the leaf covering the voxel at `position`, measured in the smallest cells of the
+    ///tree
+    pub fn get_voxel(&self, position: UVec3) -> Option<u32> {
+        if position.max_element() >= 1 << self.depth {
+            return None;
+        }
+        let mut octant_id = self.root?;
+        let mut size = 1u32 << self.depth;
+        loop {
+            size /= 2;
+            let index = child_index(position / size % 2);
+            match self.octants[octant_id as usize].get_child(index) {
+                (ChildType::Empty, _) => return None,
+                (ChildType::Leaf, value) => return Some(value),
+                (ChildType::Octant, id) => octant_id = id,
+            }
+        }
+    }
+
+    ///Sets the voxel at `position`, growing the tree if it doesn't reach that far. Returns the
+    ///previous value. Positions past the reach of any tree, see [`Octree::fill_box`], are ignored
+    pub fn set_voxel(&mut self, position: UVec3, value: u32) -> Option<u32> {
+        let previous = self.get_voxel(position);
+        self.fill_box(position, position, value);
+        previous
+    }
+
+    ///Clears the voxel at `position` and returns its value
+    pub fn remove_voxel(&mut self, position: UVec3) -> Option<u32> {
+        let previous = self.get_voxel(position)?;
+        self.clear_box(position, position);
+        Some(previous)
+    }
+
+    ///Sets every voxel from `min` to `max` inclusive, growing the tree if it doesn't reach that far.
+    ///The side of the tree has to fit in a u32, boxes reaching further are rejected and false is
+    ///returned without writing anything
+    pub fn fill_box(&mut self, min: UVec3, max: UVec3, value: u32) -> bool {
+        let Some(required_size) = max
+            .max_element()
+            .checked_add(1)
+            .and_then(u32::checked_next_power_of_two)
+        else {
+            return false;
+        };
+        self.expand_to(required_size.ilog2().max(1) as u8);
+        self.write_box(min, max, Some(value));
+        true
+    }
+
+    ///Clears every voxel from `min` to `max` inclusive
+    pub fn clear_box(&mut self, min: UVec3, max: UVec3) {
+        self.write_box(min, max, None);
+    }
+
+    ///Writes `value` into a box, splitting the compacted leaves it cuts through and compacting the
+    ///octants it leaves uniform. Representative values are dropped, as they may no longer match
+    fn write_box(&mut self, min: UVec3, max: UVec3, value: Option<u32>) {
+        let Some(root) = self.root else {
+            if value.is_none() {
+                return;
+            }
+            self.root = Some(self.new_octant());
+            return self.write_box(min, max, value);
+        };
+        self.lod_values.clear();
+        let size = 1u32 << self.depth;
+        let root = (ChildType::Octant, root);
+        self.root = match self.write_child(root, UVec3::ZERO, size, min, max, value) {
+            (ChildType::Empty, _) => None,
+            (ChildType::Octant, id) => Some(id),
+            //the root stays an octant, even when the whole tree is one value
+            (ChildType::Leaf, leaf) => {
+                let id = self.new_octant();
+                self.octants[id as usize].init_children_with(|_| (ChildType::Leaf, leaf));
+                Some(id)
+            }
+        };
+    }
+
+    ///Writes `value` into the part of a child covering `size` cells from `origin` that overlaps
+    ///`min..=max`, and returns what the child turns into
+    fn write_child(
+        &mut self,
+        child: (ChildType, u32),
+        origin: UVec3,
+        size: u32,
+        min: UVec3,
+        max: UVec3,
+        value: Option<u32>,
+    ) -> (ChildType, u32) {
+        let end = origin + (size - 1);
+        if min.cmpgt(end).any() || max.cmplt(origin).any() {
+            return child;
+        }
+        let written = match value {
+            Some(value) => (ChildType::Leaf, value),
+            None => (ChildType::Empty, 0),
+        };
+        if min.cmple(origin).all() && max.cmpge(end).all() {
+            self.release(child);
+            return written;
+        }
+        if child.0 == written.0 && (child.0 == ChildType::Empty || child.1 == written.1) {
+            return child;
+        }
+
+        let octant_id = match child {
+            (ChildType::Octant, id) => self.writable_octant(id),
+            //a compacted leaf is split into eight smaller ones
+            (ChildType::Leaf, leaf) => {
+                let id = self.new_octant();
+                self.octants[id as usize].init_children_with(|_| (ChildType::Leaf, leaf));
+                id
+            }
+            (ChildType::Empty, _) => self.new_octant(),
+        };
+        let half = size / 2;
+        (0..8u8).for_each(|index| {
+            let child = self.octants[octant_id as usize].get_child(index);
+            let child_origin = origin + child_offset(index) * half;
+            let (child_type, data) = self.write_child(child, child_origin, half, min, max, value);
+            self.octants[octant_id as usize].overwrite_child(child_type, data, index);
+        });
+
+        let octant = &self.octants[octant_id as usize];
+        let compacted = if octant.is_empty() {
+            (ChildType::Empty, 0)
+        } else if octant.is_compactable() {
+            (ChildType::Leaf, octant.children[0])
+        } else {
+            return (ChildType::Octant, octant_id);
+        };
+        self.free_list.push(octant_id);
+        compacted
+    }
+
+    ///An octant that can be changed in place of `octant_id`, which is a copy if octants are shared
+    fn writable_octant(&mut self, octant_id: OctantId) -> OctantId {
+        if !self.shared {
+            return octant_id;
+        }
+        let copy = self.octants[octant_id as usize].clone();
+        let id = self.new_octant();
+        self.octants[id as usize] = copy;
+        id
+    }
+
+    ///Frees an overwritten child along with everything below it. Shared octants may still be used
+    ///elsewhere, so they're left for [`Octree::deduplicate`] to drop
+    fn release(&mut self, child: (ChildType, u32)) {
+        let (ChildType::Octant, octant_id) = child else {
+            return;
+        };
+        if self.shared {
+            return;
+        }
+        let mut stack = vec![octant_id];
+        while let Some(octant_id) = stack.pop() {
+            self.octants[octant_id as usize]
+                .iter_children()
+                .for_each(|(child_type, &child)| {
+                    if child_type == ChildType::Octant {
+                        stack.push(child);
+                    }
+                });
+            self.free_list.push(octant_id);
+        }
+    }
+
     pub fn expand_by(&mut self, depth: u8) {
         for _ in 0..depth {
             let new_root_id = self.new_octant();
 
             if let Some(root_id) = self.root {
-                self.octants[new_root_id as usize].set_child(ChildType::Octant, root_id, 0);
+                let root = &self.octants[root_id as usize];
+                //a root of eight equal leaves is only kept because roots have to be octants
+                if root.is_compactable() && !root.is_empty() {
+                    let leaf = root.children[0];
+                    self.octants[new_root_id as usize].set_child(ChildType::Leaf, leaf, 0);
+                    if !self.shared {
+                        self.free_list.push(root_id);
+                    }
+                } else {
+                    self.octants[new_root_id as usize].set_child(ChildType::Octant, root_id, 0);
+                }
             }
             self.root = Some(new_root_id)
         }
-        self.depth += depth
+        self.depth += depth;
+        self.lod_values.clear();
+    }
+
+    ///Counts what the tree is made of and how much memory it takes. Octants shared by a
+    ///deduplicated tree are counted once, so the counts follow what is stored rather than the
+    ///space the tree covers
+    pub fn stats(&self) -> OctreeStats {
+        let mut stats = OctreeStats {
+            octants: self.octants.len(),
+            free_octants: self.free_list.len(),
+            leaves_per_depth: vec![0; self.depth as usize + 1],
+            cpu_bytes: self.octants.capacity() * size_of::<Octant>()
+                + self.lod_values.capacity() * size_of::<Option<u32>>()
+                + self.free_list.capacity() * size_of::<OctantId>(),
+            //the lod value buffer always holds at least one value so it can be bound
+            gpu_bytes: self.octants.len() * size_of::<GPUOctreeNode>()
+                + self.octants.len().max(1) * size_of::<u32>(),
+            ..Default::default()
+        };
+        let root = match self.root {
+            Some(root) if self.depth > 0 => root,
+            _ => return stats,
+        };
+
+        let mut value_counts: HashMap<u32, usize> = HashMap::new();
+        let mut visited = vec![false; self.octants.len()];
+        let mut stack = vec![(root, 0u8)];
+        while let Some((octant_id, depth)) = stack.pop() {
+            if std::mem::replace(&mut visited[octant_id as usize], true) {
+                continue;
+            }
+            stats.reachable_octants += 1;
+            self.octants[octant_id as usize]
+                .iter_children()
+                .for_each(|(child_type, &child)| match child_type {
+                    ChildType::Empty => {}
+                    ChildType::Octant => stack.push((child, depth + 1)),
+                    ChildType::Leaf => {
+                        stats.leaves_per_depth[depth as usize + 1] += 1;
+                        *value_counts.entry(child).or_default() += 1;
+                    }
+                });
+        }
+
+        stats.compacted_leaves = stats.leaves_per_depth[..self.depth as usize].iter().sum();
+        stats.value_histogram = value_counts.into_iter().collect();
+        stats
+            .value_histogram
+            .sort_by(|(a_value, a_count), (b_value, b_count)| {
+                b_count.cmp(a_count).then(a_value.cmp(b_value))
+            });
+        stats
     }
 }
 
 pub type OctantId = u32;
 
-#[derive(Default, Debug, Clone)]
+///Position of a child within its parent, in units of the child's size
+fn child_offset(index: u8) -> UVec3 {
+    UVec3::new(
+        index as u32 & 1,
+        (index as u32 >> 1) & 1,
+        (index as u32 >> 2) & 1,
+    )
+}
+
+///Index of the child at an offset from [`child_offset`]
+fn child_index(offset: UVec3) -> u8 {
+    (offset.x | offset.y << 1 | offset.z << 2) as u8
+}
+
+///Whether any octant is referenced more than once, by the root or by other octants
+fn has_shared_octants(root: Option<OctantId>, octants: &[Octant]) -> bool {
+    let mut referenced = vec![false; octants.len()];
+    root.into_iter()
+        .chain(octants.iter().flat_map(|octant| {
+            octant
+                .iter_children()
+                .filter(|(child_type, _)| *child_type == ChildType::Octant)
+                .map(|(_, &child)| child)
+        }))
+        .any(|octant_id| std::mem::replace(&mut referenced[octant_id as usize], true))
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Octant {
     child_mask: u16,
     children: [u32; 8],
 }
 
+///A non-empty leaf of an [`Octree`]. Positions and sizes are measured in the smallest cells of the
+///tree, which are blocks for world octrees
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OctreeLeaf {
+    ///Lowest corner
+    pub position: UVec3,
+    pub size: u32,
+    pub value: u32,
+}
+
+impl OctreeLeaf {
+    ///Number of cells the leaf covers
+    pub fn volume(&self) -> u64 {
+        (self.size as u64).pow(3)
+    }
+
+    pub fn aabb(&self) -> AABB {
+        AABB::new(
+            self.position.as_vec3a(),
+            (self.position + self.size).as_vec3a(),
+        )
+    }
+}
+
+///Summary of an [`Octree`] from [`Octree::stats`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OctreeStats {
+    ///Every stored octant, including ones left unreachable by edits
+    pub octants: usize,
+    pub reachable_octants: usize,
+    ///Octants freed by edits and waiting to be reused
+    pub free_octants: usize,
+    ///Stored leaves at each depth, where the children of the root are at depth 1 and single voxels
+    ///at the depth of the tree
+    pub leaves_per_depth: Vec<usize>,
+    ///Leaves covering more than one voxel
+    pub compacted_leaves: usize,
+    ///Memory allocated for the octants and the data kept alongside them
+    pub cpu_bytes: usize,
+    ///Size of the packed [`GPUOctreeNode`] buffer and of the lod values uploaded next to it
+    pub gpu_bytes: usize,
+    ///How many stored leaves hold each value, most common first
+    pub value_histogram: Vec<(u32, usize)>,
+}
+
+impl OctreeStats {
+    pub fn leaves(&self) -> usize {
+        self.leaves_per_depth.iter().sum()
+    }
+}
+
+///Depth first walk over the leaves of an [`Octree`] that overlap a box, from
+///[`Octree::query_box`]
+pub struct OctreeLeafIterator<'a> {
+    octants: &'a [Octant],
+    ///Children left to visit, with their lowest corner and size
+    stack: Vec<(ChildType, u32, UVec3, u32)>,
+    min: UVec3,
+    max: UVec3,
+}
+
+impl Iterator for OctreeLeafIterator<'_> {
+    type Item = OctreeLeaf;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((child_type, data, position, size)) = self.stack.pop() {
+            let end = position + (size - 1);
+            if self.min.cmpgt(end).any() || self.max.cmplt(position).any() {
+                continue;
+            }
+            match child_type {
+                ChildType::Empty => {}
+                ChildType::Leaf => {
+                    return Some(OctreeLeaf {
+                        position,
+                        size,
+                        value: data,
+                    });
+                }
+                ChildType::Octant => {
+                    let half = size / 2;
+                    //pushed in reverse so children come out in index order
+                    let octant = &self.octants[data as usize];
+                    (0..8u8).rev().for_each(|index| {
+                        let (child_type, data) = octant.get_child(index);
+                        if child_type != ChildType::Empty {
+                            let child_position = position + child_offset(index) * half;
+                            self.stack.push((child_type, data, child_position, half));
+                        }
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
 pub struct OctantChildIterator<'a> {
     child_mask: u16,
     index: usize,
@@ -119,18 +773,17 @@ impl<'a> Iterator for OctantChildIteratorMut<'a> {
         let i = self.index;
         self.index += 1;
 
+        let child = unsafe { self.children.add(i).as_mut().unwrap() };
+
         if ((1 << i) & self.child_mask) != 0 {
             if ((1 << (i + 8)) & self.child_mask) != 0 {
-                return Some((ChildType::Leaf, unsafe {
-                    self.children.add(i).as_mut().unwrap()
-                }));
+                Some((ChildType::Leaf, child))
             } else {
-                return Some((ChildType::Octant, unsafe {
-                    self.children.add(i).as_mut().unwrap()
-                }));
+                Some((ChildType::Octant, child))
             }
+        } else {
+            Some((ChildType::Empty, child))
         }
-        None
     }
 }
 
@@ -170,9 +823,9 @@ impl Octant {
                 self.child_mask |= 1 << (index + 8);
             }
             ChildType::Octant => {
-                self.child_mask |= 1 << (index + 8);
+                self.child_mask |= 1 << index;
 
-                self.child_mask &= !(1 << index);
+                self.child_mask &= !(1 << (index + 8));
             }
         }
     }
@@ -250,6 +903,16 @@ impl Octant {
     pub fn get_child(&self, index: u8) -> (ChildType, u32) {
         (self.get_type_of(index), self.children[index as usize])
     }
+    ///A copy with the data of empty children zeroed, so equal octants compare equal
+    fn normalized(&self) -> Octant {
+        let mut octant = Octant::default();
+        octant.init_children_with(|index| match self.get_child(index) {
+            (ChildType::Empty, _) => (ChildType::Empty, 0),
+            child => child,
+        });
+        octant
+    }
+
     #[inline]
     fn clear(&mut self) {
         *self = Octant::default()
@@ -275,14 +938,158 @@ pub struct LeafId {
     idx: u8,
 }
 
-fn calculate_loading_range(position: &BlockCoords, octree_depth: u8) {
-    let world_size = 2_u32.pow(octree_depth as u32);
+///Returns the inclusive range of region coordinates on the x and z axes covered by a cube with a
+///side length of `2^octree_depth` centered on `position`
+fn calculate_loading_range(position: &BlockCoords, octree_depth: u8) -> ((i64, i64), (i64, i64)) {
+    let world_size = 2_i64.pow(octree_depth as u32);
 
     let half_world_size = world_size / 2;
 
-    let _start_x = position.x - half_world_size as i64;
-    let _start_z = position.z - half_world_size as i64;
-    todo!()
+    let start_x = position.x - half_world_size;
+    let start_z = position.z - half_world_size;
+
+    let end_x = start_x + world_size - 1;
+    let end_z = start_z + world_size - 1;
+
+    (
+        (
+            start_x.div_euclid(REGION_SIZE),
+            end_x.div_euclid(REGION_SIZE),
+        ),
+        (
+            start_z.div_euclid(REGION_SIZE),
+            end_z.div_euclid(REGION_SIZE),
+        ),
+    )
+}
+
+const REGION_SIZE: i64 = 1 << REGION_OCTREE_DEPTH;
+
+///Builds an octree out of every region of the world at `world_path` that overlaps the cube of side
+///`2^depth` centered on `origin`. The tree starts at the lowest corner of the lowest region on the
+///x and z axes, and at the bottom of the world (y = -64) on the y axis. The biomes of the loaded
+///chunks are returned alongside it. Leaves still hold block state ids, so the tree isn't
+///deduplicated yet
+pub fn build_world_octree(
+    world_path: &Path,
+    origin: &BlockCoords,
+    depth: u8,
+    blockstate_map: Arc<Mutex<HashMap<NBTString, u32>>>,
+) -> Option<(Octree, BiomeMap)> {
+    let ((start_x, end_x), (start_z, end_z)) = calculate_loading_range(origin, depth);
+
+    let regions_per_axis = (end_x - start_x + 1).max(end_z - start_z + 1) as u32;
+    let tree_depth = REGION_OCTREE_DEPTH as u8 + regions_per_axis.next_power_of_two().ilog2() as u8;
+
+    let subtrees = region_coords_in_range(origin, depth)
+        .into_par_iter()
+        .filter_map(|(x, z)| {
+            let region_path = region_file_path(world_path, x, z);
+            let bytes = std::fs::read(&region_path)
+                .map_err(|err| warn!("skipping region {}: {err}", region_path.display()))
+                .ok()?;
+
+            let region =
+                Region::from_bytes(&bytes, mc_utils::coords::region::RegionCoords { x, z });
+
+            let (tree, biomes) = build_region_octree(region, blockstate_map.clone())?;
+            let grid_position = UVec3::new((x - start_x) as u32, 0, (z - start_z) as u32);
+            Some(((grid_position, tree), (grid_position, biomes)))
+        })
+        .collect::<Vec<_>>();
+
+    if subtrees.is_empty() {
+        return None;
+    }
+
+    let (subtrees, biome_maps) = subtrees.into_iter().unzip();
+    let tree = Octree::from_subtrees(tree_depth, REGION_OCTREE_DEPTH as u8, subtrees);
+    Some((
+        tree,
+        BiomeMap::from_submaps(tree_depth, REGION_OCTREE_DEPTH as u8, biome_maps),
+    ))
+}
+
+fn region_coords_in_range(origin: &BlockCoords, depth: u8) -> Vec<(i64, i64)> {
+    let ((start_x, end_x), (start_z, end_z)) = calculate_loading_range(origin, depth);
+    (start_x..=end_x)
+        .flat_map(|x| (start_z..=end_z).map(move |z| (x, z)))
+        .collect()
+}
+
+fn region_file_path(world_path: &Path, x: i64, z: i64) -> PathBuf {
+    world_path.join("region").join(format!("r.{x}.{z}.mca"))
+}
+
+///Same as [`build_world_octree`], but first looks for a cached build of the same world, origin and
+///depth in `cache_directory`. The cache is only used if none of the region files it was built from
+///have changed, in which case the blockstate map is replaced with the cached palette. Fresh builds
+///are written back to the cache. Neither comes with LOD values, call
+///[`Octree::compute_lod_values`] once the leaves hold their final values
+pub fn load_or_build_world_octree(
+    world_path: &Path,
+    origin: &BlockCoords,
+    depth: u8,
+    blockstate_map: Arc<Mutex<HashMap<NBTString, u32>>>,
+    cache_directory: &Path,
+) -> Option<(Octree, BiomeMap)> {
+    let sources = region_coords_in_range(origin, depth)
+        .into_iter()
+        .filter_map(|(x, z)| SourceStamp::for_file(&region_file_path(world_path, x, z)))
+        .collect::<Vec<_>>();
+
+    let world_name = world_path
+        .canonicalize()
+        .unwrap_or_else(|_| world_path.to_path_buf())
+        .display()
+        .to_string();
+    let key = fxhash::hash64(&(world_name, origin.x, origin.y, origin.z, depth));
+    let cache_path = cache_directory.join(format!("{key:016x}.octree"));
+
+    match OctreeCache::load(&cache_path) {
+        Ok(cache) if cache.sources == sources => {
+            let start = Instant::now();
+            let mut blockstate_map = blockstate_map.lock().unwrap();
+            blockstate_map.clear();
+            blockstate_map.extend(
+                cache
+                    .palette
+                    .iter()
+                    .map(|(state, id)| (NBTString::new_from_str(state), *id)),
+            );
+            info!(
+                "loaded octree from cache {} in {:?}",
+                cache_path.display(),
+                start.elapsed()
+            );
+            return Some((cache.octree, cache.biomes));
+        }
+        Ok(_) => info!("octree cache {} is stale", cache_path.display()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => warn!("ignoring octree cache {}: {err}", cache_path.display()),
+    }
+
+    let (octree, biomes) = build_world_octree(world_path, origin, depth, blockstate_map.clone())?;
+
+    let palette = blockstate_map
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(state, id)| (state.to_str().to_string(), *id))
+        .collect();
+    let cache = OctreeCache {
+        octree,
+        palette,
+        sources,
+        biomes,
+    };
+    if let Err(err) = cache.save(&cache_path) {
+        warn!(
+            "failed to write octree cache {}: {err}",
+            cache_path.display()
+        );
+    }
+    Some((cache.octree, cache.biomes))
 }
 
 pub fn construct_all() {
@@ -314,7 +1121,7 @@ const HIGHEST_SECTION_INDEX: i8 = 19;
 pub fn build_region_octree(
     region: Region,
     blockstate_map: Arc<Mutex<HashMap<NBTString, u32>>>,
-) -> Option<Octree> {
+) -> Option<(Octree, BiomeMap)> {
     //TODO maybe redo blockstate hash function
     let start = Instant::now();
     let region_chunk_data = region.load_all_chunk_data();
@@ -349,6 +1156,23 @@ pub fn build_region_octree(
 
     println!("time parsing chunks: {:?}", end.duration_since(start));
 
+    let mut biomes = BiomeMap::new(REGION_OCTREE_DEPTH as u8);
+    chunks.iter().enumerate().for_each(|(i, chunk)| {
+        let Some(columns) = chunk.as_ref().and_then(surface_biomes) else {
+            return;
+        };
+        let (chunk_local_x, chunk_local_z) = chunk_index_to_coordinates(i);
+        let cells_per_chunk = 16 / BIOME_CELL_SIZE;
+        columns.iter().enumerate().for_each(|(column, biome)| {
+            let column = column as u32;
+            biomes.set_column(
+                chunk_local_x as u32 * cells_per_chunk + column % cells_per_chunk,
+                chunk_local_z as u32 * cells_per_chunk + column / cells_per_chunk,
+                biome,
+            );
+        });
+    });
+
     let coords_and_sections = chunks
         .iter()
         .enumerate()
@@ -430,7 +1254,50 @@ pub fn build_region_octree(
 
     println!("time to build region tree:{:?}", end.duration_since(start));
 
-    tree
+    tree.map(|tree| (tree, biomes))
+}
+
+///Blocks the surface biome is looked for above
+fn is_air(mapped_state: &str) -> bool {
+    let name = mapped_state
+        .split_once('#')
+        .map_or(mapped_state, |(name, _)| name);
+    matches!(
+        name,
+        "minecraft:air" | "minecraft:cave_air" | "minecraft:void_air"
+    )
+}
+
+///Biomes of the top layer of cells of the highest section in a chunk that isn't all air, as 4x4
+///columns with x first. Cave biomes further down are ignored
+fn surface_biomes(chunk: &Chunk<'_>) -> Option<Vec<String>> {
+    let sections = chunk.get_section_tower()?;
+    let surface = sections
+        .iter_sections()
+        .filter(|section| {
+            (LOWEST_SECTION_INDEX..HIGHEST_SECTION_INDEX + 1).contains(&section.get_y_index())
+        })
+        .filter(|section| {
+            section
+                .get_palette()
+                .iter()
+                .any(|blockstate| !is_air(&blockstate.to_mapped_state().to_str()))
+        })
+        .max_by_key(|section| section.get_y_index())?;
+
+    //cells are stored y first, then z, then x
+    let cells_per_layer = (16 / BIOME_CELL_SIZE) * (16 / BIOME_CELL_SIZE);
+    let biome_palette = surface.get_biome_palette();
+    let columns = surface
+        .iter_biome_indices()
+        .skip((cells_per_layer * (16 / BIOME_CELL_SIZE - 1)) as usize)
+        .map(|index| {
+            biome_palette
+                .get(index as usize)
+                .map(|biome| biome.to_str().to_string())
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (columns.len() == cells_per_layer as usize).then_some(columns)
 }
 
 fn chunk_index_to_coordinates(i: usize) -> (u8, u8) {
@@ -488,12 +1355,18 @@ impl RegionOctreeBuilder {
                     root: Some(0),
                     octants,
                     depth: 9,
+                    lod_values: Vec::new(),
+                    free_list: Vec::new(),
+                    shared: false,
                 })
             }
             RegionSubtreeResult::Octant(id) => Some(Octree {
                 root: Some(id),
                 octants: self.octants,
                 depth: REGION_OCTREE_DEPTH as u8,
+                lod_values: Vec::new(),
+                free_list: Vec::new(),
+                shared: false,
             }),
         }
     }
@@ -851,6 +1724,8 @@ fn section_index_to_block_coordinates(index: u16) -> (u16, u16, u16) {
 
 #[cfg(test)]
 mod test {
+    use crate::gpu_structs::gpu_octree::{gpu_data_to_octree, octree_to_gpu_data};
+
     use super::*;
 
     #[test]
@@ -862,6 +1737,292 @@ mod test {
     pub fn section_test() {
         construct_all();
     }
+    #[test]
+    pub fn subtrees_are_placed_on_grid() {
+        let leaf_tree = |value: u32| {
+            let mut tree = Octree::default();
+            let root = tree.new_octant();
+            tree.octants[root as usize].set_child(ChildType::Leaf, value, 0);
+            tree.root = Some(root);
+            tree.depth = 1;
+            tree
+        };
+
+        let tree = Octree::from_subtrees(
+            3,
+            1,
+            vec![
+                (UVec3::new(0, 0, 0), leaf_tree(1)),
+                (UVec3::new(3, 0, 2), leaf_tree(2)),
+            ],
+        );
+
+        assert_eq!(tree.depth(), 3);
+        let octants = tree.octants_slice();
+        let root = &octants[tree.root().unwrap() as usize];
+
+        let (child_type, first) = root.get_child(0);
+        assert_eq!(child_type, ChildType::Octant);
+        let (child_type, first) = octants[first as usize].get_child(0);
+        assert_eq!(child_type, ChildType::Octant);
+        assert_eq!(octants[first as usize].get_child(0), (ChildType::Leaf, 1));
+
+        //(3, 0, 2) is x = 0b11, z = 0b10
+        let (child_type, second) = root.get_child(0b101);
+        assert_eq!(child_type, ChildType::Octant);
+        let (child_type, second) = octants[second as usize].get_child(0b001);
+        assert_eq!(child_type, ChildType::Octant);
+        assert_eq!(octants[second as usize].get_child(0), (ChildType::Leaf, 2));
+    }
+
+    #[test]
+    pub fn identical_subtrees_are_shared() {
+        let mut tree = Octree::default();
+        let leaves = |tree: &mut Octree, garbage: u32| {
+            let id = tree.new_octant();
+            let octant = &mut tree.octants[id as usize];
+            octant.set_child(ChildType::Leaf, 5, 0);
+            octant.set_child(ChildType::Leaf, 6, 7);
+            //data left behind in an empty slot doesn't make octants different
+            octant.overwrite_child(ChildType::Empty, garbage, 3);
+            id
+        };
+        let first = leaves(&mut tree, 0);
+        let copy = leaves(&mut tree, 99);
+        //the same leaves one level further down cover less space and stay separate
+        let lower = leaves(&mut tree, 0);
+        let middle = tree.new_octant();
+        tree.octants[middle as usize].set_child(ChildType::Octant, lower, 2);
+        let _unreachable = leaves(&mut tree, 0);
+        let root = tree.new_octant();
+        tree.octants[root as usize].set_child(ChildType::Octant, first, 0);
+        tree.octants[root as usize].set_child(ChildType::Octant, middle, 1);
+        tree.octants[root as usize].set_child(ChildType::Octant, copy, 4);
+        tree.root = Some(root);
+        tree.depth = 3;
+
+        let mut leaves_before = Vec::new();
+        tree.for_each_leaf(|position, size, value| leaves_before.push((position, size, value)));
+
+        assert_eq!(tree.deduplicate(), 2);
+        assert_eq!(tree.octants_slice().len(), 4);
+        let root = &tree.octants_slice()[tree.root().unwrap() as usize];
+        assert_eq!(root.get_child(0), root.get_child(4));
+        let mut deduplicated = Octree::from_parts(tree.root(), tree.octants.clone(), 3);
+        assert_eq!(deduplicated.deduplicate(), 0);
+
+        let mut leaves_after = Vec::new();
+        tree.for_each_leaf(|position, size, value| leaves_after.push((position, size, value)));
+        leaves_before.sort_by_key(|&(position, size, _)| (position.to_array(), size));
+        leaves_after.sort_by_key(|&(position, size, _)| (position.to_array(), size));
+        assert_eq!(leaves_before, leaves_after);
+    }
+
+    #[test]
+    pub fn lod_values_follow_the_majority() {
+        let mut tree = Octree::default();
+        let mostly_ones = tree.new_octant();
+        tree.octants[mostly_ones as usize]
+            .init_children_with(|index| (ChildType::Leaf, if index < 6 { 1 } else { 2 }));
+        let sparse = tree.new_octant();
+        tree.octants[sparse as usize].set_child(ChildType::Leaf, 3, 0);
+        let root = tree.new_octant();
+        tree.octants[root as usize].set_child(ChildType::Octant, mostly_ones, 0);
+        tree.octants[root as usize].set_child(ChildType::Octant, sparse, 1);
+        (2..6).for_each(|index| {
+            tree.octants[root as usize].set_child(ChildType::Leaf, 2, index);
+        });
+        tree.root = Some(root);
+        tree.depth = 2;
+
+        assert_eq!(tree.lod_value(root), None);
+        tree.compute_lod_values();
+        assert_eq!(tree.lod_value(mostly_ones), Some(1));
+        //an eighth full is too thin to stand in for
+        assert_eq!(tree.lod_value(sparse), None);
+        //four full children of 2 outweigh the one child that is mostly 1
+        assert_eq!(tree.lod_value(root), Some(2));
+
+        tree.remap_leaves(|value| (value != 1).then_some(value * 10));
+        assert_eq!(tree.lod_value(mostly_ones), None);
+        assert_eq!(tree.lod_value(root), Some(20));
+
+        tree.deduplicate();
+        assert_eq!(tree.lod_value(tree.root().unwrap()), None);
+    }
+
+    #[test]
+    pub fn voxels_can_be_set_and_cleared() {
+        let mut tree = Octree::default();
+        assert_eq!(tree.set_voxel(UVec3::new(5, 2, 7), 3), None);
+        assert_eq!(tree.depth(), 3);
+        assert_eq!(tree.get_voxel(UVec3::new(5, 2, 7)), Some(3));
+        assert_eq!(tree.get_voxel(UVec3::new(5, 2, 6)), None);
+        assert_eq!(tree.get_voxel(UVec3::new(50, 2, 6)), None);
+        assert_eq!(tree.set_voxel(UVec3::new(5, 2, 7), 4), Some(3));
+
+        //filling the whole tree compacts it down to a root of eight leaves
+        tree.fill_box(UVec3::ZERO, UVec3::splat(7), 1);
+        let root = &tree.octants_slice()[tree.root().unwrap() as usize];
+        assert!(root.is_compactable() && !root.is_empty());
+        assert_eq!(root.get_child(3), (ChildType::Leaf, 1));
+
+        //writing into a compacted leaf splits it
+        tree.set_voxel(UVec3::new(1, 1, 1), 2);
+        assert_eq!(tree.get_voxel(UVec3::new(1, 1, 1)), Some(2));
+        assert_eq!(tree.get_voxel(UVec3::new(1, 1, 0)), Some(1));
+        assert_eq!(tree.get_voxel(UVec3::new(6, 6, 6)), Some(1));
+        assert_eq!(tree.remove_voxel(UVec3::new(1, 1, 1)), Some(2));
+        assert_eq!(tree.remove_voxel(UVec3::new(1, 1, 1)), None);
+
+        tree.clear_box(UVec3::ZERO, UVec3::splat(7));
+        assert_eq!(tree.root(), None);
+        tree.for_each_leaf(|_, _, _| panic!("the tree should be empty"));
+    }
+
+    #[test]
+    pub fn cleared_trees_can_be_uploaded() {
+        let mut tree = Octree::default();
+        tree.fill_box(UVec3::new(1, 2, 3), UVec3::new(4, 5, 6), 9);
+        tree.clear_box(UVec3::ZERO, UVec3::splat(7));
+        assert_eq!(tree.root(), None);
+
+        let (uniform, nodes) = octree_to_gpu_data(&tree);
+        let root = nodes[uniform.root as usize].decode();
+        assert!(root.is_empty());
+        assert_eq!(gpu_data_to_octree(&uniform, &nodes).root(), None);
+    }
+
+    #[test]
+    pub fn boxes_past_the_largest_tree_are_rejected() {
+        let mut tree = Octree::default();
+        assert!(!tree.fill_box(UVec3::ZERO, UVec3::MAX, 1));
+        assert!(!tree.fill_box(UVec3::ZERO, UVec3::new(0, 1 << 31, 0), 1));
+        assert_eq!(tree.set_voxel(UVec3::MAX, 1), None);
+        assert_eq!(tree.root(), None);
+
+        assert!(tree.fill_box(UVec3::ZERO, UVec3::ONE, 1));
+        assert_eq!(tree.depth(), 1);
+    }
+
+    #[test]
+    pub fn edits_reuse_freed_octants() {
+        let mut tree = Octree::default();
+        tree.set_voxel(UVec3::new(15, 0, 0), 1);
+        tree.set_voxel(UVec3::new(3, 9, 12), 2);
+        tree.set_voxel(UVec3::new(0, 7, 1), 3);
+        tree.remove_voxel(UVec3::new(0, 7, 1));
+        let octants = tree.octants_slice().len();
+        (1..10).for_each(|i| {
+            tree.set_voxel(UVec3::new(i, 7, 1), i);
+            tree.remove_voxel(UVec3::new(i, 7, 1));
+        });
+        assert_eq!(tree.octants_slice().len(), octants);
+        assert_eq!(tree.get_voxel(UVec3::new(3, 9, 12)), Some(2));
+
+        //a fully overwritten subtree is freed along with everything below it
+        tree.set_voxel(UVec3::new(1, 1, 1), 4);
+        tree.fill_box(UVec3::ZERO, UVec3::splat(7), 5);
+        tree.set_voxel(UVec3::new(12, 12, 12), 6);
+        assert_eq!(tree.octants_slice().len(), octants);
+        let freed = tree.free_list.len();
+        assert_eq!(tree.deduplicate(), freed);
+        assert_eq!(tree.get_voxel(UVec3::new(1, 1, 1)), Some(5));
+        assert_eq!(tree.get_voxel(UVec3::new(12, 12, 12)), Some(6));
+    }
+
+    #[test]
+    pub fn edits_leave_shared_subtrees_alone() {
+        let mut tree = Octree::default();
+        tree.set_voxel(UVec3::new(1, 0, 0), 7);
+        tree.set_voxel(UVec3::new(5, 0, 0), 7);
+        tree.deduplicate();
+        let root = &tree.octants_slice()[tree.root().unwrap() as usize];
+        assert_eq!(root.get_child(0), root.get_child(1));
+
+        tree.set_voxel(UVec3::new(0, 0, 0), 8);
+        assert_eq!(tree.get_voxel(UVec3::new(0, 0, 0)), Some(8));
+        assert_eq!(tree.get_voxel(UVec3::new(4, 0, 0)), None);
+        assert_eq!(tree.get_voxel(UVec3::new(5, 0, 0)), Some(7));
+        tree.remove_voxel(UVec3::new(5, 0, 0));
+        assert_eq!(tree.get_voxel(UVec3::new(1, 0, 0)), Some(7));
+    }
+
+    #[test]
+    pub fn box_queries_find_overlapping_leaves() {
+        let mut tree = Octree::default();
+        tree.fill_box(UVec3::ZERO, UVec3::splat(3), 1);
+        tree.set_voxel(UVec3::new(6, 6, 6), 2);
+        tree.set_voxel(UVec3::new(4, 0, 0), 3);
+
+        let leaves: Vec<OctreeLeaf> = tree.leaves().collect();
+        assert_eq!(leaves.len(), 3);
+        //the filled corner stays one compacted leaf
+        assert_eq!(
+            leaves[0],
+            OctreeLeaf {
+                position: UVec3::ZERO,
+                size: 4,
+                value: 1,
+            }
+        );
+        assert_eq!(leaves.iter().map(OctreeLeaf::volume).sum::<u64>(), 66);
+        assert_eq!(leaves[0].aabb().max, glam::Vec3A::splat(4.0));
+
+        let values = |min: UVec3, max: UVec3| {
+            let mut values: Vec<u32> = tree.query_box(min, max).map(|leaf| leaf.value).collect();
+            values.sort();
+            values
+        };
+        assert_eq!(values(UVec3::splat(3), UVec3::splat(4)), [1]);
+        assert_eq!(values(UVec3::new(3, 0, 0), UVec3::new(6, 6, 6)), [1, 2, 3]);
+        assert_eq!(
+            values(UVec3::new(5, 0, 0), UVec3::new(7, 5, 5)),
+            Vec::<u32>::new()
+        );
+        assert_eq!(values(UVec3::splat(6), UVec3::MAX), [2]);
+        assert_eq!(Octree::default().leaves().count(), 0);
+    }
+
+    #[test]
+    pub fn stats_count_stored_leaves() {
+        assert_eq!(Octree::default().stats().leaves(), 0);
+
+        let mut tree = Octree::default();
+        tree.fill_box(UVec3::ZERO, UVec3::splat(3), 1);
+        tree.set_voxel(UVec3::new(6, 6, 6), 2);
+        tree.set_voxel(UVec3::new(6, 6, 7), 2);
+        tree.set_voxel(UVec3::new(4, 0, 0), 3);
+        tree.remove_voxel(UVec3::new(4, 0, 0));
+
+        let stats = tree.stats();
+        assert_eq!(stats.leaves_per_depth, [0, 1, 0, 2]);
+        assert_eq!(stats.leaves(), 3);
+        assert_eq!(stats.compacted_leaves, 1);
+        assert_eq!(stats.value_histogram, [(2, 2), (1, 1)]);
+        assert_eq!(stats.reachable_octants, 3);
+        assert_eq!(stats.octants, stats.reachable_octants + stats.free_octants);
+        assert_eq!(
+            stats.gpu_bytes,
+            stats.octants * (size_of::<GPUOctreeNode>() + size_of::<u32>())
+        );
+        assert!(stats.cpu_bytes >= stats.octants * size_of::<Octant>());
+    }
+
+    #[test]
+    pub fn stats_of_a_single_leaf_tree() {
+        let mut root = Octant::default();
+        root.init_children_with(|_| (ChildType::Leaf, 1));
+        //a depth 0 tree is a single voxel, so like query_box this doesn't look into the root
+        let stats = Octree::from_parts(Some(0), vec![root], 0).stats();
+        assert_eq!(stats.leaves_per_depth, [0]);
+        assert_eq!(stats.reachable_octants, 0);
+        assert_eq!(stats.octants, 1);
+
+        let stats = Octree::default().stats();
+        assert_eq!(stats.gpu_bytes, size_of::<u32>());
+    }
+
     #[test]
     pub fn morton_code_bit_pattern() {
         let coord = (1, 0, 1);
@@ -0,0 +1,257 @@
+use glam::{UVec3, Vec3A};
+
+use super::new_octree::{ChildType, Octree};
+
+///The cell a ray walked into when [`Octree::intersect`] stopped
+#[derive(Debug, Clone, PartialEq)]
+pub struct OctreeHit {
+    ///Distance along the ray from its origin
+    pub t: f32,
+    ///Point on the face of the cell the ray entered through, in octree space
+    pub position: Vec3A,
+    ///Normal of that face, pointing back towards the ray
+    pub normal: Vec3A,
+    ///Value of the leaf the cell belongs to, `None` for empty space
+    pub value: Option<u32>,
+    ///Lowest corner of the cell
+    pub cell: UVec3,
+    ///Side length of the cell, leaves can cover more than one block
+    pub size: u32,
+}
+
+impl Octree {
+    ///Walks the ray `origin + t * direction` through the tree and returns the first cell it enters
+    ///for which `stop` returns true. The cell holding the origin is never tested, the ray is
+    ///already inside it. Shared octants are entered through whichever parent the ray is in, so
+    ///this works on deduplicated trees too
+    pub fn intersect<F: FnMut(&OctreeHit) -> bool>(
+        &self,
+        origin: Vec3A,
+        direction: Vec3A,
+        mut stop: F,
+    ) -> Option<OctreeHit> {
+        let root = self.root()?;
+        if self.depth() == 0 {
+            return None;
+        }
+        let size = (1u64 << self.depth()) as f32;
+
+        let (t_enter, t_exit) = clip_to_cube(origin, direction, size)?;
+        let mut t = t_enter.max(0.0);
+        if t_exit <= t {
+            return None;
+        }
+        let mut position = origin + direction * t;
+        let mut normal = Vec3A::ZERO;
+        //rays starting outside the tree have to be tested on the cell they enter first
+        let mut test_cell = t_enter > 0.0;
+        if test_cell {
+            let axis = entry_axis(origin, direction, size);
+            position[axis] = if direction[axis] > 0.0 { 0.0 } else { size };
+            normal[axis] = -direction[axis].signum();
+        }
+
+        loop {
+            let cell = cell_at(position, direction, size)?;
+            let (value, cell_origin, cell_size) = self.locate(root, cell);
+            if test_cell {
+                let hit = OctreeHit {
+                    t,
+                    position,
+                    normal,
+                    value,
+                    cell: cell_origin,
+                    size: cell_size,
+                };
+                if stop(&hit) {
+                    return Some(hit);
+                }
+            }
+            test_cell = true;
+
+            //leave the cell through the face the ray reaches first
+            let cell_min = cell_origin.as_vec3a();
+            let cell_max = cell_min + cell_size as f32;
+            let boundary = Vec3A::select(direction.cmpgt(Vec3A::ZERO), cell_max, cell_min);
+            let mut exits = Vec3A::INFINITY;
+            (0..3).for_each(|axis| {
+                if direction[axis] != 0.0 {
+                    exits[axis] = (boundary[axis] - origin[axis]) / direction[axis];
+                }
+            });
+            let t_next = exits.min_element();
+
+            let previous = position;
+            position = origin + direction * t_next;
+            normal = Vec3A::ZERO;
+            (0..3).for_each(|axis| {
+                if exits[axis] == t_next {
+                    position[axis] = boundary[axis];
+                    normal[axis] = -direction[axis].signum();
+                } else if direction[axis] > 0.0 {
+                    //rounding must never move the ray back into a cell it already left
+                    position[axis] = position[axis].max(previous[axis]);
+                } else if direction[axis] < 0.0 {
+                    position[axis] = position[axis].min(previous[axis]);
+                }
+            });
+            t = t_next.max(t);
+        }
+    }
+
+    ///Descends from `root` to the largest node holding `cell` and returns its value, lowest
+    ///corner and side length
+    fn locate(&self, root: u32, cell: UVec3) -> (Option<u32>, UVec3, u32) {
+        let octants = self.octants_slice();
+        let mut octant = root;
+        let mut origin = UVec3::ZERO;
+        let mut size = 1u32 << self.depth();
+        loop {
+            size /= 2;
+            let offset = (cell - origin).cmpge(UVec3::splat(size));
+            let index = offset.bitmask() as u8;
+            origin += UVec3::select(offset, UVec3::splat(size), UVec3::ZERO);
+            match octants[octant as usize].get_child(index) {
+                (ChildType::Octant, child) => octant = child,
+                (ChildType::Leaf, value) => return (Some(value), origin, size),
+                (ChildType::Empty, _) => return (None, origin, size),
+            }
+        }
+    }
+}
+
+///Distances at which the ray enters and leaves the cube from the origin to `size`
+fn clip_to_cube(origin: Vec3A, direction: Vec3A, size: f32) -> Option<(f32, f32)> {
+    let mut t_enter = f32::NEG_INFINITY;
+    let mut t_exit = f32::INFINITY;
+    for axis in 0..3 {
+        if direction[axis] == 0.0 {
+            if origin[axis] < 0.0 || origin[axis] >= size {
+                return None;
+            }
+            continue;
+        }
+        let a = -origin[axis] / direction[axis];
+        let b = (size - origin[axis]) / direction[axis];
+        t_enter = t_enter.max(a.min(b));
+        t_exit = t_exit.min(a.max(b));
+    }
+    (t_enter <= t_exit).then_some((t_enter, t_exit))
+}
+
+///Axis of the face a ray starting outside the cube enters it through
+fn entry_axis(origin: Vec3A, direction: Vec3A, size: f32) -> usize {
+    let mut entries = Vec3A::NEG_INFINITY;
+    (0..3).for_each(|axis| {
+        if direction[axis] != 0.0 {
+            let face = if direction[axis] > 0.0 { 0.0 } else { size };
+            entries[axis] = (face - origin[axis]) / direction[axis];
+        }
+    });
+    let t = entries.max_element();
+    (0..3).find(|&axis| entries[axis] == t).unwrap_or(0)
+}
+
+///The smallest cell of the tree a ray at `position` moves through next. Points on a face between
+///two cells belong to the one the ray is heading into
+pub(crate) fn cell_at(position: Vec3A, direction: Vec3A, size: f32) -> Option<UVec3> {
+    let mut cell = position.floor();
+    (0..3).for_each(|axis| {
+        if direction[axis] < 0.0 && cell[axis] == position[axis] {
+            cell[axis] -= 1.0;
+        }
+    });
+    if cell.cmplt(Vec3A::ZERO).any() || cell.cmpge(Vec3A::splat(size)).any() {
+        return None;
+    }
+    Some(cell.as_uvec3())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::octree::new_octree::Octant;
+
+    use super::*;
+
+    ///A depth 2 tree with a single block of value 7 at (2, 1, 1)
+    fn single_block() -> Octree {
+        let mut tree = Octree::default();
+        tree.set_voxel(UVec3::new(2, 1, 1), 7);
+        tree.expand_to(2);
+        tree
+    }
+
+    #[test]
+    pub fn rays_stop_at_the_first_block() {
+        let tree = single_block();
+        let hit = tree
+            .intersect(Vec3A::new(-3.0, 1.5, 1.5), Vec3A::X, |hit| {
+                hit.value.is_some()
+            })
+            .unwrap();
+        assert_eq!(hit.t, 5.0);
+        assert_eq!(hit.position, Vec3A::new(2.0, 1.5, 1.5));
+        assert_eq!(hit.normal, Vec3A::NEG_X);
+        assert_eq!(hit.value, Some(7));
+        assert_eq!((hit.cell, hit.size), (UVec3::new(2, 1, 1), 1));
+
+        let hit = tree
+            .intersect(Vec3A::new(2.5, 3.5, 1.5), Vec3A::NEG_Y, |hit| {
+                hit.value.is_some()
+            })
+            .unwrap();
+        assert_eq!(hit.t, 1.5);
+        assert_eq!(hit.normal, Vec3A::Y);
+        //the ray leaves the block through its bottom face
+        let hit = tree
+            .intersect(hit.position, Vec3A::NEG_Y, |hit| hit.value.is_none())
+            .unwrap();
+        assert_eq!(hit.position, Vec3A::new(2.5, 1.0, 1.5));
+        assert_eq!(hit.normal, Vec3A::Y);
+    }
+
+    #[test]
+    pub fn rays_can_miss() {
+        let tree = single_block();
+        let miss = tree.intersect(Vec3A::new(-3.0, 2.5, 1.5), Vec3A::X, |hit| {
+            hit.value.is_some()
+        });
+        assert_eq!(miss, None);
+        let miss = tree.intersect(Vec3A::new(-3.0, 1.5, 1.5), Vec3A::NEG_X, |hit| {
+            hit.value.is_some()
+        });
+        assert_eq!(miss, None);
+        let diagonal = Vec3A::new(1.0, 1.0, 1.0).normalize();
+        let hit = tree.intersect(Vec3A::new(0.25, 1.25, 0.25), diagonal, |hit| {
+            hit.value.is_some()
+        });
+        assert_eq!(hit, None);
+    }
+
+    #[test]
+    pub fn shared_octants_are_walked_through_every_parent() {
+        let mut octant = Octant::default();
+        octant.init_children_with(|index| match index {
+            0 => (ChildType::Leaf, 3),
+            _ => (ChildType::Empty, 0),
+        });
+        let mut root = Octant::default();
+        root.init_children_with(|index| match index {
+            0 | 1 => (ChildType::Octant, 1),
+            _ => (ChildType::Empty, 0),
+        });
+        let tree = Octree::from_parts(Some(0), vec![root, octant], 2);
+
+        let first = tree
+            .intersect(Vec3A::new(-1.0, 0.5, 0.5), Vec3A::X, |hit| {
+                hit.value.is_some()
+            })
+            .unwrap();
+        assert_eq!(first.position, Vec3A::new(0.0, 0.5, 0.5));
+        let second = tree
+            .intersect(first.position, Vec3A::X, |hit| hit.value.is_some())
+            .unwrap();
+        assert_eq!(second.position, Vec3A::new(2.0, 0.5, 0.5));
+        assert_eq!(second.value, Some(3));
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use super::{
+    biome_map::BiomeMap,
+    new_octree::{ChildType, Octant, OctantId, Octree},
+};
+
+const MAGIC: [u8; 4] = *b"OCTC";
+pub const OCTREE_CACHE_VERSION: u32 = 2;
+const NO_ROOT: u32 = u32::MAX;
+///Longer strings can only come from a corrupted file, block states and biome names are far shorter
+const MAX_STRING_LENGTH: usize = 1 << 16;
+
+///Identifies one of the files a cached octree was built from, so a stale cache can be detected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceStamp {
+    pub name: String,
+    pub length: u64,
+    pub modified: u64,
+}
+
+impl SourceStamp {
+    ///Returns `None` if the file doesn't exist
+    pub fn for_file(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or(0);
+        Some(Self {
+            name: path.file_name()?.to_string_lossy().into_owned(),
+            length: metadata.len(),
+            modified,
+        })
+    }
+}
+
+///A built octree together with the blockstate palette its leaves index into
+///LOD values are not stored, they depend on what the leaves are remapped to after loading and are
+///recomputed with [`Octree::compute_lod_values`]
+#[derive(Default)]
+pub struct OctreeCache {
+    pub octree: Octree,
+    pub palette: Vec<(String, u32)>,
+    pub sources: Vec<SourceStamp>,
+    pub biomes: BiomeMap,
+}
+
+impl OctreeCache {
+    ///Layout, all integers little endian:
+    ///magic, version: u32, depth: u8, root: u32 (u32::MAX for none), octant count: u64,
+    ///octants as child mask: u16 followed by 8 children: u32, palette length: u32,
+    ///palette entries as id: u32 and a string, source count: u32,
+    ///sources as a string, length: u64 and modified: u64, biome columns per side: u32, biome
+    ///palette length: u32, biome names as strings, biome columns as u16 palette indices.
+    ///Strings are stored as a u32 byte length followed by utf-8 bytes
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        write_u32(writer, OCTREE_CACHE_VERSION)?;
+        writer.write_all(&[self.octree.depth()])?;
+        write_u32(writer, self.octree.root().unwrap_or(NO_ROOT))?;
+
+        let octants = self.octree.octants_slice();
+        writer.write_all(&(octants.len() as u64).to_le_bytes())?;
+        for octant in octants {
+            let mut child_mask = 0u16;
+            let mut children = [0u32; 8];
+            (0..8u8).for_each(|index| {
+                let (child_type, data) = octant.get_child(index);
+                match child_type {
+                    ChildType::Empty => {}
+                    ChildType::Leaf => child_mask |= (1 << index) | (1 << (index + 8)),
+                    ChildType::Octant => child_mask |= 1 << index,
+                }
+                children[index as usize] = data;
+            });
+            writer.write_all(&child_mask.to_le_bytes())?;
+            for child in children {
+                write_u32(writer, child)?;
+            }
+        }
+
+        write_u32(writer, self.palette.len() as u32)?;
+        for (state, id) in &self.palette {
+            write_u32(writer, *id)?;
+            write_string(writer, state)?;
+        }
+
+        write_u32(writer, self.sources.len() as u32)?;
+        for source in &self.sources {
+            write_string(writer, &source.name)?;
+            writer.write_all(&source.length.to_le_bytes())?;
+            writer.write_all(&source.modified.to_le_bytes())?;
+        }
+
+        write_u32(writer, self.biomes.cells_per_side())?;
+        write_u32(writer, self.biomes.palette().len() as u32)?;
+        for biome in self.biomes.palette() {
+            write_string(writer, biome)?;
+        }
+        for cell in self.biomes.cells() {
+            writer.write_all(&cell.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        if read_array::<4, _>(reader)? != MAGIC {
+            return Err(invalid_data("not an octree cache file"));
+        }
+        let version = read_u32(reader)?;
+        if version != OCTREE_CACHE_VERSION {
+            return Err(invalid_data(format!(
+                "octree cache version {version} does not match {OCTREE_CACHE_VERSION}"
+            )));
+        }
+        let [depth] = read_array::<1, _>(reader)?;
+        let root = match read_u32(reader)? {
+            NO_ROOT => None,
+            root => Some(root as OctantId),
+        };
+
+        let octant_count = u64::from_le_bytes(read_array(reader)?) as usize;
+        let mut octants = Vec::with_capacity(octant_count.min(1 << 24));
+        for _ in 0..octant_count {
+            let child_mask = u16::from_le_bytes(read_array(reader)?);
+            let mut children = [0u32; 8];
+            for child in children.iter_mut() {
+                *child = read_u32(reader)?;
+            }
+            let mut octant = Octant::default();
+            octant.init_children_with(|index| {
+                let data = children[index as usize];
+                if child_mask & (1 << index) == 0 {
+                    (ChildType::Empty, 0)
+                } else if child_mask & (1 << (index + 8)) != 0 {
+                    (ChildType::Leaf, data)
+                } else {
+                    (ChildType::Octant, data)
+                }
+            });
+            octants.push(octant);
+        }
+
+        let out_of_bounds = |id: u32| id as usize >= octant_count;
+        if root.is_some_and(out_of_bounds)
+            || octants.iter().any(|octant| {
+                octant
+                    .iter_children()
+                    .any(|(child_type, &id)| child_type == ChildType::Octant && out_of_bounds(id))
+            })
+        {
+            return Err(invalid_data("octant index out of bounds"));
+        }
+
+        let palette_length = read_u32(reader)?;
+        let palette = (0..palette_length)
+            .map(|_| {
+                let id = read_u32(reader)?;
+                Ok((read_string(reader)?, id))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let source_count = read_u32(reader)?;
+        let sources = (0..source_count)
+            .map(|_| {
+                Ok(SourceStamp {
+                    name: read_string(reader)?,
+                    length: u64::from_le_bytes(read_array(reader)?),
+                    modified: u64::from_le_bytes(read_array(reader)?),
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let cells_per_side = read_u32(reader)?;
+        let biome_palette_length = read_u32(reader)?;
+        let biome_palette = (0..biome_palette_length)
+            .map(|_| read_string(reader))
+            .collect::<io::Result<Vec<_>>>()?;
+        let cells = (0..cells_per_side as u64 * cells_per_side as u64)
+            .map(|_| Ok(u16::from_le_bytes(read_array(reader)?)))
+            .collect::<io::Result<Box<[_]>>>()?;
+        let biomes = BiomeMap::from_parts(cells_per_side, biome_palette, cells)
+            .ok_or_else(|| invalid_data("biome index out of bounds"))?;
+
+        Ok(Self {
+            octree: Octree::from_parts(root, octants, depth),
+            palette,
+            sources,
+            biomes,
+        })
+    }
+
+    ///Writes to a temporary file first, so an interrupted save never leaves a truncated cache
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temporary_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temporary_path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(temporary_path, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::read_from(&mut reader)
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_string<W: Write>(writer: &mut W, string: &str) -> io::Result<()> {
+    write_u32(writer, string.len() as u32)?;
+    writer.write_all(string.as_bytes())
+}
+
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(read_array(reader)?))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let length = read_u32(reader)? as usize;
+    if length > MAX_STRING_LENGTH {
+        return Err(invalid_data(format!("string of {length} bytes is too long")));
+    }
+    let mut bytes = vec![0u8; length];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(invalid_data)
+}
+
+#[cfg(test)]
+mod test {
+    use glam::UVec3;
+
+    use super::*;
+
+    #[test]
+    pub fn cache_round_trip() {
+        let mut root_octant = Octant::default();
+        root_octant.init_children_with(|index| match index {
+            0 => (ChildType::Leaf, 4),
+            5 => (ChildType::Leaf, 9),
+            _ => (ChildType::Empty, 0),
+        });
+        let tree = Octree::from_parts(Some(0), vec![root_octant], 1);
+        let tree = Octree::from_subtrees(3, 1, vec![(UVec3::new(1, 0, 3), tree)]);
+        let mut biomes = BiomeMap::new(3);
+        biomes.set_column(1, 0, "minecraft:plains");
+
+        let cache = OctreeCache {
+            octree: tree,
+            palette: vec![
+                ("minecraft:air#normal".to_string(), 0),
+                ("minecraft:stone#normal".to_string(), 4),
+            ],
+            sources: vec![SourceStamp {
+                name: "r.0.0.mca".to_string(),
+                length: 1234,
+                modified: 5678,
+            }],
+            biomes,
+        };
+
+        let mut bytes = Vec::new();
+        cache.write_to(&mut bytes).unwrap();
+        let read = OctreeCache::read_from(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(read.octree.depth(), cache.octree.depth());
+        assert_eq!(read.octree.root(), cache.octree.root());
+        assert_eq!(
+            read.octree.octants_slice().len(),
+            cache.octree.octants_slice().len()
+        );
+        read.octree
+            .octants_slice()
+            .iter()
+            .zip(cache.octree.octants_slice())
+            .for_each(|(read, original)| {
+                (0..8)
+                    .for_each(|index| assert_eq!(read.get_child(index), original.get_child(index)))
+            });
+        assert_eq!(read.palette, cache.palette);
+        assert_eq!(read.sources, cache.sources);
+        assert_eq!(read.biomes, cache.biomes);
+    }
+
+    #[test]
+    pub fn truncated_cache_is_rejected() {
+        let cache = OctreeCache::default();
+        let mut bytes = Vec::new();
+        cache.write_to(&mut bytes).unwrap();
+        bytes.pop();
+        assert!(OctreeCache::read_from(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    pub fn huge_strings_are_rejected() {
+        let mut bytes = Vec::new();
+        write_u32(&mut bytes, u32::MAX).unwrap();
+        let error = read_string(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -59,11 +59,14 @@ impl Ray {
                 u: self.hit.u,
                 v: self.hit.v,
                 current_material: self.hit.current_material,
+                face_material: self.hit.face_material,
                 normal: self.hit.normal,
                 previous_material: self.hit.previous_material,
                 color: Vec4::ZERO,
                 depth: self.hit.depth,
                 specular: self.hit.specular,
+                bsdf_pdf: 0.0,
+                throughput: self.hit.throughput,
             },
             inv_dir: self.inv_dir,
         }
@@ -121,11 +124,14 @@ impl Ray {
                 u: 0.0,
                 v: 0.0,
                 current_material: self.hit.current_material,
+                face_material: self.hit.face_material,
                 normal: self.hit.normal,
                 previous_material: self.hit.previous_material,
                 color: Vec4::ZERO,
                 depth: self.hit.depth,
                 specular: self.hit.specular,
+                bsdf_pdf: 0.0,
+                throughput: self.hit.throughput,
             },
             inv_dir: self.inv_dir,
         };
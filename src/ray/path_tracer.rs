@@ -1,17 +1,29 @@
 use core::f32;
-use std::hint::black_box;
+use std::{f32::consts::PI, hint::black_box};
 
-use rand::rngs::StdRng;
+use rand::{Rng, rngs::StdRng};
 
 use glam::{Vec3A, Vec4, Vec4Swizzles};
 
 use crate::{
     random_float,
     ray::Ray,
-    scene::{EmitterSamplingStrategy, Scene},
-    textures::material::{Material, MaterialFlags},
+    scene::{
+        EmitterSamplingStrategy, Scene,
+        emitters::Emitter,
+        resource_manager::MaterialID,
+        subsurface::{MAX_SUBSURFACE_STEPS, isotropic_direction, sample_free_path},
+        water::contains_water,
+    },
+    textures::{
+        lab_pbr::WET_DARKENING,
+        material::{Material, MaterialFlags},
+    },
 };
 
+///Model 0 is always air, the only material fog can be in
+pub(crate) const AIR: MaterialID = 0;
+
 pub fn path_trace(
     rng: &mut StdRng,
     scene: &Scene,
@@ -21,8 +33,50 @@ pub fn path_trace(
     branch_count: u32,
 ) -> bool {
     let mut hit: bool = false;
+
+    //russian roulette decides whether to continue the path that spawned this ray. The vertex it
+    //started from has already added its own emission
+    let survival = if ray.hit.depth >= scene.russian_roulette_depth {
+        ray.hit
+            .throughput
+            .max_element()
+            .clamp(Scene::MIN_SURVIVAL_PROBABILITY, 1.0)
+    } else {
+        1.0
+    };
+    if survival < 1.0 {
+        if random_float(rng) >= survival {
+            ray.hit.color = Vec4::new(0.0, 0.0, 0.0, 1.0);
+            return false;
+        }
+        ray.hit.throughput /= survival;
+    }
+
+    //light lost on its way through water to the origin of the ray
+    let mut water_transmittance = Vec3A::ONE;
     loop {
-        if !next_intersection(scene, ray) {
+        let segment_start = ray.origin;
+        let medium = ray.hit.current_material;
+        let intersected = next_intersection(scene, ray);
+        let length = segment_length(scene, segment_start, ray, intersected);
+        if contains_water(scene.get_material(medium)) {
+            let transmittance = scene.water.transmittance(length);
+            water_transmittance *= transmittance;
+            ray.hit.throughput *= transmittance;
+        }
+        if medium == AIR && scene.fog.is_enabled() {
+            let direction = *ray.get_direction();
+            if let Some(distance) = scene
+                .fog
+                .sample_distance(segment_start, direction, length, rng)
+            {
+                ray.origin = segment_start + direction * distance;
+                hit =
+                    do_fog_scattering(ray, first_reflection, rng, scene, attenuation, branch_count);
+                break;
+            }
+        }
+        if !intersected {
             if ray.hit.depth == 0 {
                 //direct sky hit
                 scene.get_sky_color_interp(ray);
@@ -30,6 +84,9 @@ pub fn path_trace(
             } else if ray.hit.specular {
                 scene.get_sky_color(ray, true);
                 hit = true;
+            } else if scene.sun_sampling_strategy.multiple_importance {
+                scene.get_sky_color_mis(ray);
+                hit = true;
             } else {
                 scene.get_sky_color_diffuse_sun(ray, scene.sun_sampling_strategy.diffuse_sun);
                 hit = true;
@@ -38,11 +95,33 @@ pub fn path_trace(
         }
         //println!("hit!");
 
-        let current_material = scene.get_material(ray.hit.current_material);
+        let current_material = scene.get_material(ray.hit.face_material);
+
+        //the sides of water blocks stay flat, only the surface has waves
+        if current_material
+            .material_flags
+            .contains(MaterialFlags::WATER)
+            && ray.hit.normal.y.abs() > 0.99
+        {
+            ray.hit.normal = scene.water.wave_normal(ray.origin) * ray.hit.normal.y.signum();
+        }
+        let (u, v) = (ray.hit.u.abs(), ray.hit.v.abs());
+        ray.hit.normal = current_material.normal_at(u, v, ray.hit.normal);
+        let surface = current_material.surface_at(u, v);
+
+        ray.hit.color *= scene
+            .tint_at(ray.origin, current_material.tint_index)
+            .extend(1.0);
 
         let prev_material = scene.get_material(ray.hit.previous_material);
 
-        let specular = current_material.specular;
+        //porous blocks soak up the water around them and look darker
+        if surface.porosity > 0.0 && contains_water(prev_material) {
+            let wet = 1.0 - WET_DARKENING * surface.porosity;
+            ray.hit.color *= Vec4::new(wet, wet, wet, 1.0);
+        }
+
+        let specular = surface.specular;
         let diffuse = ray.hit.color.w;
         let absorb = ray.hit.color.w;
 
@@ -53,15 +132,21 @@ pub fn path_trace(
             continue;
         }
 
-        if ray.hit.depth + 1 >= 5 {
+        if ray.hit.depth + 1 >= scene.max_depth {
             break;
         }
         ray.hit.depth += 1;
+        ray.hit.throughput *= Vec3A::from(ray.hit.color.xyz());
 
         let mut cumm_color = Vec4::splat(0.0);
         let mut next = Ray::default();
 
-        let metal = current_material.metalness;
+        let metal = surface.metalness;
+        //emissive blocks keep their emission on the surface rather than letting light in
+        let subsurface = current_material
+            .material_flags
+            .contains(MaterialFlags::SUBSURFACE_SCATTER)
+            && surface.emittance <= Ray::EPSILON;
 
         let count = if first_reflection { branch_count } else { 1 };
 
@@ -73,6 +158,7 @@ pub fn path_trace(
                     &mut next,
                     &mut cumm_color,
                     do_metal,
+                    surface.roughness,
                     rng,
                     scene,
                     attenuation,
@@ -80,11 +166,24 @@ pub fn path_trace(
                 );
             } else if random_float(rng) < diffuse {
                 //println!("diffuse");
+                if subsurface && random_float(rng) < scene.f_sub_surface {
+                    hit |= do_subsurface_scattering(
+                        ray,
+                        &mut next,
+                        &mut cumm_color,
+                        current_material,
+                        rng,
+                        scene,
+                        attenuation,
+                        branch_count,
+                    );
+                    continue;
+                }
                 hit |= do_diffuse_reflection(
                     ray,
                     &mut next,
                     &mut cumm_color,
-                    current_material,
+                    surface.emittance,
                     rng,
                     scene,
                     attenuation,
@@ -129,7 +228,11 @@ pub fn path_trace(
         if first_reflection {
             let air_distance = ray.distance_travelled;
         }
+    } else if survival < 1.0 {
+        let scale = 1.0 / survival;
+        ray.hit.color *= Vec4::new(scale, scale, scale, 1.0);
     }
+    ray.hit.color *= water_transmittance.extend(1.0);
 
     hit
 }
@@ -144,7 +247,6 @@ pub fn preview_render(rng: &mut StdRng, scene: &Scene, ray: &mut Ray, attenuatio
             black_box(());
             break;
         } else {
-            dbg!("s");
             ray.origin = ray.at(Ray::OFFSET);
         }
     }
@@ -153,6 +255,8 @@ pub fn preview_render(rng: &mut StdRng, scene: &Scene, ray: &mut Ray, attenuatio
         scene.get_sky_color_inner(ray);
         scene.add_sun_color(ray);
     } else {
+        let material = scene.get_material(ray.hit.face_material);
+        ray.hit.color *= scene.tint_at(ray.origin, material.tint_index).extend(1.0);
         scene.sun.flat_shading(ray);
     }
 }
@@ -162,6 +266,7 @@ pub fn do_specular_reflection(
     next: &mut Ray,
     cumulative_color: &mut Vec4,
     do_metal: bool,
+    roughness: f32,
     rng: &mut StdRng,
     scene: &Scene,
     attenuation: &mut Vec4,
@@ -169,7 +274,7 @@ pub fn do_specular_reflection(
 ) -> bool {
     println!("specular");
     let mut hit = false;
-    *next = ray.specular_reflection(scene.get_material(ray.hit.current_material).roughness, rng);
+    *next = ray.specular_reflection(roughness, rng);
 
     if path_trace(rng, scene, next, false, attenuation, current_spp) {
         if do_metal {
@@ -191,7 +296,7 @@ pub fn do_diffuse_reflection(
     ray: &mut Ray,
     next: &mut Ray,
     cumulative_color: &mut Vec4,
-    material: &Material,
+    emittance: f32,
     rng: &mut StdRng,
     scene: &Scene,
     attenuation: &mut Vec4,
@@ -199,31 +304,33 @@ pub fn do_diffuse_reflection(
 ) -> bool {
     let mut hit = false;
     let mut emmitance = Vec3A::splat(0.0);
-    let indirect_emmitter_color = Vec4::splat(0.0);
+    let mut indirect_emmitter_color = Vec4::splat(0.0);
+    let multiple_importance = scene.sun_sampling_strategy.multiple_importance;
+    let emitters_sampled = scene.emitter_sampling_strategy != EmitterSamplingStrategy::NONE;
+    let scatterer = Scatterer::Diffuse(ray.hit.normal);
     if scene.emitters_enabled
-        && (scene.emitter_sampling_strategy == EmitterSamplingStrategy::NONE || ray.hit.depth == 1)
-        && material.emittance > Ray::EPSILON
+        && (!emitters_sampled || ray.hit.depth == 1 || multiple_importance)
+        && emittance > Ray::EPSILON
     {
         emmitance = Vec3A::new(
             ray.hit.color.x * ray.hit.color.x,
             ray.hit.color.y * ray.hit.color.y,
             ray.hit.color.z * ray.hit.color.z,
         );
-        emmitance *= material.emittance;
+        emmitance *= emittance * scene.emmitter_intensity;
+        if emitters_sampled && ray.hit.depth > 1 {
+            emmitance *= emitter_hit_weight(scene, ray);
+        }
         hit = true
     } else if scene.emitters_enabled
         && scene.emitter_sampling_strategy != EmitterSamplingStrategy::NONE
     {
-        match scene.emitter_sampling_strategy {
-            EmitterSamplingStrategy::None { name, description } => {}
-            EmitterSamplingStrategy::All { name, description } => todo!(),
-            EmitterSamplingStrategy::One { name, description } => todo!(),
-            EmitterSamplingStrategy::OneBlock { name, description } => todo!(),
-        }
+        indirect_emmitter_color = sample_emitters(scene, ray, scatterer, rng);
     }
+    //bounces into the environment map are always weighted against this, so it can't be skipped
+    indirect_emmitter_color += sample_environment_light(scene, ray, scatterer, rng);
 
     if scene.sun_sampling_strategy.sun_sampling {
-        dbg!("huh?");
         *next = ray.new_from_self();
         scene.sun.get_random_sun_direction(next, rng);
 
@@ -231,18 +338,7 @@ pub fn do_diffuse_reflection(
         let mut direct_light_g = 0.0;
         let mut direct_light_b = 0.0;
 
-        let front_light = next.get_direction().dot(ray.hit.normal) > 0.0;
-
-        if front_light
-            || (material
-                .material_flags
-                .contains(MaterialFlags::SUBSURFACE_SCATTER)
-                && random_float(rng) < scene.f_sub_surface)
-        {
-            if !front_light {
-                next.origin += -Ray::OFFSET * ray.hit.normal;
-            }
-
+        if next.get_direction().dot(ray.hit.normal) > 0.0 {
             next.hit.current_material = next.hit.previous_material;
 
             get_direct_light_attenuation(scene, next, attenuation);
@@ -253,7 +349,13 @@ pub fn do_diffuse_reflection(
                 1.0
             };
             if attenuation.w > 0.0 {
-                let mult = next.get_direction().dot(ray.hit.normal).abs() * a;
+                let cos_theta = next.get_direction().dot(ray.hit.normal).abs();
+                let mut mult = cos_theta * a;
+                if scene.sun_sampling_strategy.multiple_importance {
+                    mult *= scene
+                        .mis_heuristic
+                        .weight(scene.sun.sample_pdf(), cos_theta / PI);
+                }
                 direct_light_r = attenuation.x * attenuation.w * mult;
                 direct_light_g = attenuation.y * attenuation.w * mult;
                 direct_light_b = attenuation.z * attenuation.w * mult;
@@ -261,6 +363,7 @@ pub fn do_diffuse_reflection(
             }
         }
         next.diffuse_reflection(ray, rng, scene);
+        next.hit.bsdf_pdf = next.get_direction().dot(ray.hit.normal).abs() / PI;
         hit = path_trace(rng, scene, next, false, attenuation, branch_count) || hit;
 
         if hit {
@@ -292,6 +395,7 @@ pub fn do_diffuse_reflection(
     } else {
         let ray_color = ray.hit.color;
         next.diffuse_reflection(ray, rng, scene);
+        next.hit.bsdf_pdf = next.get_direction().dot(ray.hit.normal).abs() / PI;
         hit = path_trace(rng, scene, next, false, attenuation, branch_count) || hit;
 
         if hit {
@@ -315,6 +419,138 @@ pub fn do_diffuse_reflection(
     hit
 }
 
+///Lets light wander into the subsurface scattering block `ray` hit. The random walk scatters off
+///the inside of the block until it leaves through one of its faces, where the light arriving at
+///that face is gathered like on a diffuse surface. It is tinted by the texture once and by the
+///material's subsurface colour at every scattering event
+pub fn do_subsurface_scattering(
+    ray: &Ray,
+    next: &mut Ray,
+    cumulative_color: &mut Vec4,
+    material: &Material,
+    rng: &mut StdRng,
+    scene: &Scene,
+    attenuation: &mut Vec4,
+    branch_count: u32,
+) -> bool {
+    let medium = ray.hit.current_material;
+    let mut walker = ray.new_from_self();
+    walker.hit.normal = -ray.hit.normal;
+    walker.scatter_normal(rng);
+
+    let mut tint = Vec3A::from(ray.hit.color.xyz());
+    for _ in 0..MAX_SUBSURFACE_STEPS {
+        let start = walker.origin;
+        walker.hit.current_material = medium;
+        if !next_intersection(scene, &mut walker) {
+            return false;
+        }
+        let distance = sample_free_path(material.mean_free_path, rng);
+        if distance < segment_length(scene, start, &walker, true) {
+            walker.origin = start + *walker.get_direction() * distance;
+            walker.set_direction(isotropic_direction(rng));
+            tint *= material.subsurface_color;
+            continue;
+        }
+
+        //continue as if the path had hit the face the walk left through from the outside
+        let normal = walker.hit.normal;
+        let outward = if normal.dot(*walker.get_direction()) < 0.0 {
+            -normal
+        } else {
+            normal
+        };
+        let mut exit = walker.new_from_self();
+        exit.set_direction(-outward);
+        exit.hit.normal = outward;
+        exit.hit.previous_material = walker.hit.current_material;
+        exit.hit.current_material = medium;
+        exit.hit.color = tint.extend(1.0);
+        return do_diffuse_reflection(
+            &mut exit,
+            next,
+            cumulative_color,
+            0.0,
+            rng,
+            scene,
+            attenuation,
+            branch_count,
+        );
+    }
+    //the light was absorbed before finding its way out
+    false
+}
+
+///Scatters `ray` off a fog particle at its origin, gathering the light reaching the particle
+///directly and continuing the path in a direction picked by the phase function
+pub fn do_fog_scattering(
+    ray: &mut Ray,
+    first_reflection: bool,
+    rng: &mut StdRng,
+    scene: &Scene,
+    attenuation: &mut Vec4,
+    branch_count: u32,
+) -> bool {
+    if ray.hit.depth + 1 >= scene.max_depth {
+        return false;
+    }
+    ray.hit.depth += 1;
+    ray.hit.specular = false;
+    ray.hit.previous_material = AIR;
+    ray.hit.current_material = AIR;
+    let albedo = scene.fog.albedo;
+    ray.hit.throughput *= albedo;
+
+    let incoming = *ray.get_direction();
+    let scatterer = Scatterer::Fog(incoming);
+    let emitters_sampled =
+        scene.emitters_enabled && scene.emitter_sampling_strategy != EmitterSamplingStrategy::NONE;
+    let mut hit = false;
+    let mut color = Vec3A::ZERO;
+    let count = if first_reflection { branch_count } else { 1 };
+    for _ in 0..count {
+        let mut direct_light = Vec4::ZERO;
+        if scene.sun_sampling_strategy.sun_sampling {
+            let mut shadow_ray = ray.new_from_self();
+            scene.sun.get_random_sun_direction(&mut shadow_ray, rng);
+            let phase = scene.fog.phase(incoming.dot(*shadow_ray.get_direction()));
+            get_direct_light_attenuation(scene, &mut shadow_ray, attenuation);
+            if attenuation.w > 0.0 {
+                let a = if scene.sun_sampling_strategy.sun_luminosity {
+                    scene.sun.luminosity_pdf
+                } else {
+                    1.0
+                };
+                //the sun's emittance is scaled for lambertian surfaces, which reflect 1 / pi of it
+                let mut mult = PI * phase * a * attenuation.w;
+                if scene.sun_sampling_strategy.multiple_importance {
+                    mult *= scene.mis_heuristic.weight(scene.sun.sample_pdf(), phase);
+                }
+                direct_light += *attenuation * scene.sun.emmittance * mult;
+            }
+        }
+        if emitters_sampled {
+            direct_light += sample_emitters(scene, ray, scatterer, rng);
+        }
+        direct_light += sample_environment_light(scene, ray, scatterer, rng);
+
+        let mut next = ray.new_from_self();
+        next.set_direction(scene.fog.sample_phase(incoming, rng));
+        next.hit.bsdf_pdf = scene.fog.phase(incoming.dot(*next.get_direction()));
+        let next_hit = path_trace(rng, scene, &mut next, false, attenuation, branch_count);
+
+        color += Vec3A::from(direct_light.xyz());
+        if next_hit {
+            color += Vec3A::from(next.hit.color.xyz());
+        }
+        hit |= next_hit || direct_light.xyz().max_element() > Ray::EPSILON;
+    }
+
+    let color = albedo * color / count as f32;
+    ray.hit.color = Vec4::new(color.x, color.y, color.z, 1.0);
+    hit
+}
+
 pub fn do_refraction(
     ray: &Ray,
     next: &mut Ray,
@@ -428,8 +664,8 @@ pub fn translucent_ray_color(
     cumulative_color: &mut Vec4,
     absorption: f32,
 ) {
-    //todo: implement fancy translucent ray color
-    let rgb_trans = Vec3A::from(ray.hit.color.xyz()) * absorption;
+    //like shadow rays, light is tinted by the opaque part of the surface and passes the rest
+    let rgb_trans = Vec3A::from(ray.hit.color.xyz()) * absorption + (1.0 - absorption);
 
     let output_color = Vec4::new(rgb_trans.x, rgb_trans.y, rgb_trans.z, 1.0) * next.hit.color;
 
@@ -455,11 +691,249 @@ pub fn next_intersection_preview(scene: &Scene, ray: &mut Ray) -> bool {
     false
 }
 
+///How a path vertex turns light arriving from a direction into light continuing along the path
+#[derive(Debug, Clone, Copy)]
+pub enum Scatterer {
+    ///A lambertian surface with this normal
+    Diffuse(Vec3A),
+    ///A fog particle hit by a ray travelling in this direction
+    Fog(Vec3A),
+}
+
+impl Scatterer {
+    ///Returns the fraction of the light from `direction` that is scattered along the path, not
+    ///including the albedo, and the solid angle pdf of the vertex picking `direction` itself.
+    ///`None` if no light from `direction` can be scattered
+    #[inline]
+    pub fn evaluate(&self, scene: &Scene, direction: Vec3A) -> Option<(f32, f32)> {
+        match *self {
+            Scatterer::Diffuse(normal) => {
+                let cos_theta = direction.dot(normal);
+                (cos_theta > 0.0).then_some((cos_theta / PI, cos_theta / PI))
+            }
+            Scatterer::Fog(incoming) => {
+                let phase = scene.fog.phase(incoming.dot(direction));
+                Some((phase, phase))
+            }
+        }
+    }
+}
+
+///Estimates the light reaching the hit point of `ray` directly from the emitters chosen by the
+///scene's emitter sampling strategy. Emitters hit by later bounces must not be counted again,
+///unless they are weighted with [`emitter_hit_weight`]
+pub fn sample_emitters(scene: &Scene, ray: &Ray, scatterer: Scatterer, rng: &mut StdRng) -> Vec4 {
+    let emitters = &scene.emitters;
+    if emitters.is_empty() {
+        return Vec4::ZERO;
+    }
+    let face_pdf = emitter_face_pdf(scene);
+    let mut color = Vec4::ZERO;
+    match scene.emitter_sampling_strategy {
+        EmitterSamplingStrategy::None { .. } => {}
+        EmitterSamplingStrategy::One { .. } => {
+            let emitter = &emitters[rng.random_range(0..emitters.len())];
+            let face = rng.random_range(0..Emitter::FACE_COUNT);
+            color += sample_emitter_face(scene, ray, scatterer, emitter, face, face_pdf, rng);
+        }
+        EmitterSamplingStrategy::OneBlock { .. } => {
+            let emitter = &emitters[rng.random_range(0..emitters.len())];
+            (0..Emitter::FACE_COUNT).for_each(|face| {
+                color += sample_emitter_face(scene, ray, scatterer, emitter, face, face_pdf, rng);
+            });
+        }
+        EmitterSamplingStrategy::All { .. } => {
+            emitters.iter().for_each(|emitter| {
+                (0..Emitter::FACE_COUNT).for_each(|face| {
+                    color +=
+                        sample_emitter_face(scene, ray, scatterer, emitter, face, face_pdf, rng);
+                });
+            });
+        }
+    }
+    color
+}
+
+///Chance of a given emitter face being picked by one call to [`sample_emitters`]
+pub fn emitter_face_pdf(scene: &Scene) -> f32 {
+    let emitter_count = scene.emitters.len().max(1) as f32;
+    match scene.emitter_sampling_strategy {
+        EmitterSamplingStrategy::None { .. } => 0.0,
+        EmitterSamplingStrategy::One { .. } => 1.0 / (emitter_count * Emitter::FACE_COUNT as f32),
+        EmitterSamplingStrategy::OneBlock { .. } => 1.0 / emitter_count,
+        EmitterSamplingStrategy::All { .. } => 1.0,
+    }
+}
+
+///Solid angle pdf of [`sample_emitters`] producing a direction that hits an emitter face
+///`distance` away at an angle of `cos_emitter` to its normal
+#[inline]
+fn emitter_light_pdf(face_pdf: f32, distance_squared: f32, cos_emitter: f32) -> f32 {
+    face_pdf * distance_squared / (Emitter::FACE_AREA * cos_emitter)
+}
+
+///Multiple importance sampling weight of an emitter that `ray` hit after a diffuse bounce. Without
+///multiple importance sampling such hits were already counted by [`sample_emitters`]
+pub fn emitter_hit_weight(scene: &Scene, ray: &Ray) -> f32 {
+    if !scene.sun_sampling_strategy.multiple_importance {
+        return 0.0;
+    }
+    if ray.hit.bsdf_pdf <= 0.0 {
+        return 1.0;
+    }
+    let cos_emitter = ray.get_direction().dot(ray.hit.normal).abs();
+    if cos_emitter <= 0.0 {
+        return 1.0;
+    }
+    let light_pdf = emitter_light_pdf(emitter_face_pdf(scene), ray.hit.t * ray.hit.t, cos_emitter);
+    scene.mis_heuristic.weight(ray.hit.bsdf_pdf, light_pdf)
+}
+
+///Samples a random point on one face of `emitter` and returns the light it sends to the hit point
+///of `ray`, already divided by the pdf of picking that point. `face_pdf` is the chance of the face
+///having been picked
+pub fn sample_emitter_face(
+    scene: &Scene,
+    ray: &Ray,
+    scatterer: Scatterer,
+    emitter: &Emitter,
+    face: usize,
+    face_pdf: f32,
+    rng: &mut StdRng,
+) -> Vec4 {
+    let u = random_float(rng);
+    let v = random_float(rng);
+    let (face_normal, point) = emitter.face_point(face, u, v);
+
+    let to_emitter = point - ray.origin;
+    let distance_squared = to_emitter.length_squared();
+    if distance_squared < Ray::EPSILON || face_pdf <= 0.0 {
+        return Vec4::ZERO;
+    }
+    let distance = distance_squared.sqrt();
+    let direction = to_emitter / distance;
+
+    let cos_emitter = -direction.dot(face_normal);
+    if cos_emitter <= 0.0 {
+        return Vec4::ZERO;
+    }
+    let Some((scattered, scatter_pdf)) = scatterer.evaluate(scene, direction) else {
+        return Vec4::ZERO;
+    };
+
+    let mut shadow_ray = ray.new_from_self();
+    shadow_ray.set_direction(direction);
+    shadow_ray.hit.current_material = shadow_ray.hit.previous_material;
+    let mut attenuation = Vec4::ZERO;
+    get_emitter_attenuation(scene, &mut shadow_ray, distance, &mut attenuation);
+    if attenuation.w <= 0.0 {
+        return Vec4::ZERO;
+    }
+
+    let light_pdf = emitter_light_pdf(face_pdf, distance_squared, cos_emitter);
+    let weight = if scene.sun_sampling_strategy.multiple_importance {
+        scene.mis_heuristic.weight(light_pdf, scatter_pdf)
+    } else {
+        1.0
+    };
+
+    let material = scene.get_material(emitter.material);
+    let emitter_color = material.texture.value(u, v, &point);
+    let scale = material.surface_at(u, v).emittance
+        * scene.emmitter_intensity
+        * attenuation.w
+        * weight
+        * scattered
+        / light_pdf;
+    Vec4::new(
+        emitter_color.x * emitter_color.x * attenuation.x * scale,
+        emitter_color.y * emitter_color.y * attenuation.y * scale,
+        emitter_color.z * emitter_color.z * attenuation.z * scale,
+        1.0,
+    )
+}
+
+///Picks a direction towards a bright part of the environment map and returns the light arriving
+///from it at the hit point of `ray`, weighted against finding that direction by a bounce
+pub fn sample_environment_light(
+    scene: &Scene,
+    ray: &Ray,
+    scatterer: Scatterer,
+    rng: &mut StdRng,
+) -> Vec4 {
+    let Some((direction, light_pdf)) = scene.sky.sample_environment(rng) else {
+        return Vec4::ZERO;
+    };
+    if light_pdf <= 0.0 {
+        return Vec4::ZERO;
+    }
+    let Some((scattered, scatter_pdf)) = scatterer.evaluate(scene, direction) else {
+        return Vec4::ZERO;
+    };
+
+    let mut shadow_ray = ray.new_from_self();
+    shadow_ray.set_direction(direction);
+    shadow_ray.hit.current_material = shadow_ray.hit.previous_material;
+    let mut attenuation = Vec4::ZERO;
+    get_direct_light_attenuation(scene, &mut shadow_ray, &mut attenuation);
+    if attenuation.w <= 0.0 {
+        return Vec4::ZERO;
+    }
+
+    let radiance = scene.sky_color(direction);
+    let weight = scene.mis_heuristic.weight(light_pdf, scatter_pdf);
+    let scale = attenuation.w * weight * scattered / light_pdf;
+    Vec4::new(
+        radiance.x * attenuation.x * scale,
+        radiance.y * attenuation.y * scale,
+        radiance.z * attenuation.z * scale,
+        1.0,
+    )
+}
+
+///Like [`get_direct_light_attenuation`], but stops once the ray has travelled `distance`, where it
+///reaches the emitter being sampled
+pub fn get_emitter_attenuation(
+    scene: &Scene,
+    ray: &mut Ray,
+    distance: f32,
+    attenuation: &mut Vec4,
+) {
+    const EMITTER_SURFACE_TOLERANCE: f32 = 0.001;
+    *attenuation = Vec4::splat(1.0);
+    let start = ray.origin;
+    while attenuation.w > 0.0 {
+        ray.origin = ray.at(Ray::OFFSET);
+        let segment_start = ray.origin;
+        let medium = ray.hit.current_material;
+        let intersected = next_intersection(scene, ray);
+        let remaining = distance - segment_start.distance(start);
+        let length = segment_length(scene, segment_start, ray, intersected).min(remaining);
+        *attenuation *= medium_transmittance(scene, medium, segment_start, ray, length);
+        if !intersected {
+            break;
+        }
+        if ray.origin.distance(start) >= distance - EMITTER_SURFACE_TOLERANCE {
+            break;
+        }
+        let mult = 1.0 - ray.hit.color.w;
+        attenuation.x *= ray.hit.color.x * ray.hit.color.w + mult;
+        attenuation.y *= ray.hit.color.y * ray.hit.color.w + mult;
+        attenuation.z *= ray.hit.color.z * ray.hit.color.w + mult;
+        attenuation.w *= mult;
+    }
+}
+
 pub fn get_direct_light_attenuation(scene: &Scene, ray: &mut Ray, attenuation: &mut Vec4) {
     *attenuation = Vec4::splat(1.0);
     while attenuation.w > 0.0 {
         ray.origin = ray.at(Ray::OFFSET);
-        if !next_intersection(scene, ray) {
+        let segment_start = ray.origin;
+        let medium = ray.hit.current_material;
+        let intersected = next_intersection(scene, ray);
+        let length = segment_length(scene, segment_start, ray, intersected);
+        *attenuation *= medium_transmittance(scene, medium, segment_start, ray, length);
+        if !intersected {
             break;
         }
         let mult = 1.0 - ray.hit.color.w;
@@ -481,3 +955,268 @@ pub fn get_direct_light_attenuation(scene: &Scene, ray: &mut Ray, attenuation: &
         }
     }
 }
+
+///Light left after travelling `length` blocks from `start` along `ray` through `medium`. Fog only
+///fills the air and absorbs every channel equally, so it is folded into the w component
+fn medium_transmittance(
+    scene: &Scene,
+    medium: MaterialID,
+    start: Vec3A,
+    ray: &Ray,
+    length: f32,
+) -> Vec4 {
+    if medium == AIR {
+        let fog = scene.fog.transmittance(start, *ray.get_direction(), length);
+        return Vec4::new(1.0, 1.0, 1.0, fog);
+    }
+    if contains_water(scene.get_material(medium)) {
+        return scene.water.transmittance(length).extend(1.0);
+    }
+    Vec4::ONE
+}
+
+///Distance `ray` travelled from `start` in its last call to [`next_intersection`]. Rays that
+///didn't hit anything end where they leave the octree, the fog doesn't reach beyond it
+fn segment_length(scene: &Scene, start: Vec3A, ray: &Ray, intersected: bool) -> f32 {
+    if intersected {
+        return start.distance(ray.origin);
+    }
+    let size = Vec3A::splat((scene.octree.depth() as f32).exp2());
+    let inverse_direction = *ray.get_inverse_direction();
+    let exits = ((Vec3A::ZERO - start) * inverse_direction).max((size - start) * inverse_direction);
+    exits.min_element().max(0.0)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::UVec3;
+    use rand::SeedableRng;
+
+    use crate::{
+        colors::U8Color,
+        octree::new_octree::Octree,
+        scene::{
+            Sun, SunSamplingStrategy,
+            fog::{Fog, FogMode},
+            resource_manager::BuiltModels,
+            water::Water,
+        },
+        textures::{lab_pbr::LAB_PBR_EMITTANCE, rtw_image::RTWImage, texture::Texture},
+    };
+
+    use super::*;
+
+    const WHITE: Texture = Texture::Color(U8Color::new(255, 255, 255, 255));
+
+    ///An emissive block at (4, 4, 4), with an opaque one right below it when `occluded`
+    fn emitter_scene(occluded: bool) -> Scene {
+        let mut octree = Octree::default();
+        octree.set_voxel(UVec3::new(4, 4, 4), 1);
+        if occluded {
+            octree.set_voxel(UVec3::new(4, 3, 4), 2);
+        }
+        let emitter = Material::builder().albedo(WHITE).emittance(1.0).build();
+        let blocker = Material::builder().albedo(WHITE).build();
+        Scene::new(
+            octree,
+            Box::new([Material::AIR, emitter, blocker]),
+            BuiltModels::default(),
+        )
+    }
+
+    ///Light from the bottom face of the emitter reaching a floor 2 blocks below its centre
+    fn sample_bottom_face(scene: &Scene, rng: &mut StdRng) -> Vec4 {
+        let ray = Ray::new(Vec3A::new(4.5, 2.0, 4.5), Vec3A::Y);
+        let bottom = 2;
+        sample_emitter_face(
+            scene,
+            &ray,
+            Scatterer::Diffuse(Vec3A::Y),
+            &scene.emitters[0],
+            bottom,
+            1.0,
+            rng,
+        )
+    }
+
+    #[test]
+    pub fn occluded_emitters_give_no_light() {
+        let scene = emitter_scene(true);
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..100).for_each(|_| assert_eq!(sample_bottom_face(&scene, &mut rng), Vec4::ZERO));
+    }
+
+    #[test]
+    pub fn visible_emitters_give_their_irradiance() {
+        const SAMPLES: usize = 4000;
+        const STEPS: usize = 100;
+        let scene = emitter_scene(false);
+        let mut rng = StdRng::seed_from_u64(0);
+        let estimate = (0..SAMPLES)
+            .map(|_| sample_bottom_face(&scene, &mut rng).x)
+            .sum::<f32>()
+            / SAMPLES as f32;
+
+        //cos at the floor times cos at the face over the squared distance, integrated over the face
+        let height = 2.0f32;
+        let integral = (0..STEPS * STEPS)
+            .map(|i| {
+                let x = ((i % STEPS) as f32 + 0.5) / STEPS as f32 - 0.5;
+                let z = ((i / STEPS) as f32 + 0.5) / STEPS as f32 - 0.5;
+                let distance_squared = height * height + x * x + z * z;
+                height * height / (distance_squared * distance_squared)
+            })
+            .sum::<f32>()
+            / (STEPS * STEPS) as f32;
+        let expected = scene.emmitter_intensity * integral / PI;
+        assert!(
+            (estimate - expected).abs() < 0.01 * expected,
+            "{estimate} != {expected}"
+        );
+    }
+
+    ///A white floor in uniform fog under a sun straight overhead, with a roof above it when
+    ///`roofed`
+    fn fog_scene(roofed: bool) -> Scene {
+        let mut octree = Octree::default();
+        octree.fill_box(UVec3::ZERO, UVec3::new(15, 0, 15), 1);
+        if roofed {
+            octree.fill_box(UVec3::new(0, 8, 0), UVec3::new(15, 8, 15), 1);
+        }
+        let white = Material::builder().albedo(WHITE).build();
+        let mut scene = Scene::new(
+            octree,
+            Box::new([Material::AIR, white]),
+            BuiltModels::default(),
+        );
+        scene.set_sun(Sun::from_angles(0.0, PI / 2.0));
+        scene.fog = Fog {
+            mode: FogMode::Uniform,
+            density: 0.3,
+            ..Fog::default()
+        };
+        scene
+    }
+
+    ///Average light seen along a ray starting at `origin`
+    fn average_color(scene: &Scene, origin: Vec3A, direction: Vec3A, samples: usize) -> Vec3A {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..samples)
+            .map(|_| Vec3A::from(scene.get_color(Ray::new(origin, direction), &mut rng, 1)))
+            .sum::<Vec3A>()
+            / samples as f32
+    }
+
+    #[test]
+    pub fn fog_scatters_sunlight_where_it_is_not_shadowed() {
+        let skimming =
+            |scene: &Scene| average_color(scene, Vec3A::new(0.5, 2.5, 8.5), Vec3A::X, 2000).x;
+        let lit = skimming(&fog_scene(false));
+        let shadowed = skimming(&fog_scene(true));
+        //without fog both rays would only see the sky
+        assert!(lit > 2.0 * shadowed, "{lit} <= 2 * {shadowed}");
+    }
+
+    ///A white floor, flooded with 4 blocks of clear water when `wet`
+    fn water_scene(wet: bool) -> Scene {
+        let mut octree = Octree::default();
+        octree.fill_box(UVec3::ZERO, UVec3::new(15, 0, 15), 1);
+        if wet {
+            octree.fill_box(UVec3::new(0, 1, 0), UVec3::new(15, 4, 15), 2);
+        }
+        let white = Material::builder().albedo(WHITE).build();
+        let water = Material::builder()
+            .albedo(Texture::Color(U8Color::new(255, 255, 255, 0)))
+            .index_of_refraction(Water::INDEX_OF_REFRACTION)
+            .specular(Water::SPECULAR)
+            .material_flags(MaterialFlags::REFRACTIVE | MaterialFlags::WATER)
+            .build();
+        let mut scene = Scene::new(
+            octree,
+            Box::new([Material::AIR, white, water]),
+            BuiltModels::default(),
+        );
+        //sunlight reaches the floor through shadow rays rather than by chance through caustics
+        scene.sun_sampling_strategy = SunSamplingStrategy::FAST;
+        scene
+    }
+
+    #[test]
+    pub fn light_through_water_turns_blue() {
+        let looking_down =
+            |scene: &Scene| average_color(scene, Vec3A::new(8.5, 7.5, 8.5), Vec3A::NEG_Y, 2000);
+        let dry = looking_down(&water_scene(false));
+        let wet = looking_down(&water_scene(true));
+        //the floor is seen through 4 blocks of water, lit by light that went through as many
+        assert!(wet.z > 0.0);
+        assert!(wet.x / wet.z < 0.5 * dry.x / dry.z, "{wet} {dry}");
+    }
+
+    ///A white floor under a roof of a single layer of blocks, which light can wander through when
+    ///`translucent`
+    fn roofed_scene(translucent: bool) -> Scene {
+        let mut octree = Octree::default();
+        octree.fill_box(UVec3::ZERO, UVec3::new(15, 0, 15), 1);
+        octree.fill_box(UVec3::new(0, 8, 0), UVec3::new(15, 8, 15), 2);
+        let white = Material::builder().albedo(WHITE).build();
+        let roof = if translucent {
+            Material::builder()
+                .albedo(WHITE)
+                .subsurface_color(Vec3A::splat(0.97))
+                .mean_free_path(0.35)
+                .material_flags(MaterialFlags::SUBSURFACE_SCATTER)
+                .build()
+        } else {
+            Material::builder().albedo(WHITE).build()
+        };
+        let mut scene = Scene::new(
+            octree,
+            Box::new([Material::AIR, white, roof]),
+            BuiltModels::default(),
+        );
+        scene.set_sun(Sun::from_angles(0.0, PI / 2.0));
+        scene
+    }
+
+    #[test]
+    pub fn light_wanders_through_translucent_blocks() {
+        let looking_up =
+            |scene: &Scene| average_color(scene, Vec3A::new(8.5, 2.5, 8.5), Vec3A::Y, 4000).x;
+        let opaque = looking_up(&roofed_scene(false));
+        let translucent = looking_up(&roofed_scene(true));
+        //only random walks can bring the sunlight on the roof to its underside
+        assert!(translucent > 2.0 * opaque, "{translucent} <= 2 * {opaque}");
+    }
+
+    #[test]
+    pub fn specular_maps_are_read_per_texel() {
+        let mut octree = Octree::default();
+        octree.set_voxel(UVec3::new(4, 4, 4), 1);
+        //only the left half of the texture glows
+        let specular_map = RTWImage::from_rgba(2, 1, Box::new([0, 0, 0, 127, 0, 0, 0, 255]));
+        let lamp = Material::builder()
+            .albedo(WHITE)
+            .specular_map(Texture::Image(Arc::new(specular_map)))
+            .emittance(LAB_PBR_EMITTANCE)
+            .build();
+        let mut scene = Scene::new(
+            octree,
+            Box::new([Material::AIR, lamp]),
+            BuiltModels::default(),
+        );
+        scene.emitters_enabled = true;
+
+        let looking_at = |x: f32| average_color(&scene, Vec3A::new(x, 4.5, -4.0), Vec3A::Z, 200).x;
+        //the north face is away from the sun, both halves see the same sky
+        //seen from the north, the left of the texture is on the east side of the block
+        let left = looking_at(4.75);
+        let right = looking_at(4.25);
+        let emission = 0.5 * scene.emmitter_intensity;
+        assert!(
+            (left - right - emission).abs() < 0.05 * emission,
+            "{left} - {right} != {emission}"
+        );
+    }
+}
@@ -1,6 +1,9 @@
 pub mod camera;
+pub mod cpu_renderer;
 mod dummy_renderer;
 pub mod gpu_renderer;
+pub mod image_export;
+pub mod render_dump;
 pub mod renderer_trait;
 pub mod thread_pool_renderer;
 pub mod tile_renderer;
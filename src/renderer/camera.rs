@@ -65,6 +65,28 @@ impl Camera {
         }
     }
 
+    /// Upright perspective camera facing the direction given by `yaw` and `pitch` in radians
+    pub fn from_yaw_pitch(eye: Vec3A, yaw: f32, pitch: f32, fov_radians: f32) -> Self {
+        let mut camera = Self {
+            eye,
+            fov: fov_radians,
+            yaw,
+            pitch,
+            ..Self::DEFAULT_CAMERA
+        };
+        camera.update_direction();
+        camera
+    }
+
+    /// Yaw and pitch in radians of the direction the camera is facing
+    pub fn yaw_pitch(&self) -> (f32, f32) {
+        let direction = self.direction.normalize();
+        (
+            direction.x.atan2(direction.z),
+            direction.y.clamp(-1.0, 1.0).asin(),
+        )
+    }
+
     /// Focus the camera on a position, with simulated depth-of-field
     pub fn focus(mut self, focal_point: Vec3A, aperture: f32) -> Self {
         self.focal_distance = (focal_point - self.eye).dot(self.direction);
@@ -125,19 +147,23 @@ impl Camera {
                 self.pitch =
                     (self.pitch - delta.y * 0.05).clamp(-80_f32.to_radians(), 80_f32.to_radians());
 
-                // Recompute camera direction vector using spherical coordinates.
-                let (cp, sp) = (self.pitch.cos(), self.pitch.sin());
-                let (cy, sy) = (self.yaw.cos(), self.yaw.sin());
-
-                // Assuming the camera looks towards -Z by default:
-                self.direction = Vec3A::new(cp * sy, sp, cp * cy).normalize();
-
-                // For a camera that always remains "upright", you can define the "world up"
-                // as (0, 1, 0) and then compute right and a corrected up vector:
-                let world_up = Vec3A::new(0.0, 1.0, 0.0);
-                let right = self.direction.cross(world_up).normalize();
-                self.up = right.cross(self.direction).normalize();
+                self.update_direction();
             }
         });
     }
+
+    fn update_direction(&mut self) {
+        // Recompute camera direction vector using spherical coordinates.
+        let (cp, sp) = (self.pitch.cos(), self.pitch.sin());
+        let (cy, sy) = (self.yaw.cos(), self.yaw.sin());
+
+        // Assuming the camera looks towards -Z by default:
+        self.direction = Vec3A::new(cp * sy, sp, cp * cy).normalize();
+
+        // For a camera that always remains "upright", you can define the "world up"
+        // as (0, 1, 0) and then compute right and a corrected up vector:
+        let world_up = Vec3A::new(0.0, 1.0, 0.0);
+        let right = self.direction.cross(world_up).normalize();
+        self.up = right.cross(self.direction).normalize();
+    }
 }
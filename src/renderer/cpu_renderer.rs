@@ -0,0 +1,193 @@
+use std::sync::{Arc, Mutex, TryLockError};
+
+use eframe::egui::{ColorImage, Context, TextureHandle, TextureOptions};
+
+use crate::{
+    colors::{F32Color, pixel_slice_to_u8_slice, tone_mapping::ToneMapper},
+    scene::Scene,
+    settings::RendererBackendSetting,
+};
+
+use super::{
+    camera::Camera,
+    renderer_trait::{FrameInFlight, FrameInFlightPoll, RenderingBackend},
+    tile_renderer::{RendererMode, RendererStatus, TileRenderer},
+};
+
+pub struct CPURenderer {
+    tile_renderer: TileRenderer,
+    camera: Camera,
+    scene: Option<Arc<parking_lot::RwLock<Scene>>>,
+}
+
+pub struct CPUFrameInFlight {
+    frame_buffer: Arc<Mutex<Vec<F32Color>>>,
+    resolution: (usize, usize),
+    tone_mapper: ToneMapper,
+    texture: TextureHandle,
+}
+
+impl CPUFrameInFlight {
+    fn upload(mut self, frame_buffer: &[F32Color]) -> TextureHandle {
+        //the render thread hasn't allocated a buffer for this resolution yet
+        if frame_buffer.len() != self.resolution.0 * self.resolution.1 {
+            return self.texture;
+        }
+        let pixels = frame_buffer
+            .iter()
+            .map(|pixel| self.tone_mapper.map_to_u8(pixel))
+            .collect::<Vec<_>>();
+        let image = ColorImage::from_rgba_unmultiplied(
+            [self.resolution.0, self.resolution.1],
+            pixel_slice_to_u8_slice(&pixels),
+        );
+        self.texture.set(image, TextureOptions::default());
+        self.texture
+    }
+}
+
+impl FrameInFlight for CPUFrameInFlight {
+    fn poll(self: Box<CPUFrameInFlight>) -> FrameInFlightPoll {
+        let frame_buffer = self.frame_buffer.clone();
+        //the tile workers hold the lock while they write their results
+        match frame_buffer.try_lock() {
+            Ok(guard) => FrameInFlightPoll::Ready(self.upload(&guard)),
+            Err(TryLockError::WouldBlock) => FrameInFlightPoll::NotReady(self),
+            Err(TryLockError::Poisoned(_)) => FrameInFlightPoll::Cancelled,
+        }
+    }
+
+    fn wait_for(self: Box<CPUFrameInFlight>) -> Result<TextureHandle, TextureHandle> {
+        let frame_buffer = self.frame_buffer.clone();
+        match frame_buffer.lock() {
+            Ok(guard) => Ok(self.upload(&guard)),
+            Err(_) => Err(self.texture),
+        }
+    }
+}
+
+impl CPURenderer {
+    pub fn new(resolution: (u32, u32)) -> Self {
+        let threads = std::thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1);
+        let mut tile_renderer = TileRenderer::new(
+            (resolution.0 as usize, resolution.1 as usize),
+            1024,
+            10,
+            threads,
+        );
+        tile_renderer.set_mode(RendererMode::PathTraced);
+        Self {
+            camera: tile_renderer.get_camera(),
+            tile_renderer,
+            scene: None,
+        }
+    }
+
+    pub fn tile_renderer(&self) -> &TileRenderer {
+        &self.tile_renderer
+    }
+
+    pub fn tile_renderer_mut(&mut self) -> &mut TileRenderer {
+        &mut self.tile_renderer
+    }
+
+    fn restart(&mut self) {
+        self.tile_renderer.stop();
+        if let Some(scene) = &self.scene {
+            self.tile_renderer.render_scene(scene.clone());
+        }
+    }
+}
+
+impl RenderingBackend for CPURenderer {
+    fn get_camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    fn set_camera(&mut self, camera: Camera) {
+        if self.camera == camera {
+            return;
+        }
+        self.camera = camera.clone();
+        self.tile_renderer
+            .edit_camera(|tile_camera| *tile_camera = camera.clone());
+    }
+
+    fn which_backend(&self) -> RendererBackendSetting {
+        RendererBackendSetting::CPU
+    }
+
+    fn set_resolution(&mut self, resolution: (u32, u32)) {
+        self.tile_renderer
+            .set_resolution((resolution.0 as usize, resolution.1 as usize));
+        self.restart();
+    }
+
+    fn get_status(&self) -> RendererStatus {
+        self.tile_renderer.get_renderer_status()
+    }
+
+    fn get_resolution(&self) -> (u32, u32) {
+        let (x, y) = self.tile_renderer.get_resolution();
+        (x as u32, y as u32)
+    }
+
+    fn set_mode(&mut self, mode: RendererMode) {
+        if self.tile_renderer.get_mode() == mode {
+            return;
+        }
+        self.tile_renderer.set_mode(mode);
+        self.restart();
+    }
+
+    fn get_mode(&self) -> RendererMode {
+        self.tile_renderer.get_mode()
+    }
+
+    fn update_scene(&mut self, ctx: &Context) {
+        let mut camera = self.camera.clone();
+        camera.move_with_keyboard_input(ctx);
+        camera.rotate(ctx);
+        self.set_camera(camera);
+    }
+
+    fn set_scene(&mut self, scene: &Arc<parking_lot::RwLock<Scene>>) {
+        self.scene = Some(scene.clone());
+        self.restart();
+    }
+
+    fn get_scene(&self) -> Option<&Arc<parking_lot::RwLock<Scene>>> {
+        self.scene.as_ref()
+    }
+
+    fn get_frame_buffer(&self) -> Option<Arc<Mutex<Vec<F32Color>>>> {
+        self.scene.as_ref()?;
+        Some(self.tile_renderer.get_frame_buffer())
+    }
+
+    fn set_tone_mapper(&mut self, tone_mapper: ToneMapper) {
+        self.tile_renderer.set_tone_mapper(tone_mapper);
+    }
+
+    fn get_tone_mapper(&self) -> ToneMapper {
+        self.tile_renderer.get_tone_mapper()
+    }
+
+    fn render_frame(
+        &self,
+        _egui_frame: &eframe::Frame,
+        texture: TextureHandle,
+    ) -> Result<Box<dyn FrameInFlight>, TextureHandle> {
+        if self.scene.is_none() {
+            return Err(texture);
+        }
+        Ok(Box::new(CPUFrameInFlight {
+            frame_buffer: self.tile_renderer.get_frame_buffer(),
+            resolution: self.tile_renderer.get_resolution(),
+            tone_mapper: self.tile_renderer.get_tone_mapper(),
+            texture,
+        }))
+    }
+}
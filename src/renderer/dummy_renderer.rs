@@ -1,12 +1,15 @@
 use eframe::egui::TextureHandle;
 
-use crate::scene::Scene;
+use crate::{colors::tone_mapping::ToneMapper, scene::Scene};
 
 use super::{camera::Camera, renderer_trait::RenderingBackend, tile_renderer::RendererMode};
 static mut DUMMY_CAMERA: Camera = Camera::DEFAULT_CAMERA;
 
 #[derive(Default, Clone, Copy)]
-pub struct DummyRenderer {}
+pub struct DummyRenderer {
+    ///Kept so the tone mapper survives switching to this backend and back
+    tone_mapper: ToneMapper,
+}
 
 impl RenderingBackend for DummyRenderer {
     fn render_frame(
@@ -21,6 +24,24 @@ impl RenderingBackend for DummyRenderer {
 
     fn set_scene(&mut self, scene: &std::sync::Arc<parking_lot::RwLock<Scene>>) {}
 
+    fn get_scene(&self) -> Option<&std::sync::Arc<parking_lot::RwLock<Scene>>> {
+        None
+    }
+
+    fn get_frame_buffer(
+        &self,
+    ) -> Option<std::sync::Arc<std::sync::Mutex<Vec<crate::colors::F32Color>>>> {
+        None
+    }
+
+    fn set_tone_mapper(&mut self, tone_mapper: ToneMapper) {
+        self.tone_mapper = tone_mapper;
+    }
+
+    fn get_tone_mapper(&self) -> ToneMapper {
+        self.tone_mapper
+    }
+
     fn get_mode(&self) -> super::tile_renderer::RendererMode {
         super::tile_renderer::RendererMode::Preview
     }
@@ -1,12 +1,14 @@
 use crate::{
-    colors::PixelColor as _,
+    colors::{PixelColor as _, tone_mapping::ToneMapper},
     gpu_structs::{
+        gpu_biomes::biomes_to_gpu_data,
         gpu_camera::CameraUniform,
         gpu_material::GPUMaterial,
-        gpu_octree::octree_to_gpu_data,
+        gpu_octree::{lod_values_to_gpu_data, octree_to_gpu_data},
         gpu_quad::GPUQuad,
     },
     scene::Scene,
+    textures::animation::AnimatedTexture,
 };
 use std::{fs, num::NonZero, slice, sync::Arc, time::Instant};
 
@@ -158,6 +160,7 @@ pub struct GPURenderer {
     status: RendererStatus,
     render_data: Option<RenderData>,
     pipeline: Option<SVOPipeline>,
+    tone_mapper: ToneMapper,
 }
 pub struct GPUFrameInFlight {
     device: Device,
@@ -196,13 +199,26 @@ impl GPURenderer {
             render_data: None,
             render_size,
             status: RendererStatus::Stopped,
+            tone_mapper: Default::default(),
         }
     }
     pub fn create_pipeline(device: &Device, queue: &Queue, scene: &Scene) -> SVOPipeline {
         let octree = &scene.octree;
 
         let materials = &scene.materials;
-        let quads = &scene.quads;
+        //the shader looks quads up by leaf value, so every block gets the first face of its model
+        let quads: Vec<_> = scene
+            .block_quads
+            .iter()
+            .enumerate()
+            .map(|(block, faces)| match scene.quads[faces.clone()].first() {
+                Some(quad) => GPUQuad::from(quad),
+                None => GPUQuad {
+                    material_id: block as u32,
+                    ..GPUQuad::zeroed()
+                },
+            })
+            .collect();
 
         let (octree_uniform, octant_data) = octree_to_gpu_data(&scene.octree);
 
@@ -261,10 +277,17 @@ impl GPURenderer {
                         array_layer_count: Some(1),
                     });
 
-                    let material = GPUMaterial::zeroed();
+                    let material = GPUMaterial {
+                        tint_index: material.tint_index,
+                        ..GPUMaterial::zeroed()
+                    };
                     ((texture, texture_view), material)
                 }
-                crate::textures::texture::Texture::Image(rtwimage) => {
+                crate::textures::texture::Texture::Image(rtwimage)
+                | crate::textures::texture::Texture::Animated(AnimatedTexture {
+                    frame: rtwimage,
+                    ..
+                }) => {
                     let string = format!("{:p}", &rtwimage);
                     let label = Some(string.as_str());
 
@@ -300,7 +323,10 @@ impl GPURenderer {
                         base_array_layer: 0,
                         array_layer_count: Some(1),
                     });
-                    let material = GPUMaterial::zeroed();
+                    let material = GPUMaterial {
+                        tint_index: material.tint_index,
+                        ..GPUMaterial::zeroed()
+                    };
                     ((texture, texture_view), material)
                 }
             })
@@ -312,8 +338,6 @@ impl GPURenderer {
             usage: BufferUsages::UNIFORM,
         });
 
-        let quads: Vec<_> = quads.iter().map(GPUQuad::from).collect();
-
         let material_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("materials"),
             contents: bytemuck::cast_slice(&materials),
@@ -329,6 +353,22 @@ impl GPURenderer {
             contents: bytemuck::cast_slice(&octant_data),
             usage: BufferUsages::STORAGE,
         });
+        let lod_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("octant lod values"),
+            contents: bytemuck::cast_slice(&lod_values_to_gpu_data(&scene.octree)),
+            usage: BufferUsages::STORAGE,
+        });
+        let (biome_cells, biome_tints) = biomes_to_gpu_data(&scene.biomes, &scene.biome_colors);
+        let biome_cell_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("biome cells"),
+            contents: bytemuck::cast_slice(&biome_cells),
+            usage: BufferUsages::STORAGE,
+        });
+        let biome_tint_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("biome tints"),
+            contents: bytemuck::cast_slice(&biome_tints),
+            usage: BufferUsages::STORAGE,
+        });
 
         let output_texture = device.create_texture(&TextureDescriptor {
             label: Some("Output Texture"),
@@ -484,6 +524,39 @@ impl GPURenderer {
                         },
                         count: None,
                     },
+                    //biome cells
+                    BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    //biome tints
+                    BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    //octant lod values
+                    BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
 
@@ -521,6 +594,18 @@ impl GPURenderer {
                     binding: 6,
                     resource: BindingResource::TextureView(&output_texture_view),
                 },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: biome_cell_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 8,
+                    resource: biome_tint_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 9,
+                    resource: lod_buffer.as_entire_binding(),
+                },
             ],
         });
 
@@ -668,6 +753,26 @@ impl RenderingBackend for GPURenderer {
         }
     }
 
+    fn get_scene(&self) -> Option<&std::sync::Arc<parking_lot::RwLock<Scene>>> {
+        self.scene.as_ref()
+    }
+
+    //the shader writes straight to an 8 bit texture, there is no float buffer to hand out
+    fn get_frame_buffer(
+        &self,
+    ) -> Option<std::sync::Arc<std::sync::Mutex<Vec<crate::colors::F32Color>>>> {
+        None
+    }
+
+    //only kept so it carries over to the other backends, the shader does its own conversion
+    fn set_tone_mapper(&mut self, tone_mapper: ToneMapper) {
+        self.tone_mapper = tone_mapper;
+    }
+
+    fn get_tone_mapper(&self) -> ToneMapper {
+        self.tone_mapper
+    }
+
     fn get_mode(&self) -> RendererMode {
         self.mode
     }
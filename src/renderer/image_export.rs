@@ -0,0 +1,160 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, bail};
+use image::{ImageBuffer, ImageFormat, Rgba};
+
+use crate::colors::{F32Color, PixelColor as _, tone_mapping::ToneMapper};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Png,
+    Png16,
+    Pfm,
+    Exr,
+}
+
+impl ExportFormat {
+    pub const FORMATS: [ExportFormat; 4] = [
+        ExportFormat::Png,
+        ExportFormat::Png16,
+        ExportFormat::Pfm,
+        ExportFormat::Exr,
+    ];
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "PNG",
+            ExportFormat::Png16 => "16-bit PNG",
+            ExportFormat::Pfm => "PFM",
+            ExportFormat::Exr => "OpenEXR",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png | ExportFormat::Png16 => "png",
+            ExportFormat::Pfm => "pfm",
+            ExportFormat::Exr => "exr",
+        }
+    }
+
+    ///Linear formats store the accumulated radiance as is, the others are tone mapped
+    pub fn is_linear(&self) -> bool {
+        matches!(self, ExportFormat::Pfm | ExportFormat::Exr)
+    }
+
+    ///Guesses the format from the extension of `path`. `.png` is always treated as 8-bit
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ExportFormat::Png),
+            "pfm" => Some(ExportFormat::Pfm),
+            "exr" => Some(ExportFormat::Exr),
+            _ => None,
+        }
+    }
+}
+
+///Saves a linear, row major frame buffer with the first row at the top of the image.
+///`tone_mapper` is ignored by the linear formats
+pub fn export_image(
+    path: &Path,
+    format: ExportFormat,
+    resolution: (u32, u32),
+    frame_buffer: &[F32Color],
+    tone_mapper: &ToneMapper,
+) -> anyhow::Result<()> {
+    let (width, height) = resolution;
+    if frame_buffer.len() != width as usize * height as usize {
+        bail!(
+            "frame buffer holds {} pixels but the resolution is {width}x{height}",
+            frame_buffer.len()
+        );
+    }
+    match format {
+        ExportFormat::Png => {
+            let data = frame_buffer
+                .iter()
+                .flat_map(|pixel| <[u8; 4]>::from(tone_mapper.map_to_u8(pixel)))
+                .collect::<Vec<_>>();
+            ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, data)
+                .context("invalid frame buffer size")?
+                .save_with_format(path, ImageFormat::Png)?;
+        }
+        ExportFormat::Png16 => {
+            let data = frame_buffer
+                .iter()
+                .flat_map(|pixel| tone_mapper.map_to_u16(pixel))
+                .collect::<Vec<_>>();
+            ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, data)
+                .context("invalid frame buffer size")?
+                .save_with_format(path, ImageFormat::Png)?;
+        }
+        ExportFormat::Pfm => write_pfm(path, resolution, frame_buffer)?,
+        ExportFormat::Exr => {
+            let data = frame_buffer
+                .iter()
+                .flat_map(|pixel| pixel.clone().into_array())
+                .collect::<Vec<_>>();
+            ImageBuffer::<Rgba<f32>, _>::from_raw(width, height, data)
+                .context("invalid frame buffer size")?
+                .save_with_format(path, ImageFormat::OpenExr)?;
+        }
+    }
+    Ok(())
+}
+
+///PFM stores RGB floats with the bottom row first. A negative scale marks little endian data
+fn write_pfm(path: &Path, resolution: (u32, u32), frame_buffer: &[F32Color]) -> anyhow::Result<()> {
+    let (width, height) = resolution;
+    let mut writer = BufWriter::new(
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?,
+    );
+    write!(writer, "PF\n{width} {height}\n-1.0\n")?;
+    for row in frame_buffer.chunks_exact(width as usize).rev() {
+        for pixel in row {
+            for channel in [pixel.r(), pixel.g(), pixel.b()] {
+                writer.write_all(&channel.to_le_bytes())?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn pfm_rows_are_flipped() {
+        let path = std::env::temp_dir().join("octree_pathtracing_export_test.pfm");
+        let frame_buffer = vec![
+            F32Color::new(1.0, 2.0, 3.0, 1.0),
+            F32Color::new(4.0, 5.0, 6.0, 1.0),
+        ];
+        export_image(
+            &path,
+            ExportFormat::Pfm,
+            (1, 2),
+            &frame_buffer,
+            &ToneMapper::default(),
+        )
+        .unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let header = b"PF\n1 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let floats = bytes[header.len()..]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(floats, [4.0, 5.0, 6.0, 1.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{colors::F32Color, scene::Scene, scene::scene_file::SceneFile};
+
+use super::camera::Camera;
+
+const MAGIC: [u8; 4] = *b"OCTD";
+pub const RENDER_DUMP_VERSION: u32 = 1;
+///Bytes of a pixel in a dump, four f32 channels
+const PIXEL_SIZE: usize = 4 * size_of::<f32>();
+
+///A snapshot of a progressive render. `samples` holds the running average of every pixel after
+///`spp` samples
+#[derive(Debug, Clone)]
+pub struct RenderDump {
+    pub resolution: (u32, u32),
+    pub spp: u32,
+    pub scene_hash: u64,
+    pub samples: Vec<F32Color>,
+}
+
+///Hashes everything a render depends on besides the octree itself. Dumps with different hashes
+///were rendered from different views or lighting and can't be merged
+pub fn scene_hash(scene: &Scene, camera: &Camera) -> u64 {
+    let scene_file = SceneFile::from_scene(scene, camera);
+    fxhash::hash64(&format!("{scene_file:?}"))
+}
+
+impl RenderDump {
+    ///Combines two renders of the same scene, weighting each by its sample count
+    pub fn merge(&self, other: &RenderDump) -> io::Result<RenderDump> {
+        if self.resolution != other.resolution {
+            return Err(invalid_data(format!(
+                "cannot merge a {:?} dump with a {:?} dump",
+                self.resolution, other.resolution
+            )));
+        }
+        if self.scene_hash != other.scene_hash {
+            return Err(invalid_data("cannot merge dumps of different scenes"));
+        }
+        let spp = self
+            .spp
+            .checked_add(other.spp)
+            .ok_or_else(|| invalid_data("merged dump would have more samples than fit in a u32"))?;
+        let weight = |samples: u32| {
+            if spp == 0 {
+                0.0
+            } else {
+                samples as f32 / spp as f32
+            }
+        };
+        let (self_weight, other_weight) = (weight(self.spp), weight(other.spp));
+        let samples = self
+            .samples
+            .iter()
+            .zip(&other.samples)
+            .map(|(a, b)| {
+                let [ar, ag, ab, aa] = a.clone().into_array();
+                let [br, bg, bb, ba] = b.clone().into_array();
+                F32Color::new(
+                    ar * self_weight + br * other_weight,
+                    ag * self_weight + bg * other_weight,
+                    ab * self_weight + bb * other_weight,
+                    aa.max(ba),
+                )
+            })
+            .collect();
+        Ok(RenderDump {
+            resolution: self.resolution,
+            spp,
+            scene_hash: self.scene_hash,
+            samples,
+        })
+    }
+
+    ///Layout, all values little endian: magic, version: u32, width: u32, height: u32, spp: u32,
+    ///scene hash: u64, then width * height pixels of 4 f32s
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&RENDER_DUMP_VERSION.to_le_bytes())?;
+        writer.write_all(&self.resolution.0.to_le_bytes())?;
+        writer.write_all(&self.resolution.1.to_le_bytes())?;
+        writer.write_all(&self.spp.to_le_bytes())?;
+        writer.write_all(&self.scene_hash.to_le_bytes())?;
+        for pixel in &self.samples {
+            for channel in pixel.clone().into_array() {
+                writer.write_all(&channel.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        if read_array::<4, _>(reader)? != MAGIC {
+            return Err(invalid_data("not a render dump"));
+        }
+        let version = u32::from_le_bytes(read_array(reader)?);
+        if version != RENDER_DUMP_VERSION {
+            return Err(invalid_data(format!(
+                "render dump version {version} does not match {RENDER_DUMP_VERSION}"
+            )));
+        }
+        let width = u32::from_le_bytes(read_array(reader)?);
+        let height = u32::from_le_bytes(read_array(reader)?);
+        let spp = u32::from_le_bytes(read_array(reader)?);
+        let scene_hash = u64::from_le_bytes(read_array(reader)?);
+
+        //the header can't be trusted with the allocation, the samples are read first and have to
+        //match it
+        let sample_bytes = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixel_count| pixel_count.checked_mul(PIXEL_SIZE))
+            .ok_or_else(|| invalid_data(format!("render dump of {width}x{height} is too large")))?;
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        if bytes.len() != sample_bytes {
+            return Err(invalid_data(format!(
+                "render dump of {width}x{height} needs {sample_bytes} bytes of samples, found {}",
+                bytes.len()
+            )));
+        }
+        let samples = bytes
+            .chunks_exact(PIXEL_SIZE)
+            .map(|pixel| {
+                F32Color::from_array(std::array::from_fn(|channel| {
+                    let start = channel * size_of::<f32>();
+                    f32::from_le_bytes(pixel[start..start + size_of::<f32>()].try_into().unwrap())
+                }))
+            })
+            .collect();
+
+        Ok(Self {
+            resolution: (width, height),
+            spp,
+            scene_hash,
+            samples,
+        })
+    }
+
+    ///Writes to a temporary file first so an interrupted save never clobbers an older dump
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let temporary_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temporary_path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(temporary_path, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::read_from(&mut reader)
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn dump(spp: u32, value: f32) -> RenderDump {
+        RenderDump {
+            resolution: (2, 1),
+            spp,
+            scene_hash: 7,
+            samples: vec![F32Color::new(value, value, value, 1.0); 2],
+        }
+    }
+
+    #[test]
+    pub fn dump_round_trip() {
+        let dump = dump(12, 0.25);
+        let mut bytes = Vec::new();
+        dump.write_to(&mut bytes).unwrap();
+        let read = RenderDump::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.resolution, dump.resolution);
+        assert_eq!(read.spp, dump.spp);
+        assert_eq!(read.scene_hash, dump.scene_hash);
+        read.samples
+            .iter()
+            .zip(&dump.samples)
+            .for_each(|(a, b)| assert_eq!(a.clone().into_array(), b.clone().into_array()));
+    }
+
+    #[test]
+    pub fn merge_is_weighted_by_spp() {
+        let merged = dump(30, 1.0).merge(&dump(10, 0.0)).unwrap();
+        assert_eq!(merged.spp, 40);
+        let [r, ..] = merged.samples[0].clone().into_array();
+        assert!((r - 0.75).abs() < 1e-6);
+
+        assert!(dump(u32::MAX, 1.0).merge(&dump(1, 0.0)).is_err());
+
+        let mut other_scene = dump(10, 0.0);
+        other_scene.scene_hash = 8;
+        assert!(dump(30, 1.0).merge(&other_scene).is_err());
+    }
+
+    #[test]
+    pub fn headers_must_match_the_samples() {
+        let mut bytes = Vec::new();
+        dump(1, 0.5).write_to(&mut bytes).unwrap();
+        //width and height follow the magic and version
+        let mut huge = bytes.clone();
+        huge[8..16].copy_from_slice(&[0xff; 8]);
+        let error = RenderDump::read_from(&mut huge.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        bytes.pop();
+        let error = RenderDump::read_from(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,8 +1,12 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use eframe::egui::{Context, TextureHandle};
 
-use crate::{scene::Scene, settings::RendererBackendSetting};
+use crate::{
+    colors::{F32Color, tone_mapping::ToneMapper},
+    scene::Scene,
+    settings::RendererBackendSetting,
+};
 
 use super::{
     camera::Camera,
@@ -27,6 +31,11 @@ pub trait RenderingBackend {
     fn get_mode(&self) -> RendererMode;
     fn update_scene(&mut self, ctx: &Context);
     fn set_scene(&mut self, scene: &Arc<parking_lot::RwLock<Scene>>);
+    fn get_scene(&self) -> Option<&Arc<parking_lot::RwLock<Scene>>>;
+    ///The linear accumulation buffer, for backends that keep one
+    fn get_frame_buffer(&self) -> Option<Arc<Mutex<Vec<F32Color>>>>;
+    fn set_tone_mapper(&mut self, tone_mapper: ToneMapper);
+    fn get_tone_mapper(&self) -> ToneMapper;
     fn render_frame(
         &self,
         egui_frame: &eframe::Frame,
@@ -47,6 +56,6 @@ pub trait FrameInFlight {
 
 impl Default for Box<dyn RenderingBackend> {
     fn default() -> Self {
-        Box::new(DummyRenderer {})
+        Box::new(DummyRenderer::default())
     }
 }
@@ -1,15 +1,18 @@
 use std::sync::atomic::{AtomicU32, AtomicUsize};
 use std::sync::mpsc::{Receiver, Sender, channel};
-use std::sync::{self, Arc, Mutex, RwLock};
+use std::sync::{self, Arc, Mutex};
 use std::thread::{JoinHandle, spawn};
 use std::time::Instant;
 
+use parking_lot::RwLock;
 use rand::rngs::StdRng;
 
 use rand::{Rng, SeedableRng};
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 
+use crate::colors::tone_mapping::ToneMapper;
 use crate::colors::{F32Color, PixelColor as _, U8Color};
+use crate::renderer::render_dump::RenderDump;
 use crate::scene::Scene;
 
 use super::camera::Camera;
@@ -34,6 +37,7 @@ pub enum RendererMessage {
     Resume,
     ChangeSpp(u32),
     Reset,
+    SetToneMapper(ToneMapper),
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(usize)]
@@ -87,6 +91,10 @@ pub struct TileRenderer {
     thread_count: usize,
     resolution: (usize, usize),
     camera: Arc<Mutex<Camera>>,
+    frame_buffer: Arc<Mutex<Vec<F32Color>>>,
+    ///Held while a pass is added to the frame buffer, so dumps never see half of one
+    pass_lock: Arc<Mutex<()>>,
+    tone_mapper: ToneMapper,
 }
 
 impl Default for TileRenderer {
@@ -104,6 +112,9 @@ impl Default for TileRenderer {
             branch_count: 10,
             mode: RendererMode::Preview,
             camera: Default::default(),
+            frame_buffer: Default::default(),
+            pass_lock: Default::default(),
+            tone_mapper: Default::default(),
         }
     }
 }
@@ -155,6 +166,9 @@ impl TileRenderer {
             target_spp: target_samples_per_pixel,
             branch_count,
             mode: RendererMode::Preview,
+            frame_buffer: Default::default(),
+            pass_lock: Default::default(),
+            tone_mapper: Default::default(),
         }
     }
 
@@ -182,6 +196,10 @@ impl TileRenderer {
     pub fn get_camera(&self) -> Camera {
         self.camera.lock().unwrap().clone()
     }
+    ///The linear color buffer the tile workers accumulate into
+    pub fn get_frame_buffer(&self) -> Arc<Mutex<Vec<F32Color>>> {
+        self.frame_buffer.clone()
+    }
     pub fn get_mode(&self) -> RendererMode {
         self.mode
     }
@@ -221,6 +239,21 @@ impl TileRenderer {
     pub fn get_target_spp(&self) -> u32 {
         self.target_spp
     }
+    pub fn get_tone_mapper(&self) -> ToneMapper {
+        self.tone_mapper
+    }
+    ///Changes how images returned by [`TileRenderer::get_image`] are converted to 8 bit color
+    pub fn set_tone_mapper(&mut self, tone_mapper: ToneMapper) {
+        self.tone_mapper = tone_mapper;
+        if let Some(msg_channel) = &self.msg_channel {
+            match msg_channel.send(RendererMessage::SetToneMapper(tone_mapper)) {
+                Ok(()) => {}
+                Err(error) => {
+                    dbg!(error.to_string());
+                }
+            }
+        }
+    }
     pub fn get_image(&mut self) -> Option<&[U8Color]> {
         let image_buffer = self.output_image_buffer.take();
         match image_buffer {
@@ -295,10 +328,44 @@ impl TileRenderer {
         }
     }
     fn render_path_traced(&mut self, scene: Arc<RwLock<Scene>>) {
+        let frame_buffer = Self::new_frame_buffer(self.resolution);
+        self.start_path_traced(scene, frame_buffer, 0);
+    }
+
+    ///Snapshot of the accumulated samples of the current path traced render. Waits for the pass
+    ///being rendered to finish so the samples match the sample count
+    pub fn dump(&self, scene_hash: u64) -> RenderDump {
+        let _pass = self.pass_lock.lock().unwrap();
+        let samples = self.frame_buffer.lock().unwrap().clone();
+        RenderDump {
+            resolution: (self.resolution.0 as u32, self.resolution.1 as u32),
+            spp: self.get_current_spp(),
+            scene_hash,
+            samples,
+        }
+    }
+
+    ///Continues a path traced render from `dump` instead of starting from zero samples. The
+    ///renderer takes on the resolution of the dump
+    pub fn resume_from(&mut self, scene: Arc<RwLock<Scene>>, dump: RenderDump) {
+        self.stop();
+        self.mode = RendererMode::PathTraced;
+        self.resolution = (dump.resolution.0 as usize, dump.resolution.1 as usize);
+        let frame_buffer = Arc::new(Mutex::new(dump.samples));
+        self.start_path_traced(scene, frame_buffer, dump.spp);
+    }
+
+    fn start_path_traced(
+        &mut self,
+        scene: Arc<RwLock<Scene>>,
+        frame_buffer: Arc<Mutex<Vec<F32Color>>>,
+        start_spp: u32,
+    ) {
         if self.render_thread.is_some() {
             return;
         }
-        self.current_spp.store(0, sync::atomic::Ordering::SeqCst);
+        self.current_spp
+            .store(start_spp, sync::atomic::Ordering::SeqCst);
         let (msg_sender, msg_receiver) = channel::<RendererMessage>();
         let (img_sender, img_receiver) = channel::<Vec<U8Color>>();
 
@@ -308,11 +375,13 @@ impl TileRenderer {
         let target_spp = self.target_spp;
         let branch_count = self.branch_count;
         let spp_arc = self.current_spp.clone();
+        let pass_lock = self.pass_lock.clone();
         let scene_arc = scene.clone();
         let camera_arc = self.camera.clone();
         let resolution = self.resolution;
         let thread_count = self.thread_count;
         let status_arc = self.status.clone();
+        let tone_mapper = self.tone_mapper;
 
         let image_output_buffer = (0..resolution.0 * resolution.1)
             .map(|_| U8Color::BLACK)
@@ -320,20 +389,25 @@ impl TileRenderer {
 
         self.output_image_buffer = Some(image_output_buffer);
 
-        spawn(move || {
+        self.frame_buffer = frame_buffer.clone();
+
+        self.render_thread = Some(spawn(move || {
             Self::thread_task(
                 spp_arc,
+                pass_lock,
                 status_arc,
                 scene_arc,
                 camera_arc,
+                frame_buffer,
                 msg_receiver,
                 img_sender,
+                tone_mapper,
                 resolution,
                 thread_count,
                 branch_count,
                 target_spp,
             )
-        });
+        }));
     }
 
     fn render_preview(&mut self, scene: Arc<RwLock<Scene>>) {
@@ -353,6 +427,7 @@ impl TileRenderer {
         let resolution = self.resolution;
         let thread_count = self.thread_count;
         let status_arc = self.status.clone();
+        let tone_mapper = self.tone_mapper;
 
         let image_output_buffer = (0..resolution.0 * resolution.1)
             .map(|_| U8Color::BLACK)
@@ -360,26 +435,42 @@ impl TileRenderer {
 
         self.output_image_buffer = Some(image_output_buffer);
 
-        spawn(move || {
+        let frame_buffer = Self::new_frame_buffer(resolution);
+        self.frame_buffer = frame_buffer.clone();
+
+        self.render_thread = Some(spawn(move || {
             Self::preview_thread_task(
                 status_arc,
                 scene_arc,
                 camera_arc,
+                frame_buffer,
                 msg_receiver,
                 img_sender,
+                tone_mapper,
                 resolution,
                 thread_count,
             )
-        });
+        }));
+    }
+
+    fn new_frame_buffer(resolution: (usize, usize)) -> Arc<Mutex<Vec<F32Color>>> {
+        Arc::new(Mutex::new(
+            (0..resolution.0 * resolution.1)
+                .map(|_| F32Color::BLACK)
+                .collect::<Vec<_>>(),
+        ))
     }
 
     fn thread_task(
         spp_arc: Arc<AtomicU32>,
+        pass_lock: Arc<Mutex<()>>,
         status_arc: Arc<AtomicUsize>,
         scene_arc: Arc<RwLock<Scene>>,
         camera: Arc<Mutex<Camera>>,
+        frame_buffer: Arc<Mutex<Vec<F32Color>>>,
         msg_receiver: Receiver<RendererMessage>,
         output_image_sender: Sender<Vec<U8Color>>,
+        mut tone_mapper: ToneMapper,
         resolution: (usize, usize),
         rayon_thread_count: usize,
         branch_count: u32,
@@ -389,11 +480,6 @@ impl TileRenderer {
             RendererStatus::Running as usize,
             sync::atomic::Ordering::SeqCst,
         );
-        let frame_buffer = Arc::new(Mutex::new(
-            (0..resolution.0 * resolution.1)
-                .map(|_| F32Color::BLACK)
-                .collect::<Vec<_>>(),
-        ));
 
         let tile_width = resolution.0.div_ceil(rayon_thread_count);
         let tile_height = resolution.1.div_ceil(rayon_thread_count);
@@ -445,7 +531,7 @@ impl TileRenderer {
                     }
                     Some(RendererMessage::GetImage(mut buffer)) => {
                         let frame_buffer_guard = frame_buffer.lock().unwrap();
-                        Self::float_buffer_to_u8(&mut buffer, &frame_buffer_guard);
+                        tone_mapper.map_buffer(&mut buffer, &frame_buffer_guard);
                         drop(frame_buffer_guard);
                         match output_image_sender.send(buffer) {
                             Ok(_) => {}
@@ -455,6 +541,9 @@ impl TileRenderer {
                             }
                         }
                     }
+                    Some(RendererMessage::SetToneMapper(new_tone_mapper)) => {
+                        tone_mapper = new_tone_mapper;
+                    }
                     Some(RendererMessage::Pause) => {
                         status_arc.store(
                             RendererStatus::Paused as usize,
@@ -467,9 +556,10 @@ impl TileRenderer {
                     }
                 }
             }
-            let scene = scene_arc.read().unwrap();
+            let scene = scene_arc.read();
             let camera = camera.lock().unwrap().clone();
             if current_spp < target_spp {
+                let _pass = pass_lock.lock().unwrap();
                 tiles.par_iter_mut().for_each(|tile| {
                     TileRenderer::render_tile_average(
                         tile,
@@ -527,7 +617,7 @@ impl TileRenderer {
 
         'outer: loop {
             //TODO deal with this warning. idr what I wanted here
-            let scene = scene_arc.read().unwrap();
+            let scene = scene_arc.read();
             let camera = camera_arc.lock().unwrap().clone();
             let start = Instant::now();
             tiles.iter_mut().for_each(|tile| {
@@ -546,8 +636,10 @@ impl TileRenderer {
         status_arc: Arc<AtomicUsize>,
         scene_arc: Arc<RwLock<Scene>>,
         camera_arc: Arc<Mutex<Camera>>,
+        frame_buffer: Arc<Mutex<Vec<F32Color>>>,
         msg_receiver: Receiver<RendererMessage>,
         output_image_sender: Sender<Vec<U8Color>>,
+        mut tone_mapper: ToneMapper,
         resolution: (usize, usize),
         rayon_thread_count: usize,
     ) {
@@ -555,11 +647,6 @@ impl TileRenderer {
             RendererStatus::Running as usize,
             sync::atomic::Ordering::SeqCst,
         );
-        let frame_buffer = Arc::new(Mutex::new(
-            (0..resolution.0 * resolution.1)
-                .map(|_| F32Color::BLACK)
-                .collect::<Vec<_>>(),
-        ));
 
         let tile_width = resolution.0.div_ceil(rayon_thread_count);
         let tile_height = resolution.1.div_ceil(rayon_thread_count);
@@ -600,7 +687,7 @@ impl TileRenderer {
                     }
                     Some(RendererMessage::GetImage(mut buffer)) => {
                         let frame_buffer_guard = frame_buffer.lock().unwrap();
-                        Self::float_buffer_to_u8(&mut buffer, &frame_buffer_guard);
+                        tone_mapper.map_buffer(&mut buffer, &frame_buffer_guard);
                         drop(frame_buffer_guard);
                         match output_image_sender.send(buffer) {
                             Ok(_) => {}
@@ -610,6 +697,9 @@ impl TileRenderer {
                             }
                         }
                     }
+                    Some(RendererMessage::SetToneMapper(new_tone_mapper)) => {
+                        tone_mapper = new_tone_mapper;
+                    }
                     Some(RendererMessage::Pause) => {
                         status_arc.store(
                             RendererStatus::Paused as usize,
@@ -622,7 +712,7 @@ impl TileRenderer {
                     }
                 }
             }
-            let scene = scene_arc.read().unwrap();
+            let scene = scene_arc.read();
             let camera = camera_arc.lock().unwrap().clone();
             let start = Instant::now();
             tiles.par_iter_mut().for_each(|tile| {
@@ -638,12 +728,6 @@ impl TileRenderer {
         dbg!("thread finished");
     }
 
-    fn float_buffer_to_u8(u8: &mut [U8Color], float: &[F32Color]) {
-        u8.iter_mut().zip(float.iter()).for_each(|(u8, float)| {
-            *u8 = U8Color::from(float);
-        });
-    }
-
     pub fn render_tile_replace(tile: &mut Tile, camera: &Camera, scene: &Scene) {
         let mut rng = StdRng::from_os_rng();
         for y in tile.y0..tile.y1 {
@@ -701,7 +785,7 @@ impl TileRenderer {
                 let dx = rng.random_range((-1.0 / tile.dim)..(1.0 / tile.dim));
                 let dy = rng.random_range((-1.0 / tile.dim)..(1.0 / tile.dim));
                 let ray = camera.get_ray(x_normalized + dx, y_normalized + dy);
-                let color = scene.get_color(ray, &mut rng, current_spp);
+                let color = scene.get_color(ray, &mut rng, branch_count);
                 //scene.get_color(x_normalized + dx, y_normalized + dy, &mut rng, current_spp);
                 let local_buffer_idx = Self::get_pixel_index(x - tile.x0, y - tile.y0, tile.stride);
                 let r = color.x * branch_count as f32;
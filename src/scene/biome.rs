@@ -0,0 +1,282 @@
+use glam::Vec3A;
+
+use crate::{
+    octree::biome_map::BiomeMap,
+    textures::{rtw_image::RTWImage, texture::LUT_TABLE_FLOAT},
+};
+
+pub const GRASS_COLORMAP: &str = "minecraft:colormap/grass";
+pub const FOLIAGE_COLORMAP: &str = "minecraft:colormap/foliage";
+
+///Colours of plains, used where the colormaps or the biome are missing
+const DEFAULT_GRASS: u32 = 0x91BD59;
+const DEFAULT_FOLIAGE: u32 = 0x77AB2F;
+const DEFAULT_WATER: u32 = 0x3F76E4;
+const SPRUCE_FOLIAGE: u32 = 0x619961;
+const BIRCH_FOLIAGE: u32 = 0x80A755;
+const LILY_PAD: u32 = 0x208030;
+
+///Which colour the faces of a block marked with a `tintindex` are multiplied with. Stored in
+///`Material::tint_index`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TintType {
+    None = 0,
+    Grass = 1,
+    Foliage = 2,
+    Water = 3,
+    ///Spruce and birch leaves and lily pads ignore the biome
+    Spruce = 4,
+    Birch = 5,
+    LilyPad = 6,
+}
+
+impl TintType {
+    pub const TYPES: [TintType; 7] = [
+        TintType::None,
+        TintType::Grass,
+        TintType::Foliage,
+        TintType::Water,
+        TintType::Spruce,
+        TintType::Birch,
+        TintType::LilyPad,
+    ];
+
+    pub fn from_index(index: u32) -> Self {
+        Self::TYPES
+            .get(index as usize)
+            .copied()
+            .unwrap_or(TintType::None)
+    }
+
+    ///Tint of the tinted faces of a block. Blocks tinted by anything but the biome, like redstone
+    ///wire, are left untinted
+    pub fn for_block(block_name: &str) -> Self {
+        let name = block_name.strip_prefix("minecraft:").unwrap_or(block_name);
+        match name {
+            "spruce_leaves" => TintType::Spruce,
+            "birch_leaves" => TintType::Birch,
+            "lily_pad" => TintType::LilyPad,
+            "water" | "water_cauldron" | "bubble_column" => TintType::Water,
+            "vine" => TintType::Foliage,
+            _ if name.ends_with("_leaves") => TintType::Foliage,
+            "grass_block" | "short_grass" | "grass" | "tall_grass" | "fern" | "large_fern"
+            | "potted_fern" | "sugar_cane" | "bush" | "pink_petals" | "wildflowers" => {
+                TintType::Grass
+            }
+            _ => TintType::None,
+        }
+    }
+}
+
+///Temperature and downfall of a biome, which pick its grass and foliage colours from the colormaps
+fn climate(biome: &str) -> (f32, f32) {
+    let name = biome.strip_prefix("minecraft:").unwrap_or(biome);
+    match name {
+        "desert" | "savanna" | "savanna_plateau" | "windswept_savanna" | "badlands"
+        | "eroded_badlands" | "wooded_badlands" | "nether_wastes" | "soul_sand_valley"
+        | "crimson_forest" | "warped_forest" | "basalt_deltas" => (2.0, 0.0),
+        "swamp" | "mangrove_swamp" => (0.8, 0.9),
+        "forest" | "flower_forest" | "dark_forest" | "pale_garden" => (0.7, 0.8),
+        "birch_forest" | "old_growth_birch_forest" => (0.6, 0.6),
+        "taiga" | "old_growth_spruce_taiga" => (0.25, 0.8),
+        "old_growth_pine_taiga" => (0.3, 0.8),
+        "snowy_taiga" => (-0.5, 0.4),
+        "snowy_plains" | "ice_spikes" | "frozen_river" | "frozen_ocean" => (0.0, 0.5),
+        "snowy_beach" => (0.05, 0.3),
+        "windswept_hills" | "windswept_gravelly_hills" | "windswept_forest" | "stony_shore" => {
+            (0.2, 0.3)
+        }
+        "jungle" | "bamboo_jungle" => (0.95, 0.9),
+        "sparse_jungle" => (0.95, 0.8),
+        "meadow" | "cherry_grove" => (0.5, 0.8),
+        "grove" => (-0.2, 0.8),
+        "snowy_slopes" => (-0.3, 0.9),
+        "frozen_peaks" | "jagged_peaks" => (-0.7, 0.9),
+        "stony_peaks" => (1.0, 0.3),
+        "mushroom_fields" => (0.9, 1.0),
+        "river"
+        | "ocean"
+        | "deep_ocean"
+        | "warm_ocean"
+        | "lukewarm_ocean"
+        | "deep_lukewarm_ocean"
+        | "cold_ocean"
+        | "deep_cold_ocean"
+        | "deep_frozen_ocean"
+        | "lush_caves" => (0.5, 0.5),
+        _ => (0.8, 0.4),
+    }
+}
+
+///The vanilla grass and foliage colormaps, 256x256 images indexed by temperature and downfall
+#[derive(Debug, Default)]
+pub struct Colormaps {
+    pub grass: Option<RTWImage>,
+    pub foliage: Option<RTWImage>,
+}
+
+impl Colormaps {
+    fn lookup(colormap: &Option<RTWImage>, default: u32, temperature: f32, downfall: f32) -> u32 {
+        let Some(colormap) = colormap else {
+            return default;
+        };
+        let temperature = temperature.clamp(0.0, 1.0);
+        let downfall = downfall.clamp(0.0, 1.0) * temperature;
+        let x = ((1.0 - temperature) * 255.0) as u32;
+        let y = ((1.0 - downfall) * 255.0) as u32;
+        if x >= colormap.image_width || y >= colormap.image_height {
+            return default;
+        }
+        let [r, g, b, _] = colormap.pixel_data(x, y);
+        u32::from_be_bytes([0, r, g, b])
+    }
+}
+
+///Tint colours of a biome, linear
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiomeColors {
+    pub grass: Vec3A,
+    pub foliage: Vec3A,
+    pub water: Vec3A,
+}
+
+impl BiomeColors {
+    pub fn new(biome: &str, colormaps: &Colormaps) -> Self {
+        let name = biome.strip_prefix("minecraft:").unwrap_or(biome);
+        let (temperature, downfall) = climate(biome);
+        let mut grass = Colormaps::lookup(&colormaps.grass, DEFAULT_GRASS, temperature, downfall);
+        let mut foliage =
+            Colormaps::lookup(&colormaps.foliage, DEFAULT_FOLIAGE, temperature, downfall);
+        match name {
+            "swamp" => (grass, foliage) = (0x6A7039, 0x6A7039),
+            "mangrove_swamp" => (grass, foliage) = (0x6A7039, 0x8DB127),
+            "badlands" | "eroded_badlands" | "wooded_badlands" => {
+                (grass, foliage) = (0x90814D, 0x9E814D)
+            }
+            "cherry_grove" => (grass, foliage) = (0xB6DB61, 0xB6DB61),
+            "pale_garden" => (grass, foliage) = (0x778272, 0x878D76),
+            //dark forests blend their grass with a darker green
+            "dark_forest" => grass = ((grass & 0xFEFEFE) + 0x28340A) >> 1,
+            _ => {}
+        }
+        let water = match name {
+            "swamp" => 0x617B64,
+            "mangrove_swamp" => 0x3A7A6A,
+            "warm_ocean" => 0x43D5EE,
+            "lukewarm_ocean" | "deep_lukewarm_ocean" => 0x45ADF2,
+            "cold_ocean" | "deep_cold_ocean" => 0x3D57D6,
+            "frozen_ocean" | "deep_frozen_ocean" | "frozen_river" => 0x3938C9,
+            "meadow" => 0x0E4ECF,
+            "cherry_grove" => 0x5DB7EF,
+            "pale_garden" => 0x76889D,
+            _ => DEFAULT_WATER,
+        };
+        Self {
+            grass: linear_color(grass),
+            foliage: linear_color(foliage),
+            water: linear_color(water),
+        }
+    }
+
+    ///Colours of every biome in `palette`, followed by the colours used where the biome isn't
+    ///known
+    pub fn for_palette(palette: &[String], colormaps: &Colormaps) -> Box<[BiomeColors]> {
+        palette
+            .iter()
+            .map(String::as_str)
+            .chain(["minecraft:plains"])
+            .map(|biome| BiomeColors::new(biome, colormaps))
+            .collect()
+    }
+
+    ///Looks up the colours of the column holding `x`, `z` in a list from
+    ///[`BiomeColors::for_palette`]
+    pub fn at<'a>(colors: &'a [BiomeColors], biomes: &BiomeMap, x: f32, z: f32) -> &'a Self {
+        let id = biomes.biome_id_at(x, z) as usize;
+        &colors[id.min(colors.len() - 1)]
+    }
+
+    pub fn tint(&self, tint: TintType) -> Vec3A {
+        match tint {
+            TintType::None => Vec3A::ONE,
+            TintType::Grass => self.grass,
+            TintType::Foliage => self.foliage,
+            TintType::Water => self.water,
+            TintType::Spruce => linear_color(SPRUCE_FOLIAGE),
+            TintType::Birch => linear_color(BIRCH_FOLIAGE),
+            TintType::LilyPad => linear_color(LILY_PAD),
+        }
+    }
+}
+
+///Converts a 0xRRGGBB colour to linear, like texture colours are
+fn linear_color(rgb: u32) -> Vec3A {
+    let [_, r, g, b] = rgb.to_be_bytes();
+    Vec3A::new(
+        LUT_TABLE_FLOAT[r as usize],
+        LUT_TABLE_FLOAT[g as usize],
+        LUT_TABLE_FLOAT[b as usize],
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn blocks_pick_their_tint() {
+        assert_eq!(
+            TintType::for_block("minecraft:grass_block"),
+            TintType::Grass
+        );
+        assert_eq!(
+            TintType::for_block("minecraft:oak_leaves"),
+            TintType::Foliage
+        );
+        assert_eq!(
+            TintType::for_block("minecraft:spruce_leaves"),
+            TintType::Spruce
+        );
+        assert_eq!(TintType::for_block("minecraft:water"), TintType::Water);
+        assert_eq!(
+            TintType::for_block("minecraft:redstone_wire"),
+            TintType::None
+        );
+        TintType::TYPES
+            .into_iter()
+            .for_each(|tint| assert_eq!(TintType::from_index(tint as u32), tint));
+        assert_eq!(TintType::from_index(100), TintType::None);
+    }
+
+    #[test]
+    pub fn colormaps_follow_climate() {
+        //a colormap whose red channel is the column and green channel the row
+        let data = (0..256u32)
+            .flat_map(|y| (0..256u32).flat_map(move |x| [x as u8, y as u8, 0, 255]))
+            .collect();
+        let colormaps = Colormaps {
+            grass: Some(RTWImage::from_rgba(256, 256, data)),
+            foliage: None,
+        };
+
+        let hot = Colormaps::lookup(&colormaps.grass, 0, 2.0, 0.0);
+        assert_eq!(hot, 0x00FF00);
+        let plains = Colormaps::lookup(&colormaps.grass, 0, 0.8, 0.4);
+        assert_eq!(plains.to_be_bytes()[1], ((1.0 - 0.8) * 255.0) as u8);
+        assert_eq!(plains.to_be_bytes()[2], ((1.0 - 0.8 * 0.4) * 255.0) as u8);
+
+        let swamp = BiomeColors::new("minecraft:swamp", &colormaps);
+        assert_eq!(swamp.grass, linear_color(0x6A7039));
+        let plains = BiomeColors::new("minecraft:plains", &colormaps);
+        assert_eq!(plains.foliage, linear_color(DEFAULT_FOLIAGE));
+        assert!(swamp.water != plains.water);
+
+        let mut biomes = BiomeMap::new(4);
+        biomes.set_column(1, 0, "minecraft:swamp");
+        let colors = BiomeColors::for_palette(biomes.palette(), &colormaps);
+        assert_eq!(BiomeColors::at(&colors, &biomes, 5.0, 1.0), &swamp);
+        //unknown columns look like plains
+        assert_eq!(BiomeColors::at(&colors, &biomes, 0.0, 0.0), &plains);
+    }
+}
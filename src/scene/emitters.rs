@@ -0,0 +1,117 @@
+use glam::{UVec3, Vec3A};
+
+use crate::{
+    octree::new_octree::Octree, scene::resource_manager::MaterialID, textures::material::Material,
+};
+
+///A single emissive block. Every emitter has the same size so the pdf of sampling any of them is
+///known when a path hits one by chance
+#[derive(Debug, Clone, PartialEq)]
+pub struct Emitter {
+    ///Lowest corner, in blocks
+    pub position: Vec3A,
+    pub material: MaterialID,
+}
+
+impl Emitter {
+    pub const FACE_COUNT: usize = 6;
+    pub const FACE_AREA: f32 = 1.0;
+
+    ///Finds every block of `octree` whose material has a positive emittance. Compacted leaves are
+    ///split back into single blocks
+    pub fn collect(octree: &Octree, materials: &[Material]) -> Box<[Emitter]> {
+        let mut emitters = Vec::new();
+        octree
+            .leaves()
+            .filter(|leaf| {
+                materials
+                    .get(leaf.value as usize)
+                    .is_some_and(|material| material.emittance > 0.0)
+            })
+            .for_each(|leaf| {
+                for z in 0..leaf.size {
+                    for y in 0..leaf.size {
+                        for x in 0..leaf.size {
+                            emitters.push(Emitter {
+                                position: (leaf.position + UVec3::new(x, y, z)).as_vec3a(),
+                                material: leaf.value,
+                            });
+                        }
+                    }
+                }
+            });
+        emitters.into_boxed_slice()
+    }
+
+    ///Returns the outward normal of `face` and a point on it for the face coordinates `u` and `v`
+    ///in [0, 1]. Faces are ordered -x, +x, -y, +y, -z, +z
+    pub fn face_point(&self, face: usize, u: f32, v: f32) -> (Vec3A, Vec3A) {
+        let axis = face / 2;
+        let positive = face % 2 == 1;
+        let mut normal = Vec3A::ZERO;
+        normal[axis] = if positive { 1.0 } else { -1.0 };
+
+        let mut offset = Vec3A::ZERO;
+        offset[axis] = if positive { 1.0 } else { 0.0 };
+        offset[(axis + 1) % 3] = u;
+        offset[(axis + 2) % 3] = v;
+        (normal, self.position + offset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::octree::new_octree::{ChildType, Octant};
+
+    use super::*;
+
+    #[test]
+    pub fn emissive_leaves_are_collected() {
+        let mut root_octant = Octant::default();
+        root_octant.init_children_with(|index| match index {
+            0 => (ChildType::Leaf, 0),
+            0b011 => (ChildType::Leaf, 1),
+            _ => (ChildType::Empty, 0),
+        });
+        let tree = Octree::from_parts(Some(0), vec![root_octant], 1);
+        let tree = Octree::from_subtrees(2, 1, vec![(UVec3::new(1, 0, 0), tree)]);
+        let materials = [
+            Material::builder().build(),
+            Material::builder().emittance(1.0).build(),
+        ];
+
+        let emitters = Emitter::collect(&tree, &materials);
+        assert_eq!(
+            emitters.as_ref(),
+            [Emitter {
+                position: Vec3A::new(3.0, 1.0, 0.0),
+                material: 1,
+            }]
+        );
+
+        let (normal, point) = emitters[0].face_point(3, 0.5, 0.25);
+        assert_eq!(normal, Vec3A::Y);
+        assert_eq!(point, Vec3A::new(3.25, 2.0, 0.5));
+    }
+
+    #[test]
+    pub fn compacted_leaves_are_split() {
+        let mut root_octant = Octant::default();
+        root_octant.init_children_with(|index| match index {
+            0b111 => (ChildType::Leaf, 1),
+            _ => (ChildType::Empty, 0),
+        });
+        let tree = Octree::from_parts(Some(0), vec![root_octant], 2);
+        let materials = [
+            Material::builder().build(),
+            Material::builder().emittance(1.0).build(),
+        ];
+
+        let emitters = Emitter::collect(&tree, &materials);
+        assert_eq!(emitters.len(), 8);
+        assert!(emitters.iter().all(|emitter| {
+            emitter.position.cmpge(Vec3A::splat(2.0)).all()
+                && emitter.position.cmplt(Vec3A::splat(4.0)).all()
+        }));
+    }
+}
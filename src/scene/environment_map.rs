@@ -0,0 +1,202 @@
+use std::{f32::consts::PI, path::Path};
+
+use anyhow::Context;
+use glam::Vec3A;
+use rand::rngs::StdRng;
+
+use crate::random_float;
+
+///An equirectangular, linear radiance image wrapped around the scene, with the top row straight
+///up. Carries a 2D CDF over the pixels so bright regions can be importance sampled
+pub struct EnvironmentMap {
+    pub path: String,
+    width: usize,
+    height: usize,
+    pixels: Box<[Vec3A]>,
+    ///Running sum of the sampling weight of every pixel, restarting at each row
+    row_cdfs: Box<[f32]>,
+    ///Running sum of the total weight of every row
+    marginal_cdf: Box<[f32]>,
+}
+
+impl std::fmt::Debug for EnvironmentMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EnvironmentMap")
+            .field("path", &self.path)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+///Maps loaded from the same file are considered equal
+impl PartialEq for EnvironmentMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
+
+impl EnvironmentMap {
+    ///Loads any float image format the image crate understands, usually `.hdr` or `.exr`
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let image = image::open(path)
+            .with_context(|| format!("failed to load environment map {}", path.display()))?
+            .into_rgb32f();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let pixels = image
+            .pixels()
+            .map(|pixel| Vec3A::from_array(pixel.0))
+            .collect();
+        Ok(Self::from_pixels(
+            path.to_string_lossy().into_owned(),
+            width,
+            height,
+            pixels,
+        ))
+    }
+
+    pub fn from_pixels(path: String, width: usize, height: usize, pixels: Box<[Vec3A]>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        let mut row_cdfs = vec![0.0; width * height].into_boxed_slice();
+        let mut marginal_cdf = vec![0.0; height].into_boxed_slice();
+        let mut total = 0.0;
+        for y in 0..height {
+            //rows near the poles cover less solid angle
+            let sin_theta = ((y as f32 + 0.5) / height as f32 * PI).sin();
+            let mut row_total = 0.0;
+            for x in 0..width {
+                row_total += luminance(pixels[y * width + x]) * sin_theta;
+                row_cdfs[y * width + x] = row_total;
+            }
+            total += row_total;
+            marginal_cdf[y] = total;
+        }
+        Self {
+            path,
+            width,
+            height,
+            pixels,
+            row_cdfs,
+            marginal_cdf,
+        }
+    }
+
+    fn total_weight(&self) -> f32 {
+        self.marginal_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    ///Returns the pixel coordinates a direction falls into
+    fn pixel_of(&self, direction: Vec3A) -> (usize, usize) {
+        let u = (direction.z.atan2(direction.x) + PI) / (2.0 * PI);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+        let x = ((u * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32) as usize).min(self.height - 1);
+        (x, y)
+    }
+
+    pub fn radiance(&self, direction: Vec3A) -> Vec3A {
+        let (x, y) = self.pixel_of(direction);
+        self.pixels[y * self.width + x]
+    }
+
+    ///Picks a direction with a probability proportional to the brightness of the map and returns
+    ///it together with its solid angle pdf. Returns `None` for a black map
+    pub fn sample(&self, rng: &mut StdRng) -> Option<(Vec3A, f32)> {
+        let total = self.total_weight();
+        if total <= 0.0 {
+            return None;
+        }
+        let target = random_float(rng) * total;
+        let y = self
+            .marginal_cdf
+            .partition_point(|&weight| weight <= target)
+            .min(self.height - 1);
+
+        let row = &self.row_cdfs[y * self.width..(y + 1) * self.width];
+        let target = random_float(rng) * row[self.width - 1];
+        let x = row
+            .partition_point(|&weight| weight <= target)
+            .min(self.width - 1);
+
+        let u = (x as f32 + random_float(rng)) / self.width as f32;
+        let v = (y as f32 + random_float(rng)) / self.height as f32;
+        let phi = u * 2.0 * PI - PI;
+        let theta = v * PI;
+        let direction = Vec3A::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        );
+        Some((direction, self.pdf(direction)))
+    }
+
+    ///Solid angle pdf of [`EnvironmentMap::sample`] returning `direction`
+    pub fn pdf(&self, direction: Vec3A) -> f32 {
+        let total = self.total_weight();
+        let sin_theta = (1.0 - direction.y * direction.y).max(0.0).sqrt();
+        if total <= 0.0 || sin_theta <= 1e-4 {
+            return 0.0;
+        }
+        let (x, y) = self.pixel_of(direction);
+        let row_sin_theta = ((y as f32 + 0.5) / self.height as f32 * PI).sin();
+        let weight = luminance(self.pixels[y * self.width + x]) * row_sin_theta;
+        //each pixel spans 2pi / width by pi / height radians
+        weight / total * (self.width * self.height) as f32 / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[inline]
+fn luminance(color: Vec3A) -> f32 {
+    color.dot(Vec3A::new(0.2126, 0.7152, 0.0722))
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    pub fn samples_follow_brightness() {
+        let (width, height) = (8, 4);
+        let mut pixels = vec![Vec3A::splat(0.01); width * height];
+        pixels[width + 5] = Vec3A::splat(100.0);
+        let map = EnvironmentMap::from_pixels(String::new(), width, height, pixels.into());
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let bright_samples = (0..1000)
+            .filter_map(|_| map.sample(&mut rng))
+            .filter(|(direction, pdf)| {
+                assert!(*pdf > 0.0);
+                map.radiance(*direction).x == 100.0
+            })
+            .count();
+        assert!(bright_samples > 900);
+    }
+
+    #[test]
+    pub fn pdf_integrates_to_one() {
+        let (width, height) = (16, 8);
+        let pixels = (0..width * height)
+            .map(|index| Vec3A::splat((index % 7) as f32 + 0.5))
+            .collect();
+        let map = EnvironmentMap::from_pixels(String::new(), width, height, pixels);
+
+        let steps = 256;
+        let mut integral = 0.0;
+        for j in 0..steps {
+            let theta = (j as f32 + 0.5) / steps as f32 * PI;
+            for i in 0..steps * 2 {
+                let phi = (i as f32 + 0.5) / (steps * 2) as f32 * 2.0 * PI - PI;
+                let direction = Vec3A::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let solid_angle = (PI / steps as f32) * (PI / steps as f32) * theta.sin();
+                integral += map.pdf(direction) * solid_angle;
+            }
+        }
+        assert!((integral - 1.0).abs() < 0.05, "{integral}");
+    }
+}
@@ -0,0 +1,256 @@
+use std::f32::consts::PI;
+
+use glam::{Mat3A, Vec3A};
+use rand::rngs::StdRng;
+
+use crate::random_float;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FogMode {
+    ///Air is a vacuum
+    #[default]
+    None,
+    ///The same density everywhere
+    Uniform,
+    ///Thick close to the ground, thinning out exponentially with height
+    Layered,
+}
+
+impl FogMode {
+    pub const MODES: [FogMode; 3] = [FogMode::None, FogMode::Uniform, FogMode::Layered];
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            FogMode::None => "None",
+            FogMode::Uniform => "Uniform",
+            FogMode::Layered => "Layered",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::MODES.into_iter().find(|mode| mode.to_str() == name)
+    }
+}
+
+///A participating medium filling the air of the scene. Distances are in blocks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fog {
+    pub mode: FogMode,
+    ///Chance per block of a ray hitting a particle, at `height` for layered fog
+    pub density: f32,
+    ///Fraction of the light scattered rather than absorbed by a particle, per channel
+    pub albedo: Vec3A,
+    ///Henyey-Greenstein asymmetry, positive values scatter light forwards
+    pub anisotropy: f32,
+    ///Layered fog has a density of `density` at this y level
+    pub height: f32,
+    ///Blocks over which layered fog becomes e times thinner
+    pub falloff: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            mode: FogMode::default(),
+            density: Fog::DEFAULT_DENSITY,
+            albedo: Vec3A::splat(Fog::DEFAULT_ALBEDO),
+            anisotropy: Fog::DEFAULT_ANISOTROPY,
+            height: 0.0,
+            falloff: Fog::DEFAULT_FALLOFF,
+        }
+    }
+}
+
+impl Fog {
+    pub const DEFAULT_DENSITY: f32 = 0.01;
+    pub const MAX_DENSITY: f32 = 1.0;
+    pub const DEFAULT_ALBEDO: f32 = 0.9;
+    pub const DEFAULT_ANISOTROPY: f32 = 0.6;
+    ///Henyey-Greenstein becomes a delta function at ±1
+    pub const MAX_ANISOTROPY: f32 = 0.95;
+    pub const DEFAULT_FALLOFF: f32 = 8.0;
+    pub const MIN_FALLOFF: f32 = 0.5;
+    pub const MAX_FALLOFF: f32 = 128.0;
+    ///Keeps the density of layered fog far below `height` finite
+    const MAX_FALLOFF_EXPONENT: f32 = 30.0;
+
+    pub fn is_enabled(&self) -> bool {
+        self.mode != FogMode::None && self.density > 0.0
+    }
+
+    fn density_at(&self, y: f32) -> f32 {
+        match self.mode {
+            FogMode::None => 0.0,
+            FogMode::Uniform => self.density,
+            FogMode::Layered => {
+                let exponent = (self.height - y) / self.falloff.max(Fog::MIN_FALLOFF);
+                self.density * exponent.min(Fog::MAX_FALLOFF_EXPONENT).exp()
+            }
+        }
+    }
+
+    ///How quickly the density changes per block travelled in `direction`, relative to itself
+    fn density_decay(&self, direction: Vec3A) -> f32 {
+        match self.mode {
+            FogMode::Layered => direction.y / self.falloff.max(Fog::MIN_FALLOFF),
+            _ => 0.0,
+        }
+    }
+
+    ///Integral of the density along `distance` blocks of a ray
+    pub fn optical_depth(&self, origin: Vec3A, direction: Vec3A, distance: f32) -> f32 {
+        if !self.is_enabled() || distance <= 0.0 {
+            return 0.0;
+        }
+        let density = self.density_at(origin.y);
+        let decay = self.density_decay(direction);
+        if decay.abs() < 1e-6 {
+            return density * distance;
+        }
+        if distance.is_infinite() {
+            //a ray heading into thicker fog never makes it out
+            return if decay > 0.0 {
+                density / decay
+            } else {
+                f32::INFINITY
+            };
+        }
+        density * -(-decay * distance).exp_m1() / decay
+    }
+
+    ///Fraction of the light that makes it through `distance` blocks of fog
+    pub fn transmittance(&self, origin: Vec3A, direction: Vec3A, distance: f32) -> f32 {
+        (-self.optical_depth(origin, direction, distance)).exp()
+    }
+
+    ///Picks the distance at which a ray hits a particle, with a probability of the density times
+    ///the transmittance up to it. `None` if the ray gets through the first `max_distance` blocks
+    pub fn sample_distance(
+        &self,
+        origin: Vec3A,
+        direction: Vec3A,
+        max_distance: f32,
+        rng: &mut StdRng,
+    ) -> Option<f32> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let optical_depth = -(1.0 - random_float(rng)).ln();
+        let density = self.density_at(origin.y);
+        let decay = self.density_decay(direction);
+        let distance = if decay.abs() < 1e-6 {
+            optical_depth / density
+        } else {
+            let remaining = -optical_depth * decay / density;
+            //the fog thins out too quickly to ever reach this optical depth
+            if remaining <= -1.0 {
+                return None;
+            }
+            -remaining.ln_1p() / decay
+        };
+        (distance.is_finite() && distance < max_distance).then_some(distance)
+    }
+
+    ///Henyey-Greenstein phase function of the angle between the incoming and scattered direction
+    pub fn phase(&self, cos_theta: f32) -> f32 {
+        let g = self
+            .anisotropy
+            .clamp(-Fog::MAX_ANISOTROPY, Fog::MAX_ANISOTROPY);
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    ///Scatters light travelling in `direction`, the solid angle pdf of the result is
+    ///[`Fog::phase`]
+    pub fn sample_phase(&self, direction: Vec3A, rng: &mut StdRng) -> Vec3A {
+        let g = self
+            .anisotropy
+            .clamp(-Fog::MAX_ANISOTROPY, Fog::MAX_ANISOTROPY);
+        let x1 = random_float(rng);
+        let x2 = random_float(rng);
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * x1
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * x1);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        }
+        .clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * x2;
+
+        let tangent = if direction.x.abs() > 0.1 {
+            Vec3A::Y
+        } else {
+            Vec3A::X
+        };
+        let u = tangent.cross(direction).normalize();
+        let v = direction.cross(u);
+        Mat3A::from_cols(u, v, direction)
+            * Vec3A::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    pub fn sampled_distances_match_transmittance() {
+        let mut rng = StdRng::seed_from_u64(3);
+        [FogMode::Uniform, FogMode::Layered]
+            .into_iter()
+            .for_each(|mode| {
+                let fog = Fog {
+                    mode,
+                    density: 0.1,
+                    height: 10.0,
+                    ..Default::default()
+                };
+                let origin = Vec3A::new(0.0, 12.0, 0.0);
+                let direction = Vec3A::new(0.6, 0.8, 0.0);
+                let distance = 8.0;
+
+                let samples = 20000;
+                let scattered = (0..samples)
+                    .filter(|_| {
+                        fog.sample_distance(origin, direction, distance, &mut rng)
+                            .is_some()
+                    })
+                    .count();
+                let expected = 1.0 - fog.transmittance(origin, direction, distance);
+                let measured = scattered as f32 / samples as f32;
+                assert!(
+                    (measured - expected).abs() < 0.02,
+                    "{mode:?} {measured} {expected}"
+                );
+            });
+    }
+
+    #[test]
+    pub fn phase_sampling_matches_phase() {
+        let fog = Fog::default();
+        let mut rng = StdRng::seed_from_u64(5);
+        let direction = Vec3A::new(0.0, 0.0, 1.0);
+
+        //compare the chance of scattering into the forward hemisphere with the integral of the
+        //phase function over it
+        let samples = 20000;
+        let forward = (0..samples)
+            .map(|_| fog.sample_phase(direction, &mut rng))
+            .inspect(|scattered| assert!((scattered.length() - 1.0).abs() < 1e-4))
+            .filter(|scattered| scattered.dot(direction) > 0.0)
+            .count() as f32
+            / samples as f32;
+
+        let steps = 1000;
+        let integral = (0..steps)
+            .map(|step| {
+                let cos_theta = (step as f32 + 0.5) / steps as f32;
+                fog.phase(cos_theta) * 2.0 * PI / steps as f32
+            })
+            .sum::<f32>();
+        assert!((forward - integral).abs() < 0.02, "{forward} {integral}");
+    }
+}
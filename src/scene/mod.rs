@@ -1,4 +1,12 @@
+pub mod biome;
+pub mod emitters;
+pub mod environment_map;
+pub mod fog;
 pub mod resource_manager;
+pub mod scene_file;
+pub mod sky;
+pub mod subsurface;
+pub mod water;
 use std::f32::consts::PI;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -28,6 +36,20 @@ impl Default for EmitterSamplingStrategy {
 }
 
 impl EmitterSamplingStrategy {
+    pub const STRATEGIES: [EmitterSamplingStrategy; 4] = [
+        EmitterSamplingStrategy::NONE,
+        EmitterSamplingStrategy::ONE,
+        EmitterSamplingStrategy::ONE_BLOCK,
+        EmitterSamplingStrategy::ALL,
+    ];
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            EmitterSamplingStrategy::None { name, .. } => name,
+            EmitterSamplingStrategy::One { name, .. } => name,
+            EmitterSamplingStrategy::OneBlock { name, .. } => name,
+            EmitterSamplingStrategy::All { name, .. } => name,
+        }
+    }
     pub fn get_description(&self) -> &str {
         match self {
             EmitterSamplingStrategy::None { description, .. } => description,
@@ -36,6 +58,12 @@ impl EmitterSamplingStrategy {
             EmitterSamplingStrategy::All { description, .. } => description,
         }
     }
+    ///Looks up one of the predefined strategies by its display name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::STRATEGIES
+            .into_iter()
+            .find(|strategy| strategy.get_name() == name)
+    }
     pub const NONE: EmitterSamplingStrategy = EmitterSamplingStrategy::None {
         name: "None",
         description: "No emitter sampling.",
@@ -66,6 +94,8 @@ pub struct SunSamplingStrategy {
     pub strict_direct_light: bool,
     pub sun_luminosity: bool,
     pub importance_sampling: bool,
+    ///Weights sun and emitter samples against the diffuse bounces that could have found them
+    pub multiple_importance: bool,
 }
 
 impl Default for SunSamplingStrategy {
@@ -75,6 +105,26 @@ impl Default for SunSamplingStrategy {
 }
 
 impl SunSamplingStrategy {
+    pub const STRATEGIES: [SunSamplingStrategy; 6] = [
+        SunSamplingStrategy::OFF,
+        SunSamplingStrategy::NON_LUMINOUS,
+        SunSamplingStrategy::FAST,
+        SunSamplingStrategy::IMPORTANCE,
+        SunSamplingStrategy::HIGH_QUALITY,
+        SunSamplingStrategy::MULTIPLE_IMPORTANCE,
+    ];
+    pub fn get_name(&self) -> &'static str {
+        self.name
+    }
+    pub fn get_description(&self) -> &'static str {
+        self.description
+    }
+    ///Looks up one of the predefined strategies by its display name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::STRATEGIES
+            .into_iter()
+            .find(|strategy| strategy.get_name() == name)
+    }
     pub const OFF: SunSamplingStrategy = SunSamplingStrategy {
         name: "Off",
         description: "Sun is not sampled with next event estimation.",
@@ -83,6 +133,7 @@ impl SunSamplingStrategy {
         strict_direct_light: false,
         sun_luminosity: true,
         importance_sampling: false,
+        multiple_importance: false,
     };
 
     pub const NON_LUMINOUS: SunSamplingStrategy = SunSamplingStrategy {
@@ -93,6 +144,7 @@ impl SunSamplingStrategy {
         strict_direct_light: false,
         sun_luminosity: false,
         importance_sampling: false,
+        multiple_importance: false,
     };
 
     pub const FAST: SunSamplingStrategy = SunSamplingStrategy {
@@ -103,6 +155,7 @@ impl SunSamplingStrategy {
         strict_direct_light: false,
         sun_luminosity: false,
         importance_sampling: false,
+        multiple_importance: false,
     };
 
     pub const IMPORTANCE: SunSamplingStrategy = SunSamplingStrategy {
@@ -113,6 +166,7 @@ impl SunSamplingStrategy {
         strict_direct_light: false,
         sun_luminosity: true,
         importance_sampling: true,
+        multiple_importance: false,
     };
 
     pub const HIGH_QUALITY: SunSamplingStrategy = SunSamplingStrategy {
@@ -123,42 +177,246 @@ impl SunSamplingStrategy {
         strict_direct_light: true,
         sun_luminosity: true,
         importance_sampling: false,
+        multiple_importance: false,
     };
+
+    pub const MULTIPLE_IMPORTANCE: SunSamplingStrategy = SunSamplingStrategy {
+        name: "Multiple Importance",
+        description: "Sun and emitters are sampled directly and by diffuse reflections, and the two are combined with multiple importance sampling. Low noise while still modelling caustics.",
+        sun_sampling: true,
+        diffuse_sun: true,
+        strict_direct_light: true,
+        sun_luminosity: true,
+        importance_sampling: false,
+        multiple_importance: true,
+    };
+}
+
+///How multiple importance sampling weights two sampling techniques against each other
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MisHeuristic {
+    Balance,
+    #[default]
+    Power,
+}
+
+impl MisHeuristic {
+    pub const HEURISTICS: [MisHeuristic; 2] = [MisHeuristic::Balance, MisHeuristic::Power];
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            MisHeuristic::Balance => "Balance",
+            MisHeuristic::Power => "Power",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::HEURISTICS
+            .into_iter()
+            .find(|heuristic| heuristic.to_str() == name)
+    }
+
+    ///Weight of a sample taken with the technique of pdf `pdf`, when the other technique would
+    ///have produced it with `other_pdf`. Both pdfs have to be measured in solid angle
+    #[inline]
+    pub fn weight(&self, pdf: f32, other_pdf: f32) -> f32 {
+        let (pdf, other_pdf) = match self {
+            MisHeuristic::Balance => (pdf, other_pdf),
+            MisHeuristic::Power => (pdf * pdf, other_pdf * other_pdf),
+        };
+        if pdf + other_pdf <= 0.0 {
+            return 0.0;
+        }
+        pdf / (pdf + other_pdf)
+    }
 }
 
+use std::ops::Range;
+
+use hashbrown::HashMap;
 use rand::rngs::StdRng;
 
-use glam::{Vec3, Vec3A, Vec3Swizzles, Vec4, Vec4Swizzles};
+use glam::{UVec3, Vec3, Vec3A, Vec3Swizzles, Vec4, Vec4Swizzles};
 
 use crate::{
     colors::U8Color,
     geometry::{aabb::UP, quad::Quad},
-    octree::new_octree::Octree,
+    octree::{biome_map::BiomeMap, new_octree::Octree, new_octree_traversal::cell_at},
     random_float,
     ray::{
         Ray,
-        path_tracer::{path_trace, preview_render},
+        path_tracer::{AIR, path_trace, preview_render},
     },
-    scene::resource_manager::{MaterialID, ModelBuilder},
-    textures::{material::Material, texture::Texture},
+    scene::{
+        biome::{BiomeColors, Colormaps, TintType},
+        emitters::Emitter,
+        fog::Fog,
+        resource_manager::{BuiltModels, MaterialID, ModelBuilder},
+        scene_file::WorldReference,
+        sky::{PreethamSky, Sky},
+        water::Water,
+    },
+    textures::{animation::TextureAnimation, material::Material, texture::Texture},
 };
 
 pub struct Scene {
+    ///Change it with [`Scene::set_sun`], the sky depends on it
     pub sun: Sun,
+    ///Change it with [`Scene::set_sky`]
+    pub sky: Sky,
+    ///The Preetham model set up for the current sun and turbidity, rather than for every ray
+    preetham: PreethamSky,
+    pub fog: Fog,
+    pub water: Water,
     pub sun_sampling_strategy: SunSamplingStrategy,
     pub emitters_enabled: bool,
     pub emmitter_intensity: f32,
     pub emitter_sampling_strategy: EmitterSamplingStrategy,
+    pub mis_heuristic: MisHeuristic,
+    ///Paths are cut off after this many bounces
+    pub max_depth: u32,
+    ///Bounce after which paths may be ended early by russian roulette
+    pub russian_roulette_depth: u32,
+    ///Chance of a diffuse bounce off a subsurface scattering block wandering into it instead
     pub f_sub_surface: f32,
+    ///The moment animated textures are shown at, change it with [`Scene::set_texture_animation`]
+    pub texture_animation: TextureAnimation,
     pub octree: Octree,
+    ///Biome of every column of the octree, change it with [`Scene::set_biomes`]
+    pub biomes: BiomeMap,
+    ///Tint colours of every biome in the palette of `biomes`, followed by those of unknown ones
+    pub biome_colors: Box<[BiomeColors]>,
+    ///Faces of every block model, in block space from 0 to 1
     pub quads: Box<[Quad]>,
+    ///Range of `quads` making up the model of every block, by leaf value. Blocks past the end are
+    ///full cubes with the material of their leaf value
+    pub block_quads: Box<[Range<usize>]>,
     pub materials: Box<[Material]>,
+    ///Every emissive leaf of the octree, used for next event estimation
+    pub emitters: Box<[Emitter]>,
+    pub models: BuiltModels,
+    ///Where the octree was loaded from, if it came from a world
+    pub world: Option<WorldReference>,
 }
 
 impl Scene {
+    pub const DEFAULT_EMITTER_INTENSITY: f32 = 13.0;
+    pub const DEFAULT_F_SUB_SURFACE: f32 = 0.3;
+    pub const DEFAULT_MAX_DEPTH: u32 = 5;
+    pub const MAX_MAX_DEPTH: u32 = 256;
+    pub const DEFAULT_RUSSIAN_ROULETTE_DEPTH: u32 = 3;
+    ///Keeps dark paths from being ended so often that the survivors become fireflies
+    pub const MIN_SURVIVAL_PROBABILITY: f32 = 0.05;
+
+    ///`materials` holds the material of every leaf value, air and then the block material of
+    ///every model in `models`. The materials of the model faces are added after them
+    pub fn new(octree: Octree, materials: Box<[Material]>, mut models: BuiltModels) -> Self {
+        let (mut quads, model_quads, face_materials) = models.take_faces();
+        let first_face_material = materials.len() as MaterialID;
+        quads
+            .iter_mut()
+            .for_each(|quad| quad.material_id += first_face_material);
+        let block_quads = std::iter::once(0..0).chain(model_quads).collect();
+        let emitters = Emitter::collect(&octree, &materials);
+        let materials = materials.into_iter().chain(face_materials).collect();
+        let sun = Sun::default();
+        let sky = Sky::default();
+        Self {
+            preetham: PreethamSky::new(sky.turbidity, &sun),
+            sun,
+            sky,
+            fog: Fog::default(),
+            water: Water::default(),
+            sun_sampling_strategy: SunSamplingStrategy::default(),
+            emitters_enabled: false,
+            emmitter_intensity: Scene::DEFAULT_EMITTER_INTENSITY,
+            emitter_sampling_strategy: EmitterSamplingStrategy::default(),
+            mis_heuristic: MisHeuristic::default(),
+            max_depth: Scene::DEFAULT_MAX_DEPTH,
+            russian_roulette_depth: Scene::DEFAULT_RUSSIAN_ROULETTE_DEPTH,
+            f_sub_surface: Scene::DEFAULT_F_SUB_SURFACE,
+            texture_animation: TextureAnimation::default(),
+            emitters,
+            octree,
+            biomes: BiomeMap::default(),
+            biome_colors: BiomeColors::for_palette(&[], &Colormaps::default()),
+            quads: quads.into(),
+            block_quads,
+            materials,
+            models,
+            world: None,
+        }
+    }
+
     pub fn get_material(&self, material_id: MaterialID) -> &Material {
         &self.materials[material_id as usize]
     }
+
+    ///Edits the octree with `f` and refreshes what is derived from its leaves, the representative
+    ///values and the emitters. Leaves must hold material ids
+    pub fn edit_octree<F: FnOnce(&mut Octree)>(&mut self, f: F) {
+        f(&mut self.octree);
+        self.octree.compute_lod_values();
+        self.emitters = Emitter::collect(&self.octree, &self.materials);
+    }
+
+    ///Replaces the biomes of the octree, picking their grass and foliage colours from `colormaps`
+    pub fn set_biomes(&mut self, biomes: BiomeMap, colormaps: &Colormaps) {
+        self.biome_colors = BiomeColors::for_palette(biomes.palette(), colormaps);
+        self.biomes = biomes;
+    }
+
+    ///Moves the sun, and the bright part of the sky with it
+    pub fn set_sun(&mut self, sun: Sun) {
+        self.preetham = PreethamSky::new(self.sky.turbidity, &sun);
+        self.sun = sun;
+    }
+
+    pub fn set_sky(&mut self, sky: Sky) {
+        self.preetham = PreethamSky::new(sky.turbidity, &self.sun);
+        self.sky = sky;
+    }
+
+    ///Radiance of the sky arriving from `direction`, which has to be normalized
+    pub fn sky_color(&self, direction: Vec3A) -> Vec4 {
+        self.sky.color(direction, &self.preetham)
+    }
+
+    ///Colour the albedo of a material with `tint_index` is multiplied with at `position`, in
+    ///octree space
+    pub fn tint_at(&self, position: Vec3A, tint_index: u32) -> Vec3A {
+        let tint = TintType::from_index(tint_index);
+        if tint == TintType::None {
+            return Vec3A::ONE;
+        }
+        BiomeColors::at(&self.biome_colors, &self.biomes, position.x, position.z).tint(tint)
+    }
+
+    ///Shows every animated texture at another moment of its animation, on the cpu materials as
+    ///well as the textures uploaded to the gpu
+    pub fn set_texture_animation(&mut self, texture_animation: TextureAnimation) {
+        if self.texture_animation == texture_animation {
+            return;
+        }
+        self.texture_animation = texture_animation;
+
+        //textures shared between materials stay shared
+        let mut reanimated: HashMap<Texture, Texture> = HashMap::new();
+        let mut reanimate = |texture: &mut Texture| {
+            let Texture::Animated(animated) = &*texture else {
+                return;
+            };
+            let frame = reanimated
+                .entry(texture.clone())
+                .or_insert_with(|| Texture::Animated(animated.at(&texture_animation)))
+                .clone();
+            *texture = frame;
+        };
+        self.materials
+            .iter_mut()
+            .for_each(|material| reanimate(&mut material.texture));
+        self.models.textures_mut().iter_mut().for_each(reanimate);
+    }
 }
 
 pub struct SceneBuilder {
@@ -169,36 +427,102 @@ pub struct SceneBuilder {
 impl Scene {
     pub const SKY_COLOR: Vec4 = Vec4::new(0.5, 0.7, 1.0, 1.0);
 
+    ///Moves `ray` to the next face where its material changes and fills in the hit record
     pub fn hit(&self, ray: &mut Ray) -> bool {
-        let hit = false;
-        let direction = ray.get_direction();
-        if direction.x == 0.0 && direction.y == 0.0 && direction.z == 0.0 || direction.is_nan() {
-            println!("invalid ray direction");
-            println!("ray dir: {}", direction);
-            ray.set_direction(UP);
+        match self.intersect_octree(ray) {
+            Some(position) => {
+                ray.origin = position;
+                true
+            }
+            None => false,
         }
-
-        let max_dst = 1024.0;
-
-        //TODO
-        //self.octree
-        // .intersect_octree_path_tracer(ray, max_dst, &self.materials, &self.quads);
-        false
     }
+    ///Like [`Scene::hit`], but leaves the origin of `ray` where it is
     pub fn hit_preview(&self, ray: &mut Ray) -> bool {
-        let hit = false;
-        let direction = ray.get_direction();
+        self.intersect_octree(ray).is_some()
+    }
+
+    ///Walks `ray` through the octree until it enters a cell of another material than the one it
+    ///is in, or hits a face of a block model. Fills in the hit record and returns the point it hit
+    fn intersect_octree(&self, ray: &mut Ray) -> Option<Vec3A> {
+        let direction = *ray.get_direction();
         if direction.x == 0.0 && direction.y == 0.0 && direction.z == 0.0 || direction.is_nan() {
             println!("invalid ray direction");
             println!("ray dir: {}", direction);
             ray.set_direction(UP);
         }
+        let origin = ray.origin;
+        let direction = *ray.get_direction();
+        let medium = ray.hit.current_material;
 
-        let max_dst = 1024.0;
+        //other parts of the model the ray starts on can be in its way
+        let size = (1u64 << self.octree.depth()) as f32;
+        if let Some(block) = cell_at(origin, direction, size)
+            && let Some(value) = self.octree.get_voxel(block)
+            && value != medium
+            && let Some(quads) = self.block_model(value)
+            && let Some(face) = hit_model(ray, quads, block, 1)
+        {
+            return Some(self.hit_face(ray, value, &quads[face]));
+        }
 
-        //self.octree
-        //  .intersect_octree_preview(ray, max_dst, &self.materials, &self.quads);
-        false
+        let mut face = None;
+        let hit = self.octree.intersect(origin, direction, |cell| {
+            let value = cell.value.unwrap_or(AIR);
+            if value == medium {
+                return false;
+            }
+            let Some(quads) = cell.value.and_then(|value| self.block_model(value)) else {
+                return true;
+            };
+            face = hit_model(ray, quads, cell.cell, cell.size);
+            face.is_some()
+        })?;
+        if let Some(face) = face {
+            let value = hit.value.unwrap_or(AIR);
+            let quads = self.block_model(value).unwrap_or_default();
+            return Some(self.hit_face(ray, value, &quads[face]));
+        }
+
+        let (u, v) = face_uv(hit.position, direction, hit.normal);
+        ray.hit.t = hit.t;
+        ray.hit.normal = hit.normal;
+        ray.hit.u = u;
+        ray.hit.v = v;
+        ray.hit.current_material = hit.value.unwrap_or(AIR);
+        ray.hit.face_material = ray.hit.current_material;
+        //the air leaving a block behind is clear
+        ray.hit.color = match hit.value {
+            Some(material_id) => self
+                .get_material(material_id)
+                .texture
+                .value(u, v, &hit.position),
+            None => Vec4::ZERO,
+        };
+        Some(hit.position)
+    }
+
+    ///Faces of the model of blocks with leaf value `value`, `None` for full cubes
+    fn block_model(&self, value: u32) -> Option<&[Quad]> {
+        self.block_quads
+            .get(value as usize)
+            .map(|range| &self.quads[range.clone()])
+    }
+
+    ///Fills in the hit record for `quad` of the model of block `value`, after [`Quad::hit`] found
+    ///it at `ray.hit.t_next`
+    fn hit_face(&self, ray: &mut Ray, value: u32, quad: &Quad) -> Vec3A {
+        let t = ray.hit.t_next;
+        let position = ray.at(t);
+        ray.hit.t = t;
+        ray.hit.normal = quad.normal;
+        ray.hit.current_material = value;
+        ray.hit.face_material = quad.material_id;
+        ray.hit.color = self
+            .get_material(quad.material_id)
+            .texture
+            .value(ray.hit.u, ray.hit.v, &position);
+        position
     }
 
     pub fn get_preview_color(&self, mut ray: Ray, x: f32, y: f32, rng: &mut StdRng) -> Vec3 {
@@ -206,9 +530,9 @@ impl Scene {
         preview_render(rng, self, &mut ray, &mut attenuation);
         ray.hit.color.xyz()
     }
-    pub fn get_color(&self, mut ray: Ray, rng: &mut StdRng, current_spp: u32) -> Vec3 {
+    pub fn get_color(&self, mut ray: Ray, rng: &mut StdRng, branch_count: u32) -> Vec3 {
         let mut attenuation = Vec4::ZERO;
-        path_trace(rng, self, &mut ray, true, &mut attenuation, current_spp);
+        path_trace(rng, self, &mut ray, true, &mut attenuation, branch_count);
         //Vec3::new(ray.hit.normal.x, ray.hit.normal.y, ray.hit.normal.z)
         ray.hit.color.xyz()
     }
@@ -232,8 +556,31 @@ impl Scene {
         ray.hit.color.w = 1.0
     }
 
+    ///Like [`Scene::get_sky_color_diffuse_sun`], but the sun is weighted against the chance of it
+    ///having been found by sampling the sun directly
+    pub fn get_sky_color_mis(&self, ray: &mut Ray) {
+        self.get_sky_color_diffuse_inner(ray);
+        let r = ray.hit.color.x;
+        let g = ray.hit.color.y;
+        let b = ray.hit.color.z;
+        if self.sun.intersect_diffuse(ray) {
+            //directions with a pdf of 0 can't be found by sampling the sun
+            let weight = if ray.hit.bsdf_pdf > 0.0 {
+                self.mis_heuristic
+                    .weight(ray.hit.bsdf_pdf, self.sun.sample_pdf())
+            } else {
+                1.0
+            };
+            let mult = self.sun.luminosity * weight;
+            ray.hit.color.x = ray.hit.color.x * mult + r;
+            ray.hit.color.y = ray.hit.color.y * mult + g;
+            ray.hit.color.z = ray.hit.color.z * mult + b;
+        }
+        ray.hit.color.w = 1.0;
+    }
+
     pub fn get_sky_color_inner(&self, ray: &mut Ray) {
-        ray.hit.color = Scene::SKY_COLOR;
+        ray.hit.color = self.sky_color(*ray.get_direction());
     }
     pub fn get_sky_color_interp(&self, ray: &mut Ray) {
         self.get_sky_color_diffuse_inner(ray);
@@ -264,7 +611,102 @@ impl Scene {
         }
     }
     pub fn get_sky_color_diffuse_inner(&self, ray: &mut Ray) {
-        ray.hit.color = Scene::SKY_COLOR;
+        let direction = *ray.get_direction();
+        ray.hit.color = self.sky_color(direction);
+        //diffuse bounces into an environment map compete with sampling it directly
+        if ray.hit.bsdf_pdf > 0.0
+            && let Some(light_pdf) = self.sky.environment_pdf(direction)
+        {
+            let weight = self.mis_heuristic.weight(ray.hit.bsdf_pdf, light_pdf);
+            ray.hit.color.x *= weight;
+            ray.hit.color.y *= weight;
+            ray.hit.color.z *= weight;
+        }
+    }
+}
+
+///Index of the nearest face of `quads` that `ray` hits in the blocks of the leaf cell of side `size`
+///at `cell`, leaving its distance and texture coordinates in the hit record
+fn hit_model(ray: &mut Ray, quads: &[Quad], cell: UVec3, size: u32) -> Option<usize> {
+    let origin = ray.origin;
+    let direction = *ray.get_direction();
+    let cell_min = cell.as_vec3a();
+    let cell_max = cell_min + size as f32;
+
+    //start in the block of the cell the ray enters first
+    let t_enter = (0..3)
+        .filter(|&axis| direction[axis] != 0.0)
+        .map(|axis| {
+            let face = if direction[axis] > 0.0 {
+                cell_min[axis]
+            } else {
+                cell_max[axis]
+            };
+            (face - origin[axis]) / direction[axis]
+        })
+        .fold(0.0f32, f32::max);
+    let entry = origin + direction * t_enter;
+    let mut block = entry.floor();
+    (0..3).for_each(|axis| {
+        if direction[axis] < 0.0 && block[axis] == entry[axis] {
+            block[axis] -= 1.0;
+        }
+    });
+    block = block.clamp(cell_min, cell_max - 1.0);
+
+    loop {
+        ray.hit.t_next = f32::INFINITY;
+        let mut nearest = None;
+        quads.iter().enumerate().for_each(|(index, quad)| {
+            if quad.hit(ray, &block) {
+                nearest = Some(index);
+            }
+        });
+        if nearest.is_some() {
+            return nearest;
+        }
+
+        //move on to the next block of the cell
+        let boundary = Vec3A::select(direction.cmpgt(Vec3A::ZERO), block + 1.0, block);
+        let mut exits = Vec3A::INFINITY;
+        (0..3).for_each(|axis| {
+            if direction[axis] != 0.0 {
+                exits[axis] = (boundary[axis] - origin[axis]) / direction[axis];
+            }
+        });
+        let axis = (0..3).find(|&axis| exits[axis] == exits.min_element())?;
+        block[axis] += direction[axis].signum();
+        if block[axis] < cell_min[axis] || block[axis] >= cell_max[axis] {
+            return None;
+        }
+    }
+}
+
+///Texture coordinates of `position` on the block face with `normal`, oriented the same way as the
+///faces of block models
+fn face_uv(position: Vec3A, direction: Vec3A, normal: Vec3A) -> (f32, f32) {
+    let fraction = position - position.floor();
+    if normal.x != 0.0 {
+        let u = if direction.x < 0.0 {
+            1.0 - fraction.z
+        } else {
+            fraction.z
+        };
+        (u, fraction.y)
+    } else if normal.y != 0.0 {
+        let v = if direction.y < 0.0 {
+            1.0 - fraction.z
+        } else {
+            fraction.z
+        };
+        (fraction.x, v)
+    } else {
+        let u = if direction.z < 0.0 {
+            fraction.x
+        } else {
+            1.0 - fraction.x
+        };
+        (u, fraction.y)
     }
 }
 
@@ -293,16 +735,7 @@ pub struct Sun {
 
 impl Default for Sun {
     fn default() -> Self {
-        Sun::new(
-            PI / 2.5,
-            PI / 3.0,
-            0.03,
-            Vec4::splat(1.0),
-            Texture::Color(U8Color::new(255, 255, 255, 255)),
-            true,
-            false,
-            Vec3A::splat(1.0),
-        )
+        Sun::from_angles(Sun::DEFAULT_AZIMUTH, Sun::DEFAULT_ALTITUDE)
     }
 }
 
@@ -315,9 +748,24 @@ impl Sun {
     pub const MAX_IMPORTANCE_SAMPLE_RADIUS: f32 = 5.0;
     pub const DEFAULT_IMPORTANCE_SAMPLE_RADIUS: f32 = 1.2;
     pub const MIN_IMPORTANCE_SAMPLE_RADIUS: f32 = 0.1;
+    ///Keeps `luminosity_pdf` finite
+    pub const MIN_LUMINOSITY: f32 = 1.0;
     const AMBIENT: f32 = 0.3;
     const INTENSITY: f32 = 1.25;
     const GAMMA: f32 = 2.2;
+    ///A plain white sun at the given azimuth and altitude in radians
+    pub fn from_angles(azimuth: f32, altitude: f32) -> Self {
+        Sun::new(
+            azimuth,
+            altitude,
+            0.03,
+            Vec4::splat(1.0),
+            Texture::Color(U8Color::new(255, 255, 255, 255)),
+            true,
+            false,
+            Vec3A::splat(1.0),
+        )
+    }
     pub fn new(
         azimuth: f32,
         altitude: f32,
@@ -444,6 +892,11 @@ impl Sun {
         reflected.set_direction(reflected_dir);
     }
 
+    ///Solid angle pdf of the directions returned by [`Sun::get_random_sun_direction`]
+    pub fn sample_pdf(&self) -> f32 {
+        1.0 / (2.0 * PI * (1.0 - self.radius_cos))
+    }
+
     pub fn flat_shading(&self, ray: &mut Ray) {
         let n = ray.hit.normal;
         let mut shading = n.x * self.sw.x + n.y * self.sw.y + n.z * self.sw.z;
@@ -451,3 +904,144 @@ impl Sun {
         ray.hit.color *= self.emmittance * shading;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use glam::Vec2;
+    use rand::SeedableRng;
+
+    use crate::{colors::U8Color, renderer::camera::Camera, textures::texture::Texture};
+
+    use super::*;
+
+    ///A red block in the middle of a depth 3 tree and a camera looking at its south face
+    fn red_block_scene() -> (Scene, Camera) {
+        let mut octree = Octree::default();
+        octree.set_voxel(UVec3::new(4, 4, 4), 1);
+        let red = Material::builder()
+            .albedo(Texture::Color(U8Color::new(255, 0, 0, 255)))
+            .build();
+        let scene = Scene::new(
+            octree,
+            Box::new([Material::AIR, red]),
+            BuiltModels::default(),
+        );
+        let camera = Camera::look_at(
+            Vec3A::new(4.5, 4.5, 12.0),
+            Vec3A::splat(4.5),
+            Vec3A::Y,
+            30f32.to_radians(),
+        );
+        (scene, camera)
+    }
+
+    #[test]
+    pub fn rays_hit_blocks() {
+        let (scene, camera) = red_block_scene();
+        let mut ray = camera.get_ray(0.0, 0.0);
+        assert!(scene.hit(&mut ray));
+        assert_eq!(ray.origin, Vec3A::new(4.5, 4.5, 5.0));
+        assert_eq!(ray.hit.normal, Vec3A::Z);
+        assert_eq!(ray.hit.current_material, 1);
+        assert_eq!(ray.hit.color, Vec4::new(1.0, 0.0, 0.0, 1.0));
+
+        let mut ray = camera.get_ray(0.9, 0.0);
+        assert!(!scene.hit(&mut ray));
+    }
+
+    #[test]
+    pub fn rendered_blocks_are_not_sky() {
+        let (scene, camera) = red_block_scene();
+        let mut rng = StdRng::seed_from_u64(0);
+        //only the red channel of the block's texture can reach the camera, the sky is blue
+        let preview = scene.get_preview_color(camera.get_ray(0.0, 0.0), 0.0, 0.0, &mut rng);
+        assert!(preview.x > 0.0 && preview.y == 0.0 && preview.z == 0.0);
+        let color = scene.get_color(camera.get_ray(0.0, 0.0), &mut rng, 1);
+        assert!(color.x > 0.0 && color.y == 0.0 && color.z == 0.0);
+
+        let sky = scene.get_color(camera.get_ray(0.9, 0.0), &mut rng, 1);
+        assert!(sky.z > 0.0);
+    }
+
+    ///Bottom slabs filling the blocks from `min` to `max`, with a grey top and red sides. Only the
+    ///top and south faces are modelled
+    fn slab_scene(min: UVec3, max: UVec3) -> Scene {
+        let mut octree = Octree::default();
+        octree.fill_box(min, max, 1);
+        let colored = |r, g, b| {
+            Material::builder()
+                .albedo(Texture::Color(U8Color::new(r, g, b, 255)))
+                .build()
+        };
+        let full = Vec2::new(0.0, 1.0);
+        let top = Quad::new(
+            Vec3A::new(0.0, 0.5, 1.0),
+            Vec3A::X,
+            Vec3A::NEG_Z,
+            full,
+            full,
+            2,
+        );
+        let south = Quad::new(
+            Vec3A::new(0.0, 0.0, 1.0),
+            Vec3A::X,
+            Vec3A::Y * 0.5,
+            full,
+            Vec2::new(0.0, 0.5),
+            3,
+        );
+        let mut scene = Scene::new(
+            octree,
+            Box::new([
+                Material::AIR,
+                colored(128, 128, 128),
+                colored(128, 128, 128),
+                colored(255, 0, 0),
+            ]),
+            BuiltModels::default(),
+        );
+        scene.quads = Box::new([top, south]);
+        scene.block_quads = Box::new([0..0, 0..2]);
+        scene
+    }
+
+    #[test]
+    pub fn rays_hit_the_faces_of_block_models() {
+        let scene = slab_scene(UVec3::splat(4), UVec3::splat(4));
+        let mut ray = Ray::new(Vec3A::new(4.5, 4.75, 12.0), Vec3A::NEG_Z);
+        assert!(!scene.hit(&mut ray));
+
+        let mut ray = Ray::new(Vec3A::new(4.5, 4.25, 12.0), Vec3A::NEG_Z);
+        assert!(scene.hit(&mut ray));
+        assert_eq!(ray.origin, Vec3A::new(4.5, 4.25, 5.0));
+        assert_eq!(ray.hit.normal, Vec3A::Z);
+        assert_eq!(ray.hit.current_material, 1);
+        assert_eq!(ray.hit.face_material, 3);
+        assert_eq!(ray.hit.color, Vec4::new(1.0, 0.0, 0.0, 1.0));
+
+        //rays starting above the slab in its own block still land on it
+        let mut ray = Ray::new(Vec3A::new(4.5, 4.75, 4.5), Vec3A::NEG_Y);
+        assert!(scene.hit(&mut ray));
+        assert_eq!(ray.origin, Vec3A::new(4.5, 4.5, 4.5));
+        assert_eq!(ray.hit.normal, Vec3A::Y);
+        assert_eq!(ray.hit.face_material, 2);
+    }
+
+    #[test]
+    pub fn models_are_hit_in_every_block_of_a_leaf() {
+        let scene = slab_scene(UVec3::splat(4), UVec3::splat(5));
+        assert_eq!(scene.octree.leaves().count(), 1);
+        let mut ray = Ray::new(Vec3A::new(4.5, 4.75, 12.0), Vec3A::NEG_Z);
+        assert!(!scene.hit(&mut ray));
+
+        //passes over the slab in the first block and comes down on the one behind it
+        let mut ray = Ray::new(Vec3A::new(4.5, 6.5, 12.0), Vec3A::new(0.0, -0.4, -1.5));
+        assert!(scene.hit(&mut ray));
+        assert!(
+            ray.origin.abs_diff_eq(Vec3A::splat(4.5), 1e-4),
+            "{}",
+            ray.origin
+        );
+        assert_eq!(ray.hit.face_material, 2);
+    }
+}
@@ -1,13 +1,10 @@
-use crate::gpu_structs::{
-    cuboid::{Cuboid, CuboidFlags},
-    gpu_material::GPUMaterial,
-    model::{Model, ModelFlags},
-};
-use std::{hash::Hash, sync::Arc, u16};
+use crate::{geometry::quad::Quad, gpu_structs::cuboid::CuboidFlags};
+use std::{ops::Range, path::Path, sync::Arc};
 
 use glam::Mat4;
 use glam::{Quat, Vec2, Vec3A};
 use hashbrown::HashMap;
+use log::warn;
 use mc_utils::{
     block_model::borrow::BlockModel,
     block_state::{
@@ -19,52 +16,20 @@ use mc_utils::{
     resource_loader::ResourceLoader,
 };
 
-#[derive(Debug, Clone)]
-struct Mat4Wrapper {
-    data: Mat4,
-}
-
-impl From<&Mat4Wrapper> for Mat4Wrapper {
-    fn from(value: &Mat4Wrapper) -> Self {
-        value.clone()
-    }
-}
-
-impl Mat4Wrapper {
-    pub fn from_mat4(mat4: Mat4) -> Self {
-        unsafe { std::mem::transmute(mat4) }
-    }
-    pub fn from_mat4_ref(mat4: &Mat4) -> &Self {
-        unsafe { std::mem::transmute(mat4) }
-    }
-    pub fn to_mat4(self) -> Mat4 {
-        unsafe { std::mem::transmute(self) }
-    }
-    pub fn to_mat4_ref(&self) -> &Mat4 {
-        unsafe { std::mem::transmute(self) }
-    }
-}
-
-impl PartialEq for Mat4Wrapper {
-    #[inline]
-    fn eq(&self, other: &Self) -> bool {
-        let as_bytes: [u8; 16 * 4] = unsafe { std::mem::transmute(self.clone()) };
-        let as_bytes_other: [u8; 16 * 4] = unsafe { std::mem::transmute(other.clone()) };
-        as_bytes == as_bytes_other
-    }
-}
-
-impl Eq for Mat4Wrapper {}
-
-impl Hash for Mat4Wrapper {
-    #[inline]
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        let as_bytes: [u8; 16 * 4] = unsafe { std::mem::transmute(self.clone()) };
-        as_bytes.hash(state);
-    }
-}
-
-use crate::textures::{material::Material, rtw_image::RTWImage, texture::Texture};
+use crate::{
+    scene::{
+        biome::{Colormaps, FOLIAGE_COLORMAP, GRASS_COLORMAP, TintType},
+        subsurface::block_subsurface,
+        water::{WATER_BLOCK, Water, fluid_height},
+    },
+    textures::{
+        animation::{AnimatedTexture, TextureAnimation, load_animation},
+        lab_pbr::{LAB_PBR_EMITTANCE, NORMAL_SUFFIX, SPECULAR_SUFFIX, has_emission},
+        material::{Material, MaterialFlags},
+        rtw_image::RTWImage,
+        texture::Texture,
+    },
+};
 
 pub type TextureID = u32;
 pub type CuboidID = u32;
@@ -73,6 +38,17 @@ pub type ModelID = u32;
 
 pub const UNIT_BLOCK_MIN: Vec3A = Vec3A::splat(-0.5);
 pub const UNIT_BLOCK_MAX: Vec3A = Vec3A::splat(0.5);
+const WATER_TEXTURE: &str = "minecraft:block/water_still";
+
+///Looks up `property` in a mapped state like `minecraft:water#level=3`
+fn block_state_property<'a>(mapped_state: &'a str, property: &str) -> Option<&'a str> {
+    let (_, properties) = mapped_state.split_once('#')?;
+    properties
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == property)
+        .map(|(_, value)| value)
+}
 
 #[derive(Debug)]
 pub struct FinalizedBlockModel<'a> {
@@ -86,17 +62,77 @@ impl FinalizedBlockModel<'_> {
     }
 }
 
+#[derive(Default)]
 pub struct BuiltModels {
-    models: Vec<Model>,
-    cuboids: Vec<Cuboid>,
-    matrices: Vec<Mat4>,
-    materials: Vec<GPUMaterial>,
+    ///Faces of every model, in block space from 0 to 1. Their material ids index `materials`
+    quads: Vec<Quad>,
+    ///Range of `quads` making up every model, by model handle
+    model_quads: Vec<Range<usize>>,
+    ///Materials of the faces, faces of a model that look the same share one
+    materials: Vec<Material>,
+    ///Material standing in for every model as a whole, by model handle
+    block_materials: Vec<Material>,
     textures: Vec<Texture>,
 }
 
+impl BuiltModels {
+    ///Turns the cuboids of every model into quads, keeping the order of `model_data`
+    pub fn from_model_data(model_data: &[ModelData], textures: Vec<Texture>) -> Self {
+        let mut built = BuiltModels {
+            textures,
+            ..Default::default()
+        };
+        model_data.iter().for_each(|model_data| {
+            let first_quad = built.quads.len();
+            let first_material = built.materials.len();
+            model_data
+                .faces()
+                .into_iter()
+                .for_each(|(mut quad, material)| {
+                    let model_materials = &built.materials[first_material..];
+                    quad.material_id =
+                        match model_materials.iter().position(|known| known == material) {
+                            Some(index) => (first_material + index) as MaterialID,
+                            None => {
+                                built.materials.push(material.clone());
+                                (built.materials.len() - 1) as MaterialID
+                            }
+                        };
+                    built.quads.push(quad);
+                });
+            built.model_quads.push(first_quad..built.quads.len());
+            built.block_materials.push(model_data.block_material());
+        });
+        built
+    }
+
+    ///One material per model, used where the block is treated as a whole like for emitters and
+    ///the medium a ray travels through
+    pub fn block_materials(&self) -> &[Material] {
+        &self.block_materials
+    }
+
+    ///Moves out the faces of every model, the range of faces of each model and the materials the
+    ///faces point at
+    pub(crate) fn take_faces(&mut self) -> (Vec<Quad>, Vec<Range<usize>>, Vec<Material>) {
+        (
+            std::mem::take(&mut self.quads),
+            std::mem::take(&mut self.model_quads),
+            std::mem::take(&mut self.materials),
+        )
+    }
+
+    ///Textures indexed by the materials, in the order they are uploaded to the gpu
+    pub fn textures_mut(&mut self) -> &mut [Texture] {
+        &mut self.textures
+    }
+}
+
 pub struct ModelBuilder {
     model_data: Vec<ModelData>,
     textures: HashMap<String, Texture>,
+    ///Normal and specular maps by path, `None` where the resource pack doesn't have one
+    lab_pbr_maps: HashMap<String, Option<Texture>>,
     resources: ResourceLoader,
 }
 
@@ -109,6 +145,7 @@ impl ModelBuilder {
             resources,
             model_data: Default::default(),
             textures: Default::default(),
+            lab_pbr_maps: Default::default(),
         }
     }
     pub fn try_add_model_from_mapped_state(
@@ -123,209 +160,62 @@ impl ModelBuilder {
         self.model_data.get(handle.0).unwrap()
     }
 
-    pub fn build(&self) -> BuiltModels {
-        let mut unique_matrices: HashMap<Mat4Wrapper, u32> =
-            HashMap::with_capacity(self.model_data.len());
-        let mut cuboids = Vec::with_capacity(self.model_data.len());
-        let mut unique_materials: HashMap<GPUMaterial, u32> =
-            HashMap::with_capacity(self.model_data.len());
-        let mut models = Vec::with_capacity(self.model_data.len());
-
-        let num_textures = self.textures.len();
-        let texture_index_map = self
-            .textures
-            .values()
-            .cloned()
-            .zip(0..num_textures)
-            .collect::<HashMap<Texture, usize>>();
-
-        self.model_data
-            .iter()
-            .for_each(|model_data| match model_data {
-                ModelData::SimpleAABB { uvs, materials } => {
-                    let material_ids = materials
-                        .iter()
-                        .map(|mat| {
-                            let Material {
-                                index_of_refraction,
-                                material_flags,
-                                specular,
-                                emittance,
-                                roughness,
-                                metalness,
-                                texture,
-                                tint_index,
-                            } = mat;
-                            let texture_index = *texture_index_map
-                                .get(texture)
-                                .expect("Texture hash lookup failed")
-                                as u32;
-
-                            let gpu_material = GPUMaterial {
-                                ior: *index_of_refraction,
-                                specular: *specular,
-                                emittance: *emittance,
-                                roughness: *roughness,
-                                metalness: *metalness,
-                                texture_index,
-                                tint_index: *tint_index,
-                                flags: 0,
-                            };
-
-                            let materials_len = unique_materials.len();
-
-                            *unique_materials
-                                .entry(gpu_material)
-                                .or_insert_with(|| materials_len as u32)
-                        })
-                        .collect::<Vec<_>>()
-                        .try_into()
-                        .unwrap();
-
-                    let cuboid = Cuboid {
-                        flags: CuboidFlags::ALL_FACES.bits(),
-                        matrix_id: 0,
-                        material_ids,
-                        uvs: unsafe { std::mem::transmute_copy(uvs) },
-                    };
-
-                    let cuboid_index = cuboids.len() as u32;
-
-                    cuboids.push(cuboid);
-
-                    let model = Model {
-                        model_flags: ModelFlags::SIMPLE_AABB,
-                        cuboid_start_index: cuboid_index,
-                        length: 1,
-                        padding: 0,
-                    };
-
-                    models.push(model);
-                }
-                ModelData::Cuboids(cuboid_datas) => {
-                    let cuboid_start_index = cuboids.len() as u32;
-                    let cuboid_len = cuboid_datas.len() as u32;
-
-                    cuboid_datas.iter().for_each(|cuboid| {
-                        let material_ids = cuboid
-                            .materials
-                            .iter()
-                            .map(|mat| {
-                                let Material {
-                                    index_of_refraction,
-                                    material_flags,
-                                    specular,
-                                    emittance,
-                                    roughness,
-                                    metalness,
-                                    texture,
-                                    tint_index,
-                                } = mat;
-                                let texture_index = *texture_index_map
-                                    .get(texture)
-                                    .expect("Texture hash lookup failed")
-                                    as u32;
-
-                                let gpu_material = GPUMaterial {
-                                    ior: *index_of_refraction,
-                                    specular: *specular,
-                                    emittance: *emittance,
-                                    roughness: *roughness,
-                                    metalness: *metalness,
-                                    texture_index,
-                                    tint_index: *tint_index,
-                                    flags: 0,
-                                };
-
-                                let materials_len = unique_materials.len();
-
-                                *unique_materials
-                                    .entry(gpu_material)
-                                    .or_insert_with(|| materials_len as u32)
-                            })
-                            .collect::<Vec<_>>()
-                            .try_into()
-                            .unwrap();
-
-                        let CuboidData {
-                            matrix,
-                            flags,
-                            uvs,
-                            materials,
-                        } = cuboid;
-                        let matrix_id = if let Some(matrix) = matrix {
-                            let matrix_len = unique_matrices.len();
-
-                            *unique_matrices
-                                .entry_ref(Mat4Wrapper::from_mat4_ref(matrix))
-                                .or_insert_with(|| matrix_len as u32)
-                        } else {
-                            0
-                        };
-
-                        let cuboid = Cuboid {
-                            flags: flags.bits(),
-                            matrix_id,
-                            material_ids,
-                            uvs: Self::uvs_to_f16_bits(uvs),
-                        };
-
-                        cuboids.push(cuboid);
-                    });
-
-                    let model = Model {
-                        model_flags: ModelFlags::empty(),
-                        cuboid_start_index,
-                        length: cuboid_len,
-                        padding: 0,
-                    };
+    ///Turns the loaded textures that have an animation in their `.mcmeta` file into animated
+    ///textures showing their first frame. Textures with broken metadata stay as they are
+    pub fn load_texture_animations(&mut self, resource_pack_path: &Path) {
+        let mut animated: HashMap<Texture, Texture> = HashMap::new();
+        self.textures
+            .iter_mut()
+            .for_each(|(texture_path, texture)| {
+                let Texture::Image(image) = texture else {
+                    return;
+                };
+                let animation = match load_animation(resource_pack_path, texture_path, image) {
+                    Ok(Some(animation)) => animation,
+                    Ok(None) => return,
+                    Err(error) => {
+                        warn!("{error:#}");
+                        return;
+                    }
+                };
+                let animated_texture = Texture::Animated(AnimatedTexture::new(
+                    image.clone(),
+                    Arc::new(animation),
+                    &TextureAnimation::default(),
+                ));
+                animated.insert(texture.clone(), animated_texture.clone());
+                *texture = animated_texture;
+            });
+        if animated.is_empty() {
+            return;
+        }
 
-                    models.push(model);
+        self.model_data.iter_mut().for_each(|model_data| {
+            model_data.iter_materials_mut().for_each(|material| {
+                if let Some(texture) = animated.get(&material.texture) {
+                    material.texture = texture.clone();
                 }
             });
+        });
+    }
 
-        let mut unsorted_matrices = unique_matrices.into_iter().collect::<Vec<_>>();
-        unsorted_matrices.sort_by_key(|(_, i)| *i);
-
-        let matrices = unsorted_matrices
-            .into_iter()
-            .map(|(mat, _)| mat.to_mat4())
-            .collect::<Vec<_>>();
-
-        let mut unsorted_materials = unique_materials.into_iter().collect::<Vec<_>>();
-        unsorted_materials.sort_by_key(|(_, i)| *i);
-
-        let materials: Vec<GPUMaterial> = unsorted_materials
-            .into_iter()
-            .map(|(material, _)| material)
-            .collect::<Vec<_>>();
-
-        let mut unsorted_textures = texture_index_map.into_iter().collect::<Vec<_>>();
-        unsorted_textures.sort_by_key(|(_, i)| *i);
-
-        let textures = unsorted_textures
-            .into_iter()
-            .map(|(texture, _)| texture)
-            .collect::<Vec<_>>();
-
-        BuiltModels {
-            models,
-            cuboids,
-            matrices,
-            materials,
-            textures,
+    ///Loads the grass and foliage colormaps of the resource pack, biomes fall back to the colours
+    ///of plains without them
+    pub fn load_colormaps(&self) -> Colormaps {
+        let load = |colormap_path: &str| {
+            let texture_data = self.resources.get_texture_data(colormap_path)?;
+            RTWImage::load_from_memory(texture_data)
+                .inspect_err(|error| warn!("failed to load {colormap_path}: {error}"))
+                .ok()
+        };
+        Colormaps {
+            grass: load(GRASS_COLORMAP),
+            foliage: load(FOLIAGE_COLORMAP),
         }
     }
 
-    fn uvs_to_f16_bits(uvs: &[Vec2; 12]) -> [u32; 12] {
-        uvs.map(|vec2| {
-            let x = vec2.x;
-            let y = vec2.y;
-            let x_as_u16 = (u16::MAX as f32 * x) as u16;
-            let y_as_u16 = (u16::MAX as f32 * y) as u16;
-
-            (x_as_u16 as u32) << 16 | (y_as_u16 as u32)
-        })
+    pub fn build(&self) -> BuiltModels {
+        BuiltModels::from_model_data(&self.model_data, self.textures.values().cloned().collect())
     }
 
     fn apply_uv_lock(model_data: &mut ModelData, block_model_info: &BlockModelInfo) {
@@ -432,11 +322,12 @@ impl ModelBuilder {
             return None;
         }
 
-        let x_rotation = Mat4::from_rotation_x(block_x_rotation.to_degrees().to_radians());
+        //blockstate rotations are clockwise seen from the positive end of the axis, x comes first
+        let x_rotation = Mat4::from_rotation_x(-block_x_rotation.to_degrees().to_radians());
 
-        let y_rotation = Mat4::from_rotation_y(block_y_rotation.to_degrees().to_radians());
+        let y_rotation = Mat4::from_rotation_y(-block_y_rotation.to_degrees().to_radians());
 
-        let block_matrix = x_rotation * y_rotation;
+        let block_matrix = y_rotation * x_rotation;
 
         Some(block_matrix)
     }
@@ -486,6 +377,22 @@ impl ModelBuilder {
     }
 
     fn load_model_for_mapped_state(&mut self, mapped_state_str: &str) -> Option<usize> {
+        let block_name = mapped_state_str
+            .split_once('#')
+            .map_or(mapped_state_str, |(name, _)| name);
+        //fluids have no elements in their block models
+        if block_name == WATER_BLOCK {
+            let level = block_state_property(mapped_state_str, "level")
+                .and_then(|level| level.parse().ok())
+                .unwrap_or(0);
+            let water =
+                Self::water_cuboid(fluid_height(level), &mut self.textures, &self.resources);
+            let index = self.model_data.len();
+            self.model_data.push(ModelData::Cuboids(vec![water]));
+            return Some(index);
+        }
+        let waterlogged = block_state_property(mapped_state_str, "waterlogged") == Some("true");
+
         let resources = &self.resources;
         let Some(model_result) = resources.get_model_for_mapped_state(mapped_state_str) else {
             eprintln!("No variants for {}", mapped_state_str);
@@ -494,7 +401,7 @@ impl ModelBuilder {
 
         println!("variant found");
 
-        match model_result {
+        let mut model_data = match model_result {
             ModelResult::SingleModel(items) => {
                 println!("single model");
                 //TODO this would be a random model every instance of the block. might not
@@ -521,6 +428,7 @@ impl ModelBuilder {
                 let mut model_data = Self::finalized_block_model_to_model_data(
                     &finalized_model,
                     &mut self.textures,
+                    &mut self.lab_pbr_maps,
                     resources,
                 )?;
 
@@ -528,10 +436,8 @@ impl ModelBuilder {
 
                 Self::apply_block_rotation(&mut model_data, block_model_info);
 
-                let index = self.model_data.len();
-                self.model_data.push(model_data);
                 println!("returning from model loading!");
-                Some(index)
+                model_data
             }
 
             ModelResult::Multipart(model_infos) => {
@@ -559,6 +465,7 @@ impl ModelBuilder {
                         let cuboids = Self::finalized_model_to_cuboids_only(
                             &finalized_model,
                             &mut self.textures,
+                            &mut self.lab_pbr_maps,
                             resources,
                         );
 
@@ -577,10 +484,85 @@ impl ModelBuilder {
                     .flatten()
                     .collect::<Vec<_>>();
 
-                let index = self.model_data.len();
-                self.model_data.push(ModelData::Cuboids(cuboids));
-                Some(index)
+                ModelData::Cuboids(cuboids)
             }
+        };
+
+        if let Some((subsurface_color, mean_free_path)) = block_subsurface(block_name) {
+            model_data.iter_materials_mut().for_each(|material| {
+                material.material_flags |= MaterialFlags::SUBSURFACE_SCATTER;
+                material.subsurface_color = subsurface_color;
+                material.mean_free_path = mean_free_path;
+            });
+        }
+
+        let tint = TintType::for_block(block_name);
+        model_data
+            .iter_materials_mut()
+            .filter(|material| material.material_flags.contains(MaterialFlags::TINTED))
+            .for_each(|material| material.tint_index = tint as u32);
+
+        //full blocks can't be waterlogged, so only models made of cuboids get the water volume
+        if waterlogged && let ModelData::Cuboids(cuboids) = &mut model_data {
+            cuboids.iter_mut().for_each(|cuboid| {
+                cuboid.materials.iter_mut().for_each(|material| {
+                    material.material_flags |= MaterialFlags::WATERLOGGED;
+                })
+            });
+            cuboids.push(Self::water_cuboid(
+                fluid_height(0),
+                &mut self.textures,
+                &self.resources,
+            ));
+        }
+
+        let index = self.model_data.len();
+        self.model_data.push(model_data);
+        Some(index)
+    }
+
+    ///A block of water filled up to `height`
+    fn water_cuboid(
+        height: f32,
+        loaded_textures: &mut HashMap<String, Texture>,
+        resources: &ResourceLoader,
+    ) -> CuboidData {
+        let texture = Self::load_texture(WATER_TEXTURE, loaded_textures, resources);
+        let material = Material::builder()
+            .index_of_refraction(Water::INDEX_OF_REFRACTION)
+            .specular(Water::SPECULAR)
+            .material_flags(MaterialFlags::REFRACTIVE | MaterialFlags::WATER)
+            .tint_index(TintType::Water as u32)
+            .albedo(texture)
+            .build();
+
+        let min = UNIT_BLOCK_MIN;
+        let max = Vec3A::new(
+            UNIT_BLOCK_MAX.x,
+            UNIT_BLOCK_MIN.y + height,
+            UNIT_BLOCK_MAX.z,
+        );
+        let matrix = Mat4::from_translation(((min + max) / 2.0).into())
+            * Mat4::from_scale((max - min).into());
+
+        //the sides only show the top part of the texture, like in game
+        let full_uv = [Vec2::ZERO, Vec2::splat(16.0)];
+        let side_uv = [Vec2::new(0.0, 16.0 * (1.0 - height)), Vec2::splat(16.0)];
+        let mut uvs = [Vec2::ZERO; 12];
+        for face in FaceName::iter_faces() {
+            let uv = match face {
+                FaceName::Down | FaceName::Up => full_uv,
+                _ => side_uv,
+            };
+            let index = face as usize;
+            uvs[index * 2..index * 2 + 2].copy_from_slice(&uv);
+        }
+
+        CuboidData {
+            matrix: Some(matrix),
+            flags: CuboidFlags::ALL_FACES,
+            uvs,
+            materials: std::array::from_fn(|_| material.clone()),
         }
     }
 
@@ -706,12 +688,17 @@ impl ModelBuilder {
         cuboids
             .iter_mut()
             .filter(|cuboid| cuboid.matrix.is_some())
-            .for_each(|cuboid| *cuboid.matrix.as_mut().unwrap() *= matrix);
+            .for_each(|cuboid| {
+                //the block is rotated after its cuboids are put in place
+                let cuboid_matrix = cuboid.matrix.as_mut().unwrap();
+                *cuboid_matrix = matrix * *cuboid_matrix;
+            });
     }
 
     fn finalized_model_to_cuboids_only(
         model: &FinalizedBlockModel,
         loaded_textures: &mut HashMap<String, Texture>,
+        lab_pbr_maps: &mut HashMap<String, Option<Texture>>,
         resources: &ResourceLoader,
     ) -> Vec<CuboidData> {
         let FinalizedBlockModel {
@@ -722,7 +709,13 @@ impl ModelBuilder {
         elements
             .iter()
             .map(|element| {
-                Self::block_element_to_cuboid(element, texture_map, loaded_textures, resources)
+                Self::block_element_to_cuboid(
+                    element,
+                    texture_map,
+                    loaded_textures,
+                    lab_pbr_maps,
+                    resources,
+                )
             })
             .collect::<Vec<_>>()
     }
@@ -730,6 +723,7 @@ impl ModelBuilder {
     fn finalized_block_model_to_model_data(
         model: &FinalizedBlockModel,
         loaded_textures: &mut HashMap<String, Texture>,
+        lab_pbr_maps: &mut HashMap<String, Option<Texture>>,
         resources: &ResourceLoader,
     ) -> Option<ModelData> {
         let FinalizedBlockModel {
@@ -749,7 +743,13 @@ impl ModelBuilder {
             let cuboids = elements
                 .iter()
                 .map(|element| {
-                    Self::block_element_to_cuboid(element, texture_map, loaded_textures, resources)
+                    Self::block_element_to_cuboid(
+                        element,
+                        texture_map,
+                        loaded_textures,
+                        lab_pbr_maps,
+                        resources,
+                    )
                 })
                 .collect::<Vec<_>>();
             Some(ModelData::Cuboids(cuboids))
@@ -762,6 +762,7 @@ impl ModelBuilder {
                     element,
                     texture_map,
                     loaded_textures,
+                    lab_pbr_maps,
                     resources,
                 )
                 .into(),
@@ -798,6 +799,7 @@ impl ModelBuilder {
         element: &Element<'_>,
         texture_map: &HashMap<&str, &str>,
         loaded_textures: &mut HashMap<String, Texture>,
+        lab_pbr_maps: &mut HashMap<String, Option<Texture>>,
         resources: &ResourceLoader,
     ) -> [Material; 6] {
         let mut materials = [const { Material::AIR }; 6];
@@ -810,7 +812,40 @@ impl ModelBuilder {
             let texture_path =
                 Self::resolve_texture_variable(texture_map, texture_variable).unwrap();
 
-            let texture = loaded_textures.entry_ref(texture_path).or_insert_with(|| {
+            let texture = Self::load_texture(texture_path, loaded_textures, resources);
+
+            let mut builder = Material::builder().albedo(texture);
+            if let Some(normal_map) =
+                Self::load_lab_pbr_map(texture_path, NORMAL_SUFFIX, lab_pbr_maps, resources)
+            {
+                builder = builder.normal_map(normal_map);
+            }
+            if let Some(specular_map) =
+                Self::load_lab_pbr_map(texture_path, SPECULAR_SUFFIX, lab_pbr_maps, resources)
+            {
+                if has_emission(&specular_map) {
+                    builder = builder.emittance(LAB_PBR_EMITTANCE);
+                }
+                builder = builder.specular_map(specular_map);
+            }
+            let mut new_material = builder.build();
+            if face.get_tint_index().is_some() {
+                new_material.material_flags |= MaterialFlags::TINTED;
+            }
+
+            materials[index] = new_material;
+        }
+        materials
+    }
+
+    fn load_texture(
+        texture_path: &str,
+        loaded_textures: &mut HashMap<String, Texture>,
+        resources: &ResourceLoader,
+    ) -> Texture {
+        loaded_textures
+            .entry_ref(texture_path)
+            .or_insert_with(|| {
                 let texture_data = resources
                     .get_texture_data(texture_path)
                     .expect("Texture data not found");
@@ -818,13 +853,26 @@ impl ModelBuilder {
                     RTWImage::load_from_memory(texture_data).expect("Faild to create RTWImage"),
                 );
                 Texture::Image(image)
-            });
-
-            let new_material = Material::builder().albedo(texture.clone()).build();
+            })
+            .clone()
+    }
 
-            materials[index] = new_material;
-        }
-        materials
+    ///Loads the LabPBR companion of a texture, like `block/stone_n` for `block/stone`. Resource
+    ///packs without PBR support don't have them
+    fn load_lab_pbr_map(
+        texture_path: &str,
+        suffix: &str,
+        lab_pbr_maps: &mut HashMap<String, Option<Texture>>,
+        resources: &ResourceLoader,
+    ) -> Option<Texture> {
+        lab_pbr_maps
+            .entry(format!("{texture_path}{suffix}"))
+            .or_insert_with_key(|map_path| {
+                let texture_data = resources.get_texture_data(map_path)?;
+                let image = RTWImage::load_from_memory(texture_data).ok()?;
+                Some(Texture::Image(Arc::new(image)))
+            })
+            .clone()
     }
 
     fn get_matrix_from_element(element: &Element<'_>) -> Option<Mat4> {
@@ -875,6 +923,7 @@ impl ModelBuilder {
         element: &Element<'_>,
         texture_map: &HashMap<&str, &str>,
         loaded_textures: &mut HashMap<String, Texture>,
+        lab_pbr_maps: &mut HashMap<String, Option<Texture>>,
         resources: &ResourceLoader,
     ) -> CuboidData {
         CuboidData {
@@ -885,6 +934,7 @@ impl ModelBuilder {
                 element,
                 texture_map,
                 loaded_textures,
+                lab_pbr_maps,
                 resources,
             ),
         }
@@ -926,6 +976,112 @@ pub enum ModelData {
     Cuboids(Vec<CuboidData>),
 }
 
+impl ModelData {
+    ///Iterates over the materials of every face of every cuboid in the model
+    pub fn iter_materials(&self) -> Box<dyn Iterator<Item = &Material> + '_> {
+        match self {
+            ModelData::SimpleAABB { materials, .. } => Box::new(materials.iter()),
+            ModelData::Cuboids(cuboid_datas) => Box::new(
+                cuboid_datas
+                    .iter()
+                    .flat_map(|cuboid| cuboid.materials.iter()),
+            ),
+        }
+    }
+
+    pub fn iter_materials_mut(&mut self) -> Box<dyn Iterator<Item = &mut Material> + '_> {
+        match self {
+            ModelData::SimpleAABB { materials, .. } => Box::new(materials.iter_mut()),
+            ModelData::Cuboids(cuboid_datas) => Box::new(
+                cuboid_datas
+                    .iter_mut()
+                    .flat_map(|cuboid| cuboid.materials.iter_mut()),
+            ),
+        }
+    }
+
+    ///The material of the first face, tinted if any face is
+    fn block_material(&self) -> Material {
+        let mut material = self
+            .iter_materials()
+            .next()
+            .cloned()
+            .unwrap_or(Material::AIR);
+        if let Some(tint_index) = self
+            .iter_materials()
+            .map(|material| material.tint_index)
+            .find(|&tint_index| tint_index != 0)
+        {
+            material.tint_index = tint_index;
+        }
+        material
+    }
+
+    ///Every shown face of the model in block space from 0 to 1, with its material
+    fn faces(&self) -> Vec<(Quad, &Material)> {
+        match self {
+            ModelData::SimpleAABB { uvs, materials } => {
+                cuboid_faces(None, &CuboidFlags::ALL_FACES, uvs, materials)
+            }
+            ModelData::Cuboids(cuboid_datas) => cuboid_datas
+                .iter()
+                .flat_map(|cuboid| {
+                    cuboid_faces(
+                        cuboid.matrix.as_ref(),
+                        &cuboid.flags,
+                        &cuboid.uvs,
+                        &cuboid.materials,
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+///The faces of a cuboid shown in `flags`, in block space from 0 to 1. `matrix` places the unit
+///cube centred on the origin, `None` is a full block. Faces flattened to a line are left out
+fn cuboid_faces<'a>(
+    matrix: Option<&Mat4>,
+    flags: &CuboidFlags,
+    uvs: &[Vec2; 12],
+    materials: &'a [Material; 6],
+) -> Vec<(Quad, &'a Material)> {
+    let matrix = matrix.unwrap_or(&Mat4::IDENTITY);
+    FaceName::iter_faces()
+        .filter(|&face| flags.contains(CuboidFlags::from(face)))
+        .filter_map(|face| {
+            //the origin is the bottom left corner of the texture as seen from outside the block
+            let (origin, u, v) = match face {
+                FaceName::West => (Vec3A::ZERO, Vec3A::Z, Vec3A::Y),
+                FaceName::East => (Vec3A::new(1.0, 0.0, 1.0), Vec3A::NEG_Z, Vec3A::Y),
+                FaceName::Down => (Vec3A::ZERO, Vec3A::X, Vec3A::Z),
+                FaceName::Up => (Vec3A::new(0.0, 1.0, 1.0), Vec3A::X, Vec3A::NEG_Z),
+                FaceName::North => (Vec3A::X, Vec3A::NEG_X, Vec3A::Y),
+                FaceName::South => (Vec3A::Z, Vec3A::X, Vec3A::Y),
+            };
+            let origin = matrix.transform_point3a(origin + UNIT_BLOCK_MIN) - UNIT_BLOCK_MIN;
+            let u = matrix.transform_vector3a(u);
+            let v = matrix.transform_vector3a(v);
+            if u.cross(v) == Vec3A::ZERO {
+                return None;
+            }
+
+            //uvs are in pixels from the top left of the texture
+            let index = face as usize;
+            let (from, to) = (uvs[index * 2] / 16.0, uvs[index * 2 + 1] / 16.0);
+            let quad = Quad::new(
+                origin,
+                u,
+                v,
+                Vec2::new(from.x, to.x),
+                Vec2::new(1.0 - to.y, 1.0 - from.y),
+                0,
+            );
+            Some((quad, &materials[index]))
+        })
+        .collect()
+}
+
 #[derive(Debug)]
 struct CuboidData {
     matrix: Option<Mat4>,
@@ -933,3 +1089,87 @@ struct CuboidData {
     uvs: [Vec2; 12],
     materials: [Material; 6],
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{colors::U8Color, ray::Ray};
+
+    use super::*;
+
+    fn colored(r: u8, g: u8, b: u8) -> Material {
+        Material::builder()
+            .albedo(Texture::Color(U8Color::new(r, g, b, 255)))
+            .build()
+    }
+
+    #[test]
+    pub fn faces_that_look_the_same_share_a_material() {
+        let mut uvs = [Vec2::ZERO; 12];
+        (0..6).for_each(|face| uvs[face * 2 + 1] = Vec2::splat(16.0));
+        let stone = ModelData::SimpleAABB {
+            uvs,
+            materials: Box::new(std::array::from_fn(|_| colored(128, 128, 128))),
+        };
+        let mut materials: [Material; 6] = std::array::from_fn(|_| colored(0, 255, 0));
+        materials[FaceName::Down as usize] = colored(128, 64, 0);
+        let grass = ModelData::SimpleAABB {
+            uvs,
+            materials: Box::new(materials),
+        };
+
+        let built = BuiltModels::from_model_data(&[stone, grass], Vec::new());
+        assert_eq!(built.model_quads, [0..6, 6..12]);
+        assert_eq!(built.materials.len(), 3);
+        assert!(built.quads[..6].iter().all(|quad| quad.material_id == 0));
+        let grass_quads = &built.quads[6..];
+        let down = grass_quads
+            .iter()
+            .find(|quad| quad.normal == Vec3A::NEG_Y)
+            .unwrap();
+        let dirt = built.materials[down.material_id as usize].texture.clone();
+        assert_eq!(dirt, Texture::Color(U8Color::new(128, 64, 0, 255)));
+        let sides = grass_quads
+            .iter()
+            .filter(|quad| quad.material_id != down.material_id);
+        assert_eq!(sides.count(), 5);
+        assert_eq!(built.block_materials().len(), 2);
+    }
+
+    #[test]
+    pub fn cuboid_faces_follow_the_model() {
+        //a bottom slab, its sides only show the bottom half of the texture
+        let matrix = Mat4::from_translation(glam::Vec3::new(0.0, -0.25, 0.0))
+            * Mat4::from_scale(glam::Vec3::new(1.0, 0.5, 1.0));
+        let mut uvs = [Vec2::ZERO; 12];
+        (0..6).for_each(|face| {
+            uvs[face * 2] = Vec2::new(0.0, 8.0);
+            uvs[face * 2 + 1] = Vec2::splat(16.0);
+        });
+        uvs[FaceName::Up as usize * 2] = Vec2::ZERO;
+        let materials = std::array::from_fn(|_| Material::AIR);
+        let flags = CuboidFlags::UP_FACE_SHOWN | CuboidFlags::SOUTH_FACE_SHOWN;
+        let faces = cuboid_faces(Some(&matrix), &flags, &uvs, &materials);
+        assert_eq!(faces.len(), 2);
+
+        //the top of the texture faces north
+        let (up, _) = &faces[0];
+        assert_eq!(up.normal, Vec3A::Y);
+        let mut ray = Ray::new(Vec3A::new(0.25, 1.0, 0.25), Vec3A::NEG_Y);
+        ray.hit.t_next = f32::INFINITY;
+        assert!(up.hit(&mut ray, &Vec3A::ZERO));
+        assert_eq!(ray.hit.t_next, 0.5);
+        assert_eq!((ray.hit.u, ray.hit.v), (0.25, 0.75));
+
+        let (south, _) = &faces[1];
+        assert_eq!(south.normal, Vec3A::Z);
+        let mut ray = Ray::new(Vec3A::new(0.75, 0.25, 2.0), Vec3A::NEG_Z);
+        ray.hit.t_next = f32::INFINITY;
+        assert!(south.hit(&mut ray, &Vec3A::ZERO));
+        assert_eq!(ray.hit.t_next, 1.0);
+        assert_eq!((ray.hit.u, ray.hit.v), (0.75, 0.25));
+        //above the slab
+        let mut ray = Ray::new(Vec3A::new(0.75, 0.75, 2.0), Vec3A::NEG_Z);
+        ray.hit.t_next = f32::INFINITY;
+        assert!(!south.hit(&mut ray, &Vec3A::ZERO));
+    }
+}
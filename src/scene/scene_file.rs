@@ -0,0 +1,445 @@
+use std::{fs, path::Path, sync::Arc};
+
+use anyhow::{Context, bail};
+use glam::Vec3A;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    renderer::camera::Camera,
+    textures::animation::{AnimationTime, TextureAnimation},
+};
+
+use super::{
+    EmitterSamplingStrategy, MisHeuristic, Scene, Sun, SunSamplingStrategy,
+    environment_map::EnvironmentMap,
+    fog::{Fog, FogMode},
+    sky::{Sky, SkyModel},
+};
+
+///Describes the part of a minecraft world a scene was built from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorldReference {
+    pub path: String,
+    pub origin: [i64; 3],
+    pub depth: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraDescription {
+    pub eye: [f32; 3],
+    ///Degrees
+    pub yaw: f32,
+    ///Degrees
+    pub pitch: f32,
+    ///Degrees
+    pub fov: f32,
+    pub aperture: f32,
+    pub focal_distance: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SunDescription {
+    ///Degrees
+    pub azimuth: f32,
+    ///Degrees
+    pub altitude: f32,
+    pub luminosity: f32,
+    pub importance_sample_chance: f32,
+    pub importance_sample_radius: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkyDescription {
+    pub model: String,
+    pub turbidity: f32,
+    pub ground_albedo: f32,
+    ///Path of the HDR image
+    pub environment: Option<String>,
+    ///Degrees
+    pub environment_rotation: Option<f32>,
+    pub environment_intensity: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FogDescription {
+    pub mode: String,
+    pub density: f32,
+    pub albedo: [f32; 3],
+    pub anisotropy: f32,
+    pub height: f32,
+    pub falloff: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextureAnimationDescription {
+    ///`Frame` or `Tick`
+    pub time: String,
+    ///Frame index or game ticks, depending on `time`
+    pub value: f32,
+    pub interpolate: bool,
+}
+
+///The on-disk, human editable description of a scene. The octree itself is not stored, only the
+///world it was loaded from. Settings added since version 1 are optional, files that leave them out
+///keep the defaults of the scene they are applied to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub version: u32,
+    pub world: Option<WorldReference>,
+    pub camera: CameraDescription,
+    pub sun: SunDescription,
+    pub sun_sampling_strategy: String,
+    pub emitters_enabled: bool,
+    pub emitter_intensity: f32,
+    pub emitter_sampling_strategy: String,
+    pub mis_heuristic: Option<String>,
+    pub max_depth: Option<u32>,
+    pub russian_roulette_depth: Option<u32>,
+    pub sky: Option<SkyDescription>,
+    pub fog: Option<FogDescription>,
+    pub texture_animation: Option<TextureAnimationDescription>,
+}
+
+impl From<&Camera> for CameraDescription {
+    fn from(camera: &Camera) -> Self {
+        let (yaw, pitch) = camera.yaw_pitch();
+        Self {
+            eye: camera.eye.to_array(),
+            yaw: yaw.to_degrees(),
+            pitch: pitch.to_degrees(),
+            fov: camera.fov.to_degrees(),
+            aperture: camera.aperture,
+            focal_distance: camera.focal_distance,
+        }
+    }
+}
+
+impl From<&CameraDescription> for Camera {
+    fn from(description: &CameraDescription) -> Self {
+        let mut camera = Camera::from_yaw_pitch(
+            Vec3A::from_array(description.eye),
+            description.yaw.to_radians(),
+            description.pitch.to_radians(),
+            description.fov.to_radians(),
+        );
+        camera.aperture = description.aperture;
+        camera.focal_distance = description.focal_distance;
+        camera
+    }
+}
+
+impl From<&Sun> for SunDescription {
+    fn from(sun: &Sun) -> Self {
+        Self {
+            azimuth: sun.azimuth.to_degrees(),
+            altitude: sun.altitude.to_degrees(),
+            luminosity: sun.luminosity,
+            importance_sample_chance: sun.importance_sample_chance,
+            importance_sample_radius: sun.importance_sample_radius,
+        }
+    }
+}
+
+impl From<&SunDescription> for Sun {
+    fn from(description: &SunDescription) -> Self {
+        let mut sun = Sun::from_angles(
+            description.azimuth.to_radians(),
+            description.altitude.to_radians(),
+        );
+        sun.luminosity = description.luminosity.max(Sun::MIN_LUMINOSITY);
+        sun.luminosity_pdf = 1.0 / sun.luminosity;
+        sun.importance_sample_chance = description.importance_sample_chance.clamp(
+            Sun::MIN_IMPORTANCE_SAMPLE_CHANCE,
+            Sun::MAX_IMPORTANCE_SAMPLE_CHANCE,
+        );
+        sun.importance_sample_radius = description.importance_sample_radius.clamp(
+            Sun::MIN_IMPORTANCE_SAMPLE_RADIUS,
+            Sun::MAX_IMPORTANCE_SAMPLE_RADIUS,
+        );
+        sun
+    }
+}
+
+impl From<&Sky> for SkyDescription {
+    fn from(sky: &Sky) -> Self {
+        Self {
+            model: sky.model.to_str().to_string(),
+            turbidity: sky.turbidity,
+            ground_albedo: sky.ground_albedo,
+            environment: sky
+                .environment
+                .as_ref()
+                .map(|environment| environment.path.clone()),
+            environment_rotation: Some(sky.environment_rotation.to_degrees()),
+            environment_intensity: Some(sky.environment_intensity),
+        }
+    }
+}
+
+impl TryFrom<&SkyDescription> for Sky {
+    type Error = anyhow::Error;
+
+    fn try_from(description: &SkyDescription) -> anyhow::Result<Self> {
+        Ok(Self {
+            model: SkyModel::from_name(&description.model)
+                .with_context(|| format!("unknown sky model {}", description.model))?,
+            turbidity: description
+                .turbidity
+                .clamp(Sky::MIN_TURBIDITY, Sky::MAX_TURBIDITY),
+            ground_albedo: description.ground_albedo.clamp(0.0, 1.0),
+            environment: description
+                .environment
+                .as_ref()
+                .map(|path| EnvironmentMap::load(Path::new(path)).map(Arc::new))
+                .transpose()?,
+            environment_rotation: description.environment_rotation.unwrap_or(0.0).to_radians(),
+            environment_intensity: description
+                .environment_intensity
+                .unwrap_or(1.0)
+                .clamp(0.0, Sky::MAX_ENVIRONMENT_INTENSITY),
+        })
+    }
+}
+
+impl From<&Fog> for FogDescription {
+    fn from(fog: &Fog) -> Self {
+        Self {
+            mode: fog.mode.to_str().to_string(),
+            density: fog.density,
+            albedo: fog.albedo.to_array(),
+            anisotropy: fog.anisotropy,
+            height: fog.height,
+            falloff: fog.falloff,
+        }
+    }
+}
+
+impl TryFrom<&FogDescription> for Fog {
+    type Error = anyhow::Error;
+
+    fn try_from(description: &FogDescription) -> anyhow::Result<Self> {
+        Ok(Self {
+            mode: FogMode::from_name(&description.mode)
+                .with_context(|| format!("unknown fog mode {}", description.mode))?,
+            density: description.density.clamp(0.0, Fog::MAX_DENSITY),
+            albedo: Vec3A::from_array(description.albedo).clamp(Vec3A::ZERO, Vec3A::ONE),
+            anisotropy: description
+                .anisotropy
+                .clamp(-Fog::MAX_ANISOTROPY, Fog::MAX_ANISOTROPY),
+            height: description.height,
+            falloff: description
+                .falloff
+                .clamp(Fog::MIN_FALLOFF, Fog::MAX_FALLOFF),
+        })
+    }
+}
+
+impl From<&TextureAnimation> for TextureAnimationDescription {
+    fn from(texture_animation: &TextureAnimation) -> Self {
+        Self {
+            time: texture_animation.time.to_str().to_string(),
+            value: texture_animation.time.value(),
+            interpolate: texture_animation.interpolate,
+        }
+    }
+}
+
+impl TryFrom<&TextureAnimationDescription> for TextureAnimation {
+    type Error = anyhow::Error;
+
+    fn try_from(description: &TextureAnimationDescription) -> anyhow::Result<Self> {
+        Ok(Self {
+            time: AnimationTime::from_name(&description.time, description.value)
+                .with_context(|| format!("unknown animation time {}", description.time))?,
+            interpolate: description.interpolate,
+        })
+    }
+}
+
+impl SceneFile {
+    pub const VERSION: u32 = 1;
+
+    pub fn from_scene(scene: &Scene, camera: &Camera) -> Self {
+        Self {
+            version: Self::VERSION,
+            world: scene.world.clone(),
+            camera: camera.into(),
+            sun: (&scene.sun).into(),
+            sun_sampling_strategy: scene.sun_sampling_strategy.get_name().to_string(),
+            emitters_enabled: scene.emitters_enabled,
+            emitter_intensity: scene.emmitter_intensity,
+            emitter_sampling_strategy: scene.emitter_sampling_strategy.get_name().to_string(),
+            mis_heuristic: Some(scene.mis_heuristic.to_str().to_string()),
+            max_depth: Some(scene.max_depth),
+            russian_roulette_depth: Some(scene.russian_roulette_depth),
+            sky: Some((&scene.sky).into()),
+            fog: Some((&scene.fog).into()),
+            texture_animation: Some((&scene.texture_animation).into()),
+        }
+    }
+
+    pub fn camera(&self) -> Camera {
+        (&self.camera).into()
+    }
+
+    ///Copies the lighting and sampling settings onto `scene`, leaving its octree untouched
+    pub fn apply_to(&self, scene: &mut Scene) -> anyhow::Result<()> {
+        scene.sun_sampling_strategy = SunSamplingStrategy::from_name(&self.sun_sampling_strategy)
+            .with_context(|| {
+            format!(
+                "unknown sun sampling strategy {}",
+                self.sun_sampling_strategy
+            )
+        })?;
+        scene.emitter_sampling_strategy = EmitterSamplingStrategy::from_name(
+            &self.emitter_sampling_strategy,
+        )
+        .with_context(|| {
+            format!(
+                "unknown emitter sampling strategy {}",
+                self.emitter_sampling_strategy
+            )
+        })?;
+        if let Some(mis_heuristic) = &self.mis_heuristic {
+            scene.mis_heuristic = MisHeuristic::from_name(mis_heuristic)
+                .with_context(|| format!("unknown mis heuristic {mis_heuristic}"))?;
+        }
+        if let Some(max_depth) = self.max_depth {
+            scene.max_depth = max_depth.clamp(1, Scene::MAX_MAX_DEPTH);
+        }
+        if let Some(russian_roulette_depth) = self.russian_roulette_depth {
+            scene.russian_roulette_depth = russian_roulette_depth;
+        }
+        if let Some(sky) = &self.sky {
+            scene.set_sky(sky.try_into()?);
+        }
+        if let Some(fog) = &self.fog {
+            scene.fog = fog.try_into()?;
+        }
+        if let Some(texture_animation) = &self.texture_animation {
+            scene.set_texture_animation(texture_animation.try_into()?);
+        }
+        scene.set_sun((&self.sun).into());
+        scene.emitters_enabled = self.emitters_enabled;
+        scene.emmitter_intensity = self.emitter_intensity;
+        if self.world.is_some() {
+            scene.world = self.world.clone();
+        }
+        Ok(())
+    }
+
+    pub fn parse(string: &str) -> anyhow::Result<Self> {
+        let scene_file: SceneFile = serde_json::from_str(string)?;
+        if scene_file.version > Self::VERSION {
+            bail!(
+                "scene file version {} is newer than the supported version {}",
+                scene_file.version,
+                Self::VERSION
+            );
+        }
+        Ok(scene_file)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let string = fs::read_to_string(path)
+            .with_context(|| format!("failed to read scene file {}", path.display()))?;
+        Self::parse(&string).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, self.to_json()?)
+            .with_context(|| format!("failed to write scene file {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_file() -> SceneFile {
+        SceneFile {
+            version: SceneFile::VERSION,
+            world: Some(WorldReference {
+                path: "./assets/worlds/test_world".to_string(),
+                origin: [10, 64, -20],
+                depth: 8,
+            }),
+            camera: (&Camera::from_yaw_pitch(Vec3A::new(1.0, 70.0, 3.0), 0.5, -0.25, 1.2)).into(),
+            sun: (&Sun::default()).into(),
+            sun_sampling_strategy: SunSamplingStrategy::FAST.get_name().to_string(),
+            emitters_enabled: true,
+            emitter_intensity: 7.0,
+            emitter_sampling_strategy: EmitterSamplingStrategy::ONE_BLOCK.get_name().to_string(),
+            mis_heuristic: Some(MisHeuristic::Balance.to_str().to_string()),
+            max_depth: Some(16),
+            russian_roulette_depth: Some(4),
+            sky: Some(SkyDescription {
+                model: SkyModel::Preetham.to_str().to_string(),
+                turbidity: 3.0,
+                ground_albedo: 0.2,
+                environment: None,
+                environment_rotation: Some(90.0),
+                environment_intensity: Some(2.0),
+            }),
+            fog: Some(FogDescription {
+                mode: FogMode::Layered.to_str().to_string(),
+                density: 0.05,
+                albedo: [0.8, 0.85, 0.9],
+                anisotropy: 0.3,
+                height: 62.0,
+                falloff: 4.0,
+            }),
+            texture_animation: Some(TextureAnimationDescription {
+                time: AnimationTime::Tick(0.0).to_str().to_string(),
+                value: 12.5,
+                interpolate: false,
+            }),
+        }
+    }
+
+    #[test]
+    pub fn scene_file_round_trip() {
+        let file = test_file();
+        let parsed = SceneFile::parse(&file.to_json().unwrap()).unwrap();
+        assert_eq!(file, parsed);
+    }
+
+    #[test]
+    pub fn camera_round_trip() {
+        let camera = Camera::from_yaw_pitch(Vec3A::new(1.0, 70.0, 3.0), 0.5, -0.25, 1.2);
+        let description = CameraDescription::from(&camera);
+        let restored = Camera::from(&description);
+        assert!((camera.direction - restored.direction).length() < 1e-5);
+        assert!((camera.up - restored.up).length() < 1e-5);
+        assert_eq!(camera.eye, restored.eye);
+    }
+
+    #[test]
+    pub fn mis_heuristic_is_optional() {
+        let mut json: serde_json::Value =
+            serde_json::from_str(&test_file().to_json().unwrap()).unwrap();
+        json.as_object_mut().unwrap().remove("mis_heuristic");
+        let parsed = SceneFile::parse(&json.to_string()).unwrap();
+        assert_eq!(parsed.mis_heuristic, None);
+    }
+
+    #[test]
+    pub fn dark_suns_keep_a_finite_pdf() {
+        let mut file = test_file();
+        file.sun.luminosity = 0.0;
+        let sun = Sun::from(&file.sun);
+        assert_eq!(sun.luminosity, Sun::MIN_LUMINOSITY);
+        assert!(sun.luminosity_pdf.is_finite());
+    }
+
+    #[test]
+    pub fn newer_versions_are_rejected() {
+        let mut file = test_file();
+        file.version = SceneFile::VERSION + 1;
+        assert!(SceneFile::parse(&file.to_json().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use glam::{Mat3A, Vec3A, Vec4};
+use rand::rngs::StdRng;
+
+use super::{Scene, Sun, environment_map::EnvironmentMap};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SkyModel {
+    ///A constant blue sky
+    #[default]
+    Flat,
+    ///Preetham, Shirley and Smits' analytic daylight model
+    Preetham,
+    ///An HDR image surrounding the scene
+    Environment,
+}
+
+impl SkyModel {
+    pub const MODELS: [SkyModel; 3] = [SkyModel::Flat, SkyModel::Preetham, SkyModel::Environment];
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            SkyModel::Flat => "Flat",
+            SkyModel::Preetham => "Preetham",
+            SkyModel::Environment => "Environment Map",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::MODELS
+            .into_iter()
+            .find(|model| model.to_str() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sky {
+    pub model: SkyModel,
+    ///Haziness of the atmosphere, 2 is a very clear sky and 10 a hazy one
+    pub turbidity: f32,
+    ///Fraction of the light reflected by the ground seen below the horizon
+    pub ground_albedo: f32,
+    pub environment: Option<Arc<EnvironmentMap>>,
+    ///Radians around the vertical axis
+    pub environment_rotation: f32,
+    pub environment_intensity: f32,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self {
+            model: SkyModel::default(),
+            turbidity: Sky::DEFAULT_TURBIDITY,
+            ground_albedo: Sky::DEFAULT_GROUND_ALBEDO,
+            environment: None,
+            environment_rotation: 0.0,
+            environment_intensity: 1.0,
+        }
+    }
+}
+
+impl Sky {
+    pub const DEFAULT_TURBIDITY: f32 = 2.5;
+    pub const MIN_TURBIDITY: f32 = 1.7;
+    pub const MAX_TURBIDITY: f32 = 10.0;
+    pub const DEFAULT_GROUND_ALBEDO: f32 = 0.3;
+    ///Converts the model's luminance in kcd/m² to the brightness range of the rest of the renderer
+    const PREETHAM_SCALE: f32 = 0.05;
+    pub const MAX_ENVIRONMENT_INTENSITY: f32 = 16.0;
+
+    ///Radiance arriving from `direction`, which has to be normalized. `preetham` has to be set up
+    ///for the current sun and the turbidity of this sky
+    pub(crate) fn color(&self, direction: Vec3A, preetham: &PreethamSky) -> Vec4 {
+        match self.model {
+            SkyModel::Flat => Scene::SKY_COLOR,
+            SkyModel::Preetham => {
+                //the ground reflects the sky above it
+                let (direction, reflectance) = if direction.y < 0.0 {
+                    (
+                        Vec3A::new(direction.x, -direction.y, direction.z),
+                        self.ground_albedo,
+                    )
+                } else {
+                    (direction, 1.0)
+                };
+                let rgb = preetham.radiance(direction) * reflectance;
+                Vec4::new(rgb.x, rgb.y, rgb.z, 1.0)
+            }
+            SkyModel::Environment => match &self.environment {
+                Some(environment) => {
+                    let rgb = environment.radiance(self.world_to_environment(direction))
+                        * self.environment_intensity;
+                    Vec4::new(rgb.x, rgb.y, rgb.z, 1.0)
+                }
+                None => Scene::SKY_COLOR,
+            },
+        }
+    }
+
+    ///The environment map, if it is the active sky and can be importance sampled
+    fn sampled_environment(&self) -> Option<&EnvironmentMap> {
+        match self.model {
+            SkyModel::Environment => self.environment.as_deref(),
+            _ => None,
+        }
+    }
+
+    ///Picks a direction towards a bright part of the environment map, returning it with its solid
+    ///angle pdf. `None` if the sky isn't an environment map
+    pub fn sample_environment(&self, rng: &mut StdRng) -> Option<(Vec3A, f32)> {
+        let (direction, pdf) = self.sampled_environment()?.sample(rng)?;
+        Some((self.environment_to_world(direction), pdf))
+    }
+
+    ///Pdf of [`Sky::sample_environment`] returning `direction`
+    pub fn environment_pdf(&self, direction: Vec3A) -> Option<f32> {
+        let environment = self.sampled_environment()?;
+        Some(environment.pdf(self.world_to_environment(direction)))
+    }
+
+    fn world_to_environment(&self, direction: Vec3A) -> Vec3A {
+        Mat3A::from_rotation_y(-self.environment_rotation) * direction
+    }
+
+    fn environment_to_world(&self, direction: Vec3A) -> Vec3A {
+        Mat3A::from_rotation_y(self.environment_rotation) * direction
+    }
+}
+
+///Perez distribution coefficients A to E
+struct Perez([f32; 5]);
+
+impl Perez {
+    #[inline]
+    fn evaluate(&self, cos_theta: f32, gamma: f32, cos_gamma: f32) -> f32 {
+        let [a, b, c, d, e] = self.0;
+        (1.0 + a * (b / cos_theta.max(0.01)).exp())
+            * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+}
+
+///The Preetham sky for one sun position and turbidity
+pub(crate) struct PreethamSky {
+    sun_direction: Vec3A,
+    ///Zenith luminance and chromaticity already divided by the Perez function at the zenith
+    zenith: [f32; 3],
+    perez: [Perez; 3],
+    ///Fades the sky out as the sun sets, the model is only defined for a sun above the horizon
+    brightness: f32,
+}
+
+impl PreethamSky {
+    pub(crate) fn new(turbidity: f32, sun: &Sun) -> Self {
+        let t = turbidity.clamp(Sky::MIN_TURBIDITY, Sky::MAX_TURBIDITY);
+        let altitude = sun.altitude.max(0.0);
+        let sun_direction = Vec3A::new(
+            sun.azimuth.cos() * altitude.cos(),
+            altitude.sin(),
+            sun.azimuth.sin() * altitude.cos(),
+        );
+        let theta_s = PI / 2.0 - altitude;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let thetas = Vec4::new(theta_s.powi(3), theta_s.powi(2), theta_s, 1.0);
+        let chromaticity = |t2: Vec4, t1: Vec4, t0: Vec4| {
+            t * t * t2.dot(thetas) + t * t1.dot(thetas) + t0.dot(thetas)
+        };
+        let zenith_x = chromaticity(
+            Vec4::new(0.00166, -0.00375, 0.00209, 0.0),
+            Vec4::new(-0.02903, 0.06377, -0.03202, 0.00394),
+            Vec4::new(0.11693, -0.21196, 0.06052, 0.25886),
+        );
+        let zenith_y = chromaticity(
+            Vec4::new(0.00275, -0.00610, 0.00317, 0.0),
+            Vec4::new(-0.04214, 0.08970, -0.04153, 0.00516),
+            Vec4::new(0.15346, -0.26756, 0.06670, 0.26688),
+        );
+
+        let perez = [
+            Perez([
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ]),
+            Perez([
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ]),
+            Perez([
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ]),
+        ];
+        let zenith = [zenith_luminance, zenith_x, zenith_y];
+        let cos_theta_s = theta_s.cos();
+        let zenith = std::array::from_fn(|index| {
+            zenith[index] / perez[index].evaluate(1.0, theta_s, cos_theta_s)
+        });
+
+        let brightness = ((sun.altitude + 0.1) / 0.1).clamp(0.0, 1.0);
+        Self {
+            sun_direction,
+            zenith,
+            perez,
+            brightness,
+        }
+    }
+
+    fn radiance(&self, direction: Vec3A) -> Vec3A {
+        let cos_theta = direction.y;
+        let cos_gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let [luminance, x, y] = std::array::from_fn(|index| {
+            self.zenith[index] * self.perez[index].evaluate(cos_theta, gamma, cos_gamma)
+        });
+        if y <= 0.0 {
+            return Vec3A::ZERO;
+        }
+
+        let luminance = luminance.max(0.0) * Sky::PREETHAM_SCALE * self.brightness;
+        let xyz = Vec3A::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        (XYZ_TO_LINEAR_SRGB * xyz).max(Vec3A::ZERO)
+    }
+}
+
+const XYZ_TO_LINEAR_SRGB: Mat3A = Mat3A::from_cols_array(&[
+    3.2406, -0.9689, 0.0557, //
+    -1.5372, 1.8758, -0.2040, //
+    -0.4986, 0.0415, 1.0570,
+]);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn preetham_sky_is_brightest_around_the_sun() {
+        let sky = Sky {
+            model: SkyModel::Preetham,
+            ..Default::default()
+        };
+        let sun = Sun::from_angles(0.0, 0.6);
+        let preetham = PreethamSky::new(sky.turbidity, &sun);
+        let towards_sun = Vec3A::new(0.6f32.cos(), 0.6f32.sin(), 0.0);
+        let away_from_sun = Vec3A::new(-0.6f32.cos(), 0.6f32.sin(), 0.0);
+
+        let near = sky.color(towards_sun, &preetham);
+        let far = sky.color(away_from_sun, &preetham);
+        assert!(near.is_finite() && far.is_finite());
+        assert!(far.x > 0.0 && far.y > 0.0 && far.z > 0.0);
+        assert!(near.y > far.y);
+
+        //below the horizon the sky is mirrored and darkened by the ground albedo
+        let ground = sky.color(-towards_sun, &preetham);
+        assert!(ground.y < far.y);
+    }
+}
@@ -0,0 +1,74 @@
+use std::f32::consts::PI;
+
+use glam::Vec3A;
+use rand::rngs::StdRng;
+
+use crate::random_float;
+
+///Light wandering further than this many scattering events into a block is considered absorbed
+pub const MAX_SUBSURFACE_STEPS: u32 = 64;
+
+///Scattering colour and mean free path of the blocks light can wander through, `None` for every
+///other block. The colour is the fraction of each channel surviving a single scattering event
+pub fn block_subsurface(block_name: &str) -> Option<(Vec3A, f32)> {
+    let name = block_name.strip_prefix("minecraft:").unwrap_or(block_name);
+    match name {
+        _ if name.ends_with("_leaves") => Some((Vec3A::new(0.93, 0.97, 0.85), 0.2)),
+        "vine" | "glow_lichen" | "lily_pad" => Some((Vec3A::new(0.93, 0.97, 0.85), 0.1)),
+        "slime_block" => Some((Vec3A::new(0.9, 0.98, 0.88), 0.35)),
+        "honey_block" => Some((Vec3A::new(0.98, 0.9, 0.6), 0.3)),
+        _ if name.ends_with("_mushroom_block") || name == "mushroom_stem" => {
+            Some((Vec3A::new(0.95, 0.9, 0.85), 0.15))
+        }
+        _ => None,
+    }
+}
+
+///Distance light travels inside a block before it scatters again
+pub fn sample_free_path(mean_free_path: f32, rng: &mut StdRng) -> f32 {
+    -(1.0 - random_float(rng)).ln() * mean_free_path
+}
+
+///Uniformly distributed direction, light scattering inside a block forgets where it came from
+pub fn isotropic_direction(rng: &mut StdRng) -> Vec3A {
+    let cos_theta = 1.0 - 2.0 * random_float(rng);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * random_float(rng);
+    Vec3A::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin())
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    pub fn translucent_blocks_scatter() {
+        assert!(block_subsurface("minecraft:oak_leaves").is_some());
+        assert!(block_subsurface("minecraft:slime_block").is_some());
+        assert!(block_subsurface("minecraft:honey_block").is_some());
+        assert!(block_subsurface("minecraft:stone").is_none());
+        assert!(block_subsurface("minecraft:water").is_none());
+    }
+
+    #[test]
+    pub fn random_walk_steps_are_unbiased() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let samples = 20000;
+
+        let mean_free_path = 0.2;
+        let mean = (0..samples)
+            .map(|_| sample_free_path(mean_free_path, &mut rng))
+            .sum::<f32>()
+            / samples as f32;
+        assert!((mean - mean_free_path).abs() < 0.01, "{mean}");
+
+        let mean_direction = (0..samples)
+            .map(|_| isotropic_direction(&mut rng))
+            .inspect(|direction| assert!((direction.length() - 1.0).abs() < 1e-4))
+            .sum::<Vec3A>()
+            / samples as f32;
+        assert!(mean_direction.length() < 0.03, "{mean_direction}");
+    }
+}
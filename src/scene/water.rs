@@ -0,0 +1,123 @@
+use glam::Vec3A;
+
+use crate::textures::material::{Material, MaterialFlags};
+
+pub const WATER_BLOCK: &str = "minecraft:water";
+
+///Height of the surface of a fluid block with the given `level` block state, as a fraction of a
+///block. Level 0 is a source block, 1 to 7 flow away from it and 8 and above are falling
+pub fn fluid_height(level: u8) -> f32 {
+    let amount = if level >= 8 { 8 } else { 8 - level };
+    amount as f32 / 9.0
+}
+
+///Water blocks and blocks that are waterlogged both fill their volume with water
+pub fn contains_water(material: &Material) -> bool {
+    material
+        .material_flags
+        .intersects(MaterialFlags::WATER | MaterialFlags::WATERLOGGED)
+}
+
+///How water surfaces and volumes are shaded. Distances are in blocks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Water {
+    ///Fraction of each channel absorbed per block travelled through water
+    pub absorption: Vec3A,
+    ///Steepness of the procedural waves, 0 leaves the surface flat
+    pub wave_strength: f32,
+    ///Length of the longest waves
+    pub wave_scale: f32,
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        Self {
+            absorption: Water::DEFAULT_ABSORPTION,
+            wave_strength: Water::DEFAULT_WAVE_STRENGTH,
+            wave_scale: Water::DEFAULT_WAVE_SCALE,
+        }
+    }
+}
+
+///Direction, relative frequency and relative amplitude of the sine waves summed into the surface
+const WAVES: [(f32, f32, f32, f32); 4] = [
+    (0.8, 0.6, 1.0, 1.0),
+    (-0.4, 0.9165, 1.7, 0.5),
+    (0.97, -0.243, 2.9, 0.25),
+    (-0.6, -0.8, 4.3, 0.125),
+];
+
+impl Water {
+    pub const INDEX_OF_REFRACTION: f32 = 1.333;
+    pub const SPECULAR: f32 = 0.12;
+    ///Red light is absorbed first, leaving deep water blue
+    pub const DEFAULT_ABSORPTION: Vec3A = Vec3A::new(0.45, 0.09, 0.05);
+    pub const DEFAULT_WAVE_STRENGTH: f32 = 0.15;
+    pub const DEFAULT_WAVE_SCALE: f32 = 6.0;
+
+    ///Fraction of the light that makes it through `distance` blocks of water
+    pub fn transmittance(&self, distance: f32) -> Vec3A {
+        (-self.absorption * distance.max(0.0)).exp()
+    }
+
+    ///Normal of the wave surface at `position`, pointing up
+    pub fn wave_normal(&self, position: Vec3A) -> Vec3A {
+        if self.wave_strength <= 0.0 {
+            return Vec3A::Y;
+        }
+        let (mut slope_x, mut slope_z) = (0.0, 0.0);
+        WAVES
+            .iter()
+            .for_each(|&(direction_x, direction_z, frequency, amplitude)| {
+                let frequency = frequency * std::f32::consts::TAU / self.wave_scale;
+                let phase = (direction_x * position.x + direction_z * position.z) * frequency;
+                //slopes rather than heights are summed, so the steepness doesn't depend on the scale
+                let slope = amplitude * phase.cos();
+                slope_x += slope * direction_x;
+                slope_z += slope * direction_z;
+            });
+        Vec3A::new(
+            -slope_x * self.wave_strength,
+            1.0,
+            -slope_z * self.wave_strength,
+        )
+        .normalize()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn fluid_levels_lower_the_surface() {
+        assert_eq!(fluid_height(0), 8.0 / 9.0);
+        assert_eq!(fluid_height(8), fluid_height(0));
+        assert!((1..8).all(|level| fluid_height(level) < fluid_height(level - 1)));
+        assert!(fluid_height(7) > 0.0);
+    }
+
+    #[test]
+    pub fn waves_tilt_the_normal_up() {
+        let water = Water::default();
+        (0..64).for_each(|index| {
+            let position = Vec3A::new(index as f32 * 0.37, 62.9, index as f32 * -1.3);
+            let normal = water.wave_normal(position);
+            assert!((normal.length() - 1.0).abs() < 1e-5);
+            assert!(normal.y > 0.8);
+        });
+
+        let flat = Water {
+            wave_strength: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(flat.wave_normal(Vec3A::new(3.0, 1.0, 2.0)), Vec3A::Y);
+    }
+
+    #[test]
+    pub fn deep_water_is_blue() {
+        let transmittance = Water::default().transmittance(10.0);
+        assert!(transmittance.z > transmittance.y && transmittance.y > transmittance.x);
+        assert_eq!(Water::default().transmittance(0.0), Vec3A::ONE);
+    }
+}
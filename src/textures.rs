@@ -1,3 +1,5 @@
+pub mod animation;
+pub mod lab_pbr;
 pub mod material;
 pub mod rtw_image;
 pub mod texture;
@@ -0,0 +1,360 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, bail};
+use serde::Deserialize;
+
+use super::rtw_image::RTWImage;
+
+///Which moment of their animation animated textures are rendered at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationTime {
+    ///The same entry of every animation's frame list, wrapping around for shorter animations
+    Frame(u32),
+    ///Game ticks since the animations started
+    Tick(f32),
+}
+
+impl AnimationTime {
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            AnimationTime::Frame(_) => "Frame",
+            AnimationTime::Tick(_) => "Tick",
+        }
+    }
+
+    ///Parses the name from [`AnimationTime::to_str`] together with its value
+    pub fn from_name(name: &str, value: f32) -> Option<Self> {
+        match name {
+            "Frame" => Some(AnimationTime::Frame(value.max(0.0) as u32)),
+            "Tick" => Some(AnimationTime::Tick(value.max(0.0))),
+            _ => None,
+        }
+    }
+
+    pub fn value(&self) -> f32 {
+        match *self {
+            AnimationTime::Frame(frame) => frame as f32,
+            AnimationTime::Tick(tick) => tick,
+        }
+    }
+}
+
+///How animated textures are frozen for a render
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureAnimation {
+    pub time: AnimationTime,
+    ///Blends between frames of textures whose `.mcmeta` asks for it, like Minecraft does
+    pub interpolate: bool,
+}
+
+impl Default for TextureAnimation {
+    fn default() -> Self {
+        Self {
+            time: AnimationTime::Frame(0),
+            interpolate: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TextureMetadata {
+    animation: Option<AnimationSection>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnimationSection {
+    #[serde(default)]
+    interpolate: bool,
+    width: Option<u32>,
+    height: Option<u32>,
+    frametime: Option<u32>,
+    frames: Option<Vec<FrameEntry>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FrameEntry {
+    Index(u32),
+    Timed { index: u32, time: Option<u32> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationFrame {
+    ///Position of the frame in the texture, counting left to right and then top to bottom
+    pub index: u32,
+    ///Ticks the frame stays up for
+    pub time: u32,
+}
+
+///The animation block of a texture's `.mcmeta` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub frames: Box<[AnimationFrame]>,
+    pub interpolate: bool,
+    pub frame_width: u32,
+    pub frame_height: u32,
+}
+
+impl Animation {
+    ///Parses the contents of a `.mcmeta` file belonging to an image of the given size. `None` if
+    ///the texture isn't animated
+    pub fn parse(json: &str, image_width: u32, image_height: u32) -> anyhow::Result<Option<Self>> {
+        let metadata: TextureMetadata = serde_json::from_str(json)?;
+        let Some(section) = metadata.animation else {
+            return Ok(None);
+        };
+
+        //without a size, frames are squares as wide as the narrower side of the image
+        let side = image_width.min(image_height);
+        let frame_width = section.width.unwrap_or(side);
+        let frame_height = section.height.unwrap_or(side);
+        if frame_width == 0 || frame_height == 0 {
+            bail!("frames of {image_width}x{image_height} texture have no area");
+        }
+        let columns = image_width / frame_width;
+        let frame_count = columns * (image_height / frame_height);
+        if frame_count == 0 {
+            bail!(
+                "{frame_width}x{frame_height} frames don't fit in a {image_width}x{image_height} texture"
+            );
+        }
+
+        let frametime = section.frametime.unwrap_or(1).max(1);
+        let frames = match section.frames {
+            Some(entries) => entries
+                .into_iter()
+                .map(|entry| {
+                    let (index, time) = match entry {
+                        FrameEntry::Index(index) => (index, None),
+                        FrameEntry::Timed { index, time } => (index, time),
+                    };
+                    if index >= frame_count {
+                        bail!(
+                            "frame {index} is outside of the {frame_count} frames of the texture"
+                        );
+                    }
+                    Ok(AnimationFrame {
+                        index,
+                        time: time.unwrap_or(frametime).max(1),
+                    })
+                })
+                .collect::<anyhow::Result<Box<[_]>>>()?,
+            None => (0..frame_count)
+                .map(|index| AnimationFrame {
+                    index,
+                    time: frametime,
+                })
+                .collect(),
+        };
+        if frames.is_empty() {
+            bail!("animation has no frames");
+        }
+
+        Ok(Some(Self {
+            frames,
+            interpolate: section.interpolate,
+            frame_width,
+            frame_height,
+        }))
+    }
+
+    ///Ticks until the animation starts over
+    pub fn duration(&self) -> u32 {
+        self.frames.iter().map(|frame| frame.time).sum()
+    }
+
+    ///Entries of the frame list showing at `time` and the one after them, together with how far
+    ///the first has progressed towards the second
+    pub fn frames_at(&self, time: AnimationTime) -> (AnimationFrame, AnimationFrame, f32) {
+        let len = self.frames.len();
+        let (entry, progress) = match time {
+            AnimationTime::Frame(frame) => (frame as usize % len, 0.0),
+            AnimationTime::Tick(tick) => {
+                let mut tick = tick.max(0.0) % self.duration() as f32;
+                let mut entry = 0;
+                while entry < len - 1 && tick >= self.frames[entry].time as f32 {
+                    tick -= self.frames[entry].time as f32;
+                    entry += 1;
+                }
+                (entry, tick / self.frames[entry].time as f32)
+            }
+        };
+        (
+            self.frames[entry],
+            self.frames[(entry + 1) % len],
+            progress.clamp(0.0, 1.0),
+        )
+    }
+
+    ///Top left pixel of a frame in the texture
+    fn frame_origin(&self, image_width: u32, index: u32) -> (u32, u32) {
+        let columns = (image_width / self.frame_width).max(1);
+        (
+            index % columns * self.frame_width,
+            index / columns * self.frame_height,
+        )
+    }
+}
+
+///A texture holding every frame of an animation, sampled through the single frame picked by a
+///[`TextureAnimation`]
+#[derive(Debug, Clone)]
+pub struct AnimatedTexture {
+    pub strip: Arc<RTWImage>,
+    pub animation: Arc<Animation>,
+    ///The frame being rendered, already blended with the next one when interpolating
+    pub frame: Arc<RTWImage>,
+}
+
+impl AnimatedTexture {
+    pub fn new(
+        strip: Arc<RTWImage>,
+        animation: Arc<Animation>,
+        settings: &TextureAnimation,
+    ) -> Self {
+        let (current, next, progress) = animation.frames_at(settings.time);
+        let blend = if settings.interpolate && animation.interpolate {
+            progress
+        } else {
+            0.0
+        };
+        let (current_x, current_y) = animation.frame_origin(strip.image_width, current.index);
+        let (next_x, next_y) = animation.frame_origin(strip.image_width, next.index);
+
+        let (width, height) = (animation.frame_width, animation.frame_height);
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let a = strip.pixel_data(current_x + x, current_y + y);
+                let b = strip.pixel_data(next_x + x, next_y + y);
+                (0..4).map(move |channel| {
+                    (a[channel] as f32 * (1.0 - blend) + b[channel] as f32 * blend).round() as u8
+                })
+            })
+            .collect();
+
+        Self {
+            frame: Arc::new(RTWImage::from_rgba(width, height, data)),
+            strip,
+            animation,
+        }
+    }
+
+    ///The same animation frozen at another moment
+    pub fn at(&self, settings: &TextureAnimation) -> Self {
+        Self::new(self.strip.clone(), self.animation.clone(), settings)
+    }
+}
+
+///Location of the `.mcmeta` file of a texture like `minecraft:block/water_still` in the resource
+///pack at `resource_pack_path`
+pub fn texture_metadata_path(resource_pack_path: &Path, texture_path: &str) -> PathBuf {
+    let (namespace, path) = texture_path
+        .split_once(':')
+        .unwrap_or(("minecraft", texture_path));
+    resource_pack_path
+        .join("assets")
+        .join(namespace)
+        .join("textures")
+        .join(format!("{path}.png.mcmeta"))
+}
+
+///Reads the animation of a texture, `None` if it has no `.mcmeta` file or isn't animated
+pub fn load_animation(
+    resource_pack_path: &Path,
+    texture_path: &str,
+    image: &RTWImage,
+) -> anyhow::Result<Option<Animation>> {
+    let metadata_path = texture_metadata_path(resource_pack_path, texture_path);
+    let Ok(json) = std::fs::read_to_string(&metadata_path) else {
+        return Ok(None);
+    };
+    Animation::parse(&json, image.image_width, image.image_height)
+        .with_context(|| format!("failed to parse {}", metadata_path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn frames_default_to_squares() {
+        let animation = Animation::parse(r#"{"animation": {"frametime": 2}}"#, 16, 64)
+            .unwrap()
+            .unwrap();
+        assert_eq!((animation.frame_width, animation.frame_height), (16, 16));
+        assert_eq!(animation.frames.len(), 4);
+        assert_eq!(animation.duration(), 8);
+        assert!(!animation.interpolate);
+
+        assert!(
+            Animation::parse(r#"{"villager": {"hat": "full"}}"#, 16, 16)
+                .unwrap()
+                .is_none()
+        );
+        assert!(Animation::parse(r#"{"animation": {"frames": [4]}}"#, 16, 64).is_err());
+    }
+
+    #[test]
+    pub fn frame_lists_are_followed() {
+        let json = r#"{"animation": {"interpolate": true, "frametime": 3,
+            "frames": [2, {"index": 0, "time": 1}, 1]}}"#;
+        let animation = Animation::parse(json, 16, 48).unwrap().unwrap();
+        assert_eq!(animation.duration(), 7);
+
+        let (current, next, progress) = animation.frames_at(AnimationTime::Tick(1.5));
+        assert_eq!((current.index, next.index, progress), (2, 0, 0.5));
+        let (current, next, _) = animation.frames_at(AnimationTime::Tick(3.0));
+        assert_eq!((current.index, next.index), (0, 1));
+        //the last frame leads back to the first
+        let (current, next, _) = animation.frames_at(AnimationTime::Tick(13.0));
+        assert_eq!((current.index, next.index), (1, 2));
+        assert_eq!(animation.frames_at(AnimationTime::Frame(4)).0.index, 0);
+    }
+
+    #[test]
+    pub fn interpolation_blends_frames() {
+        //two 1x1 frames stacked on top of each other, black and white
+        let strip = Arc::new(RTWImage::from_rgba(
+            1,
+            2,
+            Box::new([0, 0, 0, 255, 255, 255, 255, 255]),
+        ));
+        let json = r#"{"animation": {"interpolate": true, "frametime": 4}}"#;
+        let animation = Arc::new(Animation::parse(json, 1, 2).unwrap().unwrap());
+
+        let halfway = TextureAnimation {
+            time: AnimationTime::Tick(2.0),
+            interpolate: true,
+        };
+        let texture = AnimatedTexture::new(strip, animation, &halfway);
+        assert_eq!(texture.frame.pixel_data(0, 0), [128, 128, 128, 255]);
+
+        let stepped = texture.at(&TextureAnimation {
+            interpolate: false,
+            ..halfway
+        });
+        assert_eq!(stepped.frame.pixel_data(0, 0), [0, 0, 0, 255]);
+        let second = texture.at(&TextureAnimation {
+            time: AnimationTime::Frame(1),
+            interpolate: true,
+        });
+        assert_eq!(second.frame.pixel_data(0, 0), [255; 4]);
+    }
+
+    #[test]
+    pub fn metadata_sits_next_to_the_texture() {
+        let root = Path::new("pack");
+        assert_eq!(
+            texture_metadata_path(root, "minecraft:block/water_still"),
+            Path::new("pack/assets/minecraft/textures/block/water_still.png.mcmeta")
+        );
+        assert_eq!(
+            texture_metadata_path(root, "block/lava_flow"),
+            texture_metadata_path(root, "minecraft:block/lava_flow")
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use glam::Vec3A;
+
+use super::{animation::AnimatedTexture, texture::Texture};
+
+///Suffix of the texture holding the tangent space normal, ambient occlusion and height
+pub const NORMAL_SUFFIX: &str = "_n";
+///Suffix of the texture holding smoothness, reflectance, porosity and emission
+pub const SPECULAR_SUFFIX: &str = "_s";
+///Emittance given to materials whose specular map has emissive texels, the map scales it per texel
+pub const LAB_PBR_EMITTANCE: f32 = 1.0;
+///Porous blocks under water are this much darker at full porosity
+pub const WET_DARKENING: f32 = 0.5;
+
+///Reflectance values from here up stand for metals, which reflect with their albedo
+const METAL_REFLECTANCE: u8 = 230;
+///Porosity values above this one mark subsurface scattering instead
+const MAX_POROSITY: u8 = 64;
+
+///Material properties of a single texel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceProperties {
+    pub specular: f32,
+    pub roughness: f32,
+    pub metalness: f32,
+    pub emittance: f32,
+    ///How much water the surface soaks up, from 0 to 1
+    pub porosity: f32,
+}
+
+///Decodes a texel of a LabPBR specular map, its emittance is a fraction of the material's one
+pub fn decode_specular(texel: [u8; 4]) -> SurfaceProperties {
+    let [smoothness, reflectance, porosity, emission] = texel;
+    let (specular, metalness) = if reflectance >= METAL_REFLECTANCE {
+        (1.0, 1.0)
+    } else {
+        (reflectance as f32 / 255.0, 0.0)
+    };
+    SurfaceProperties {
+        specular,
+        roughness: (1.0 - smoothness as f32 / 255.0).powi(2),
+        metalness,
+        //255 is the default value of the alpha channel, so it means no emission at all
+        emittance: if emission == 255 {
+            0.0
+        } else {
+            emission as f32 / 254.0
+        },
+        porosity: if porosity <= MAX_POROSITY {
+            porosity as f32 / MAX_POROSITY as f32
+        } else {
+            0.0
+        },
+    }
+}
+
+///Decodes a texel of a LabPBR normal map into a tangent space normal, with +z pointing out of the
+///surface and +y towards the top of the texture
+pub fn decode_normal(texel: [u8; 4]) -> Vec3A {
+    let x = texel[0] as f32 / 255.0 * 2.0 - 1.0;
+    let y = texel[1] as f32 / 255.0 * 2.0 - 1.0;
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+    Vec3A::new(x, y, z).normalize()
+}
+
+///Directions of increasing u and of the top of the texture on a block face, following the uv
+///layout of unrotated faces in Minecraft
+pub fn tangent_frame(normal: Vec3A) -> (Vec3A, Vec3A) {
+    let up = if normal.y > 0.5 {
+        Vec3A::NEG_Z
+    } else if normal.y < -0.5 {
+        Vec3A::Z
+    } else {
+        Vec3A::Y
+    };
+    let tangent = up.cross(normal).normalize();
+    (tangent, normal.cross(tangent))
+}
+
+///Bends `normal` by a tangent space normal from [`decode_normal`]
+pub fn perturb_normal(normal: Vec3A, tangent_normal: Vec3A) -> Vec3A {
+    let (tangent, bitangent) = tangent_frame(normal);
+    (tangent * tangent_normal.x + bitangent * tangent_normal.y + normal * tangent_normal.z)
+        .normalize()
+}
+
+///Whether any texel of a specular map emits light
+pub fn has_emission(specular_map: &Texture) -> bool {
+    match specular_map {
+        Texture::Color(color) => decode_specular(color.clone().into()).emittance > 0.0,
+        Texture::Image(image) | Texture::Animated(AnimatedTexture { strip: image, .. }) => {
+            (0..image.image_height).any(|y| {
+                (0..image.image_width)
+                    .any(|x| decode_specular(image.pixel_data(x, y)).emittance > 0.0)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn specular_channels_are_decoded() {
+        let dielectric = decode_specular([255, 10, 32, 255]);
+        assert_eq!(dielectric.roughness, 0.0);
+        assert_eq!(dielectric.metalness, 0.0);
+        assert!((dielectric.specular - 10.0 / 255.0).abs() < 1e-6);
+        assert_eq!(dielectric.porosity, 0.5);
+        assert_eq!(dielectric.emittance, 0.0);
+
+        let metal = decode_specular([0, 230, 200, 127]);
+        assert_eq!(metal.roughness, 1.0);
+        assert_eq!(metal.metalness, 1.0);
+        //subsurface scattering values aren't porosity
+        assert_eq!(metal.porosity, 0.0);
+        assert!((metal.emittance - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    pub fn flat_normal_maps_keep_the_normal() {
+        let flat = decode_normal([128, 128, 255, 255]);
+        assert!((flat - Vec3A::Z).length() < 0.01);
+
+        [
+            Vec3A::X,
+            Vec3A::NEG_X,
+            Vec3A::Y,
+            Vec3A::NEG_Y,
+            Vec3A::Z,
+            Vec3A::NEG_Z,
+        ]
+        .into_iter()
+        .for_each(|normal| {
+            let (tangent, bitangent) = tangent_frame(normal);
+            assert!((tangent.cross(bitangent) - normal).length() < 1e-5);
+            assert!((perturb_normal(normal, Vec3A::Z) - normal).length() < 1e-5);
+        });
+
+        //the top of the texture on the top face points north
+        let bent = perturb_normal(Vec3A::Y, decode_normal([128, 255, 128, 255]));
+        assert!(bent.z < -0.5);
+    }
+}
@@ -1,15 +1,22 @@
 use bitflags::bitflags;
+use glam::Vec3A;
 
-use super::texture::Texture;
+use super::{
+    lab_pbr::{SurfaceProperties, decode_normal, decode_specular, perturb_normal},
+    texture::Texture,
+};
 
 bitflags! {
-    #[derive(Clone, Copy,Debug)]
+    #[derive(Clone, Copy,Debug, PartialEq)]
     pub struct MaterialFlags: u32 {
         const OPAQUE = 0b00000001;
         const SUBSURFACE_SCATTER = 0b00000010;
         const REFRACTIVE = 0b00000100;
         const WATERLOGGED = 0b00001000;
         const SOLID = 0b00010000;
+        const WATER = 0b00100000;
+        ///The face has a `tintindex` in its model, see `Material::tint_index`
+        const TINTED = 0b01000000;
     }
 }
 
@@ -26,7 +33,12 @@ pub struct MaterialBuilder {
     emittance: Option<f32>,
     roughness: Option<f32>,
     metalness: Option<f32>,
+    subsurface_color: Option<Vec3A>,
+    mean_free_path: Option<f32>,
     texture: Option<Texture>,
+    normal_map: Option<Texture>,
+    specular_map: Option<Texture>,
+    tint_index: Option<u32>,
 }
 
 impl MaterialBuilder {
@@ -40,8 +52,14 @@ impl MaterialBuilder {
             emittance: self.emittance.unwrap_or(0.0),
             roughness: self.roughness.unwrap_or(0.0),
             metalness: self.metalness.unwrap_or(0.0),
+            subsurface_color: self.subsurface_color.unwrap_or(Vec3A::ONE),
+            mean_free_path: self
+                .mean_free_path
+                .unwrap_or(Material::DEFAULT_MEAN_FREE_PATH),
             texture: self.texture.unwrap_or(Texture::DEFAULT_TEXTURE),
-            tint_index: 0,
+            normal_map: self.normal_map,
+            specular_map: self.specular_map,
+            tint_index: self.tint_index.unwrap_or(0),
         }
     }
     pub fn index_of_refraction(self, ior: f32) -> Self {
@@ -74,21 +92,51 @@ impl MaterialBuilder {
             ..self
         }
     }
+    pub fn subsurface_color(self, subsurface_color: Vec3A) -> Self {
+        Self {
+            subsurface_color: Some(subsurface_color),
+            ..self
+        }
+    }
+    pub fn mean_free_path(self, mean_free_path: f32) -> Self {
+        Self {
+            mean_free_path: Some(mean_free_path),
+            ..self
+        }
+    }
     pub fn albedo(self, texture: Texture) -> Self {
         Self {
             texture: Some(texture),
             ..self
         }
     }
+    pub fn normal_map(self, normal_map: Texture) -> Self {
+        Self {
+            normal_map: Some(normal_map),
+            ..self
+        }
+    }
+    pub fn specular_map(self, specular_map: Texture) -> Self {
+        Self {
+            specular_map: Some(specular_map),
+            ..self
+        }
+    }
     pub fn material_flags(self, material_flags: MaterialFlags) -> Self {
         Self {
             material_flags: Some(material_flags),
             ..self
         }
     }
+    pub fn tint_index(self, tint_index: u32) -> Self {
+        Self {
+            tint_index: Some(tint_index),
+            ..self
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Material {
     pub index_of_refraction: f32,
     pub material_flags: MaterialFlags,
@@ -96,11 +144,21 @@ pub struct Material {
     pub emittance: f32,
     pub roughness: f32,
     pub metalness: f32,
+    ///Fraction of each channel surviving a scattering event inside a subsurface scattering block
+    pub subsurface_color: Vec3A,
+    ///Average distance in blocks light travels between scattering events inside the block
+    pub mean_free_path: f32,
     pub texture: Texture,
+    ///LabPBR normal map, overrides the geometric normal
+    pub normal_map: Option<Texture>,
+    ///LabPBR specular map, overrides the scalar properties above per texel
+    pub specular_map: Option<Texture>,
+    ///A `TintType`, picking the biome colour the albedo is multiplied with
     pub tint_index: u32,
 }
 
 impl Material {
+    pub const DEFAULT_MEAN_FREE_PATH: f32 = 0.2;
     pub const AIR: Material = Material {
         index_of_refraction: 1.000293,
         material_flags: MaterialFlags::empty(),
@@ -108,7 +166,11 @@ impl Material {
         emittance: 0.0,
         roughness: 0.0,
         metalness: 0.0,
+        subsurface_color: Vec3A::ONE,
+        mean_free_path: Material::DEFAULT_MEAN_FREE_PATH,
         texture: Texture::DEFAULT_TEXTURE,
+        normal_map: None,
+        specular_map: None,
         tint_index: 0,
     };
     pub fn builder() -> MaterialBuilder {
@@ -119,7 +181,38 @@ impl Material {
             emittance: None,
             roughness: None,
             metalness: None,
+            subsurface_color: None,
+            mean_free_path: None,
             texture: None,
+            normal_map: None,
+            specular_map: None,
+            tint_index: None,
+        }
+    }
+
+    ///Properties of the texel at `u`, `v`, from the specular map if there is one
+    pub fn surface_at(&self, u: f32, v: f32) -> SurfaceProperties {
+        let Some(specular_map) = &self.specular_map else {
+            return SurfaceProperties {
+                specular: self.specular,
+                roughness: self.roughness,
+                metalness: self.metalness,
+                emittance: self.emittance,
+                porosity: 0.0,
+            };
+        };
+        let surface = decode_specular(specular_map.texel(u, v));
+        SurfaceProperties {
+            emittance: surface.emittance * self.emittance,
+            ..surface
+        }
+    }
+
+    ///`normal` bent by the normal map at `u`, `v`
+    pub fn normal_at(&self, u: f32, v: f32, normal: Vec3A) -> Vec3A {
+        match &self.normal_map {
+            Some(normal_map) => perturb_normal(normal, decode_normal(normal_map.texel(u, v))),
+            None => normal,
         }
     }
 }
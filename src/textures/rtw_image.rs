@@ -199,6 +199,18 @@ impl RTWImage {
         }
     }
 
+    ///Wraps tightly packed rgba bytes, rows from top to bottom
+    pub fn from_rgba(image_width: u32, image_height: u32, raw_data: Box<[u8]>) -> Self {
+        assert_eq!(raw_data.len(), (image_width * image_height * 4) as usize);
+        RTWImage {
+            bytes_per_pixel: BytesPerPixel::Four,
+            raw_data,
+            image_width,
+            image_height,
+            bytes_per_scanline: image_width * 4,
+        }
+    }
+
     pub fn float_to_byte(value: f32) -> u8 {
         if value <= 0.0 {
             return 0;
@@ -9,12 +9,14 @@ use lazy_static::lazy_static;
 
 use crate::colors::{F32Color, U8Color};
 
-use super::rtw_image::RTWImage;
+use super::{animation::AnimatedTexture, rtw_image::RTWImage};
 
 #[derive(Debug, Clone)]
 pub enum Texture {
     Color(U8Color),
     Image(Arc<RTWImage>),
+    ///A texture with a `.mcmeta` animation, showing one of its frames
+    Animated(AnimatedTexture),
 }
 
 impl std::hash::Hash for Texture {
@@ -24,6 +26,7 @@ impl std::hash::Hash for Texture {
                 u8_color.hash(state);
             }
             Texture::Image(rtwimage) => Arc::as_ptr(rtwimage).hash(state),
+            Texture::Animated(animated) => Arc::as_ptr(&animated.frame).hash(state),
         }
     }
 }
@@ -35,6 +38,7 @@ impl PartialEq for Texture {
         match (self, other) {
             (Self::Color(l0), Self::Color(r0)) => l0 == r0,
             (Self::Image(l0), Self::Image(r0)) => Arc::ptr_eq(l0, r0),
+            (Self::Animated(l0), Self::Animated(r0)) => Arc::ptr_eq(&l0.frame, &r0.frame),
             _ => false,
         }
     }
@@ -67,18 +71,8 @@ impl Texture {
                 let color = F32Color::from(color);
                 Vec4::from_array(color.into_array())
             }
-            Texture::Image(image) => {
-                if image.image_height == 0 {
-                    return Vec4::new(1.0, 1.0, 1.0, 1.0);
-                }
-
-                let u = u.clamp(0.0, 1.0);
-                let v = 1.0 - v.clamp(0.0, 1.0);
-
-                let i = (u * image.image_width as f32) as u32;
-                let j = (v * image.image_height as f32) as u32;
-
-                let color = image.pixel_data(i, j);
+            Texture::Image(_) | Texture::Animated(_) => {
+                let color = self.texel(u, v);
 
                 let mut val = Vec4::splat(0.0);
 
@@ -91,4 +85,24 @@ impl Texture {
             }
         }
     }
+
+    ///Raw bytes of the texel at `u`, `v`, for textures holding data rather than colours
+    pub fn texel(&self, u: f32, v: f32) -> [u8; 4] {
+        match self {
+            Texture::Color(color) => color.clone().into(),
+            Texture::Image(image) | Texture::Animated(AnimatedTexture { frame: image, .. }) => {
+                if image.image_height == 0 {
+                    return [255; 4];
+                }
+
+                let u = u.clamp(0.0, 1.0);
+                let v = 1.0 - v.clamp(0.0, 1.0);
+
+                let i = (u * image.image_width as f32) as u32;
+                let j = (v * image.image_height as f32) as u32;
+
+                image.pixel_data(i, j)
+            }
+        }
+    }
 }
@@ -0,0 +1,104 @@
+use std::{
+    sync::Arc,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use glam::{UVec3, Vec3A};
+use octree_pathtracing::{
+    colors::{PixelColor, U8Color},
+    octree::new_octree::Octree,
+    renderer::{
+        camera::Camera,
+        render_dump::{RenderDump, scene_hash},
+        tile_renderer::{RendererMode, TileRenderer},
+    },
+    scene::{Scene, resource_manager::BuiltModels},
+    textures::{material::Material, texture::Texture},
+};
+
+const RESOLUTION: (usize, usize) = (24, 16);
+const SPP: u32 = 4;
+
+///A green floor of 8x8 blocks and a camera looking down at it, with the horizon cutting through
+///the top of the image
+fn fixture() -> (Scene, Camera) {
+    let mut octree = Octree::default();
+    octree.fill_box(UVec3::ZERO, UVec3::new(7, 0, 7), 1);
+    let green = Material::builder()
+        .albedo(Texture::Color(U8Color::new(0, 255, 0, 255)))
+        .build();
+    let scene = Scene::new(
+        octree,
+        Box::new([Material::AIR, green]),
+        BuiltModels::default(),
+    );
+    let camera = Camera::look_at(
+        Vec3A::new(1.0, 3.0, 4.0),
+        Vec3A::new(9.0, -1.0, 4.0),
+        Vec3A::Y,
+        90f32.to_radians(),
+    );
+    (scene, camera)
+}
+
+///Path traces the fixture until it reaches `SPP`, returning the renderer with the render still
+///running and the hash of the scene
+fn render_fixture() -> (TileRenderer, u64) {
+    let (scene, camera) = fixture();
+    let hash = scene_hash(&scene, &camera);
+    let mut renderer = TileRenderer::new(RESOLUTION, SPP, 2, 2);
+    renderer.set_mode(RendererMode::PathTraced);
+    renderer.edit_camera(|renderer_camera| *renderer_camera = camera.clone());
+    renderer.render_scene(Arc::new(parking_lot::RwLock::new(scene)));
+
+    let start = Instant::now();
+    while renderer.get_current_spp() < SPP {
+        assert!(
+            start.elapsed() < Duration::from_secs(120),
+            "the render didn't finish"
+        );
+        sleep(Duration::from_millis(10));
+    }
+    (renderer, hash)
+}
+
+#[test]
+fn headless_renders_show_geometry() {
+    let (mut renderer, hash) = render_fixture();
+    let dump = renderer.dump(hash);
+    renderer.stop();
+    assert_eq!(dump.spp, SPP);
+
+    let pixel = |x: usize, y: usize| &dump.samples[y * RESOLUTION.0 + x];
+    //only green light leaves the floor, the sky behind it is blue
+    let floor = pixel(RESOLUTION.0 / 2, RESOLUTION.1 / 2);
+    assert!(floor.g() > 0.0 && floor.r() == 0.0 && floor.b() == 0.0);
+    let sky = pixel(RESOLUTION.0 / 2, 0);
+    assert!(sky.b() > 0.0);
+}
+
+#[test]
+fn saved_dumps_keep_their_sample_count() {
+    let (mut renderer, hash) = render_fixture();
+    let dump = renderer.dump(hash);
+    renderer.stop();
+
+    let path = std::env::temp_dir().join("octree_pathtracing_headless_test.dump");
+    dump.save(&path).unwrap();
+    let loaded = RenderDump::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.spp, SPP);
+    assert_eq!(loaded.scene_hash, hash);
+    loaded
+        .samples
+        .iter()
+        .zip(&dump.samples)
+        .for_each(|(a, b)| assert_eq!(a.clone().into_array(), b.clone().into_array()));
+
+    //a resumed render picks up where the dump left off instead of rendering it again
+    let (scene, _) = fixture();
+    renderer.resume_from(Arc::new(parking_lot::RwLock::new(scene)), loaded);
+    assert_eq!(renderer.get_current_spp(), SPP);
+    renderer.stop();
+}